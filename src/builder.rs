@@ -0,0 +1,184 @@
+//! Resolving versions of services defined in code rather than in config files.
+//!
+//! ```no_run
+//! use version_updater::{GitConfig, ImageConfig, ServiceConfig, VersionUpdater};
+//! use version_updater::git::Provider;
+//!
+//! # async fn run() -> Result<(), version_updater::AppError> {
+//! let updater = VersionUpdater::builder()
+//!     .service(
+//!         "traefik",
+//!         ServiceConfig::new(
+//!             GitConfig::new(Provider::Github, "traefik/traefik").with_filter("v(.*)"),
+//!             ImageConfig::new("traefik", "v${RELEASE_VERSION}"),
+//!         ),
+//!     )
+//!     .build()?;
+//! for (name, version) in updater.run().await {
+//!     println!("{}: {:?}", name, version.tag());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::cache::{Cache, CacheConfig};
+use super::config::{OutputData, ServiceConfig, ServiceVersion};
+use super::error::AppError;
+use super::http::{HttpClient, HttpConfig};
+use super::login;
+use super::registry::{self, RegistryCredentials};
+use super::service::{self, RunOptions, ServiceProcessor};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use tracing::error;
+
+/// A set of services whose versions are resolved together, sharing one HTTP
+/// client and its rate limits.
+pub struct VersionUpdater {
+    services: BTreeMap<String, ServiceConfig>,
+    http: HttpClient,
+    timings: bool,
+    deep_verify: bool,
+}
+
+impl VersionUpdater {
+    pub fn builder() -> VersionUpdaterBuilder {
+        VersionUpdaterBuilder::default()
+    }
+
+    pub fn services(&self) -> &BTreeMap<String, ServiceConfig> {
+        &self.services
+    }
+
+    /// Resolves all services concurrently. Failures, including fatal ones, are
+    /// reported in the `error` of the service.
+    pub async fn run(&self) -> OutputData {
+        let options = RunOptions {
+            timing: self.timings,
+            deep_verify: self.deep_verify,
+            ..RunOptions::default()
+        };
+        let run = service::run_services(
+            &self.services,
+            &self.http.new_run(),
+            options,
+            std::future::pending(),
+        )
+        .await;
+        run.expect("failures are reported in the output without fail_fast")
+            .output
+    }
+
+    /// Resolves a single service. Transient failures are reported in the
+    /// returned `ServiceVersion`, fatal ones and unknown services as error.
+    pub async fn resolve(&self, name: &str) -> Result<ServiceVersion> {
+        self.resolve_with(name, self.http.new_run()).await
+    }
+
+    async fn resolve_with(&self, name: &str, http: HttpClient) -> Result<ServiceVersion> {
+        let service = self
+            .services
+            .get(name)
+            .ok_or_else(|| AppError::NotFound(format!("Service '{}'", name)))?;
+        ServiceProcessor::new(service.clone(), http)
+            .with_name(name)
+            .with_timing(self.timings)
+            .with_deep_verify(self.deep_verify)
+            .process()
+            .await
+    }
+}
+
+/// Collects services and settings, the equivalent of the `global` and
+/// `services` sections of a config file.
+#[derive(Default)]
+pub struct VersionUpdaterBuilder {
+    services: BTreeMap<String, ServiceConfig>,
+    http: HttpConfig,
+    cache: Option<CacheConfig>,
+    github_auth: bool,
+    gh_cli: bool,
+    timings: bool,
+    deep_verify: bool,
+    registries: BTreeMap<String, RegistryCredentials>,
+}
+
+impl VersionUpdaterBuilder {
+    /// Adds a service, replacing one with the same name.
+    pub fn service(mut self, name: &str, service: ServiceConfig) -> Self {
+        self.services.insert(name.to_string(), service);
+        self
+    }
+
+    /// Timeouts, retries, proxy and rate limits of requests.
+    pub fn http(mut self, http: HttpConfig) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Caches responses on disk. Nothing is cached by default.
+    pub fn cache(mut self, cache: CacheConfig) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Authenticates all GitHub requests, like `global.git.github.authenticate`.
+    pub fn github_auth(mut self, authenticate: bool) -> Self {
+        self.github_auth = authenticate;
+        self
+    }
+
+    /// Falls back to the token of the gh CLI, like `global.git.github.gh_cli`.
+    pub fn gh_cli(mut self, gh_cli: bool) -> Self {
+        self.gh_cli = gh_cli;
+        self
+    }
+
+    /// Adds the `Timing` of each service to its result, like `--timings`.
+    pub fn timings(mut self, timings: bool) -> Self {
+        self.timings = timings;
+        self
+    }
+
+    /// Checks that all blobs of resolved images exist, like `--deep-verify`.
+    pub fn deep_verify(mut self, deep_verify: bool) -> Self {
+        self.deep_verify = deep_verify;
+        self
+    }
+
+    /// Credentials for a registry host, like `global.registries`.
+    pub fn registry(mut self, host: &str, credentials: RegistryCredentials) -> Self {
+        self.registries.insert(host.to_string(), credentials);
+        self
+    }
+
+    /// Validates the services and creates the HTTP client.
+    pub fn build(self) -> Result<VersionUpdater, AppError> {
+        if self.gh_cli {
+            login::gh_cli_token();
+        }
+        let mut services = self.services;
+        for (name, service) in services.iter_mut() {
+            service.git = service
+                .git
+                .clone()
+                .with_global_github_auth(self.github_auth)
+                .with_gh_cli(self.gh_cli);
+            if service.image.credentials.is_none() && !service.image.is_none() {
+                service.image.credentials =
+                    registry::credentials_for(&self.registries, &service.image.name);
+            }
+            if let Err(e) = service.validate() {
+                error!("Invalid configuration for service '{}': {}", name, e);
+                return Err(e);
+            }
+        }
+        let cache = self.cache.as_ref().and_then(Cache::new);
+        Ok(VersionUpdater {
+            services,
+            http: HttpClient::new(&self.http, cache)?,
+            timings: self.timings,
+            deep_verify: self.deep_verify,
+        })
+    }
+}
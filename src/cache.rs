@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, trace, warn};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CacheConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Defaults to `version-updater` in the user's cache directory
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    /// How long responses are reused
+    #[serde(default = "default_ttl", with = "humantime_serde")]
+    pub ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            dir: None,
+            ttl: default_ttl(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_ttl() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+/// On-disk cache of API responses, one file per key. Entries expire `ttl`
+/// after they were written.
+#[derive(Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl Cache {
+    /// Returns `None` if caching is disabled or no cache directory is available.
+    pub fn new(config: &CacheConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let dir = config
+            .dir
+            .clone()
+            .or_else(|| dirs::cache_dir().map(|dir| dir.join("version-updater")))?;
+        Some(Self {
+            dir,
+            ttl: config.ttl,
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        let path = self.path(key);
+        let age = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if age > self.ttl {
+            trace!("Cache entry for {} expired", key);
+            return None;
+        }
+        let value = fs::read_to_string(&path).ok()?;
+        debug!("Using cached response for {}", key);
+        Some(value)
+    }
+
+    /// Returns an entry even if it expired, with the time it was written.
+    pub fn get_expired(&self, key: &str) -> Option<(String, SystemTime)> {
+        let path = self.path(key);
+        let written = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        let value = fs::read_to_string(&path).ok()?;
+        debug!("Using expired cache entry for {}", key);
+        Some((value, written))
+    }
+
+    /// Stores a value. Failures are only logged, the cache is best effort.
+    pub fn put(&self, key: &str, value: &str) {
+        let result = fs::create_dir_all(&self.dir).and_then(|_| fs::write(self.path(key), value));
+        if let Err(e) = result {
+            warn!("Failed to write cache entry for {}: {}", key, e);
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let hash: String = Sha256::digest(key.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        self.dir.join(hash)
+    }
+}
@@ -0,0 +1,892 @@
+//! The command line interface of the `version-updater` binary.
+
+use super::cache::Cache;
+use super::commit::{self, CommitRef};
+use super::config::{AppConfig, Args, Command, LoginProvider, OutputData, OutputFormat};
+use super::error::AppError;
+use super::git::Provider;
+use super::health::Heartbeat;
+use super::logging::{init_logging, RotatingFile};
+use super::notify::{self, EventKind};
+use super::release_webhook::ReleaseWebhooks;
+use super::schedule::Scheduler;
+use super::server::{self, ServerState};
+use super::service::{process_services, process_services_until, Stale};
+use super::shutdown;
+use super::summary::Summary;
+#[cfg(feature = "docker")]
+use super::swarm;
+use super::telemetry::Telemetry;
+use super::watch::FileWatcher;
+use super::{doctor, files, history, import, login, portainer, pull_request, registry, updater};
+use super::{GitClient, GitConfig, HttpClient, ImageConfig, RegistryClient, ServiceConfig};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Runs the binary with the parsed command line: sets up logging, holds the
+/// --lock-file and runs the subcommand or the check, exiting with the status
+/// of the signal that interrupted it, if any.
+pub async fn main(args: Args) -> Result<()> {
+    let log_file = match &args.log_file {
+        Some(path) => Some(
+            RotatingFile::open(path, args.log_rotate, args.log_max_size, args.log_keep)
+                .with_context(|| format!("Failed to open log file {}", path.display()))?,
+        ),
+        None => None,
+    };
+    let telemetry = args
+        .otlp_endpoint
+        .as_deref()
+        .map(Telemetry::init)
+        .transpose()?;
+    init_logging(
+        Some(args.log_level()),
+        args.own_log_level(),
+        args.color.enabled(),
+        log_file,
+        telemetry.as_ref(),
+    );
+    // Held until the process exits
+    let lock = match &args.lock_file {
+        Some(path) => Some(files::lock(path, args.wait_for_lock)?),
+        None => None,
+    };
+    let result = run_command(args).await;
+    if let Err(e) = &result {
+        if let Some(AppError::Interrupted(signal)) = e.downcast_ref() {
+            error!("{:#}", e);
+            drop(lock);
+            drop(telemetry);
+            std::process::exit(signal.exit_code());
+        }
+    }
+    result
+}
+
+async fn run_command(mut args: Args) -> Result<()> {
+    match args.command.take() {
+        Some(Command::Import { source }) => {
+            print!("{}", import::run(&source)?);
+            return Ok(());
+        }
+        Some(Command::Check {
+            repo,
+            git_type,
+            project_id,
+            version_filter,
+            private,
+            image,
+            tag,
+        }) => {
+            let git_type = Provider::from(git_type);
+            if repo.is_none()
+                && matches!(
+                    git_type,
+                    Provider::Github | Provider::Codeberg | Provider::AzureDevops
+                )
+            {
+                return Err(AppError::ConfigError(format!(
+                    "--repo is required for {}",
+                    git_type.name()
+                ))
+                .into());
+            }
+            let name = repo.clone().or(image.clone()).unwrap_or_default();
+            let mut git = GitConfig::new(git_type, repo.as_deref().unwrap_or_default())
+                .with_filter(&version_filter)
+                .with_private(private);
+            git.project_id = project_id;
+            let image = match &image {
+                Some(image) => ImageConfig::new(image, &tag),
+                None => ImageConfig::none(),
+            };
+            let config = AppConfig::single(args, &name, ServiceConfig::new(git, image))?;
+            return check_service(&config, &name).await;
+        }
+        Some(Command::List {
+            tags,
+            provider,
+            registry,
+        }) => {
+            let services = AppConfig::load_unchecked(args)?.services;
+            let registry = registry.as_deref().map(registry::canonical_registry);
+            let services = services.iter().filter(|(_, service)| {
+                (tags.is_empty() || tags.iter().any(|tag| service.tags.contains(tag)))
+                    && provider
+                        .as_ref()
+                        .is_none_or(|provider| service.git.git_type.name() == provider)
+                    && registry.is_none_or(|registry| {
+                        !service.image.is_none() && service.image.registry() == registry
+                    })
+            });
+            print!("{}", list_services(services));
+            return Ok(());
+        }
+        Some(Command::Releases {
+            source,
+            limit,
+            version_filter,
+        }) => return print_releases(args, &source, limit, version_filter.as_deref()).await,
+        Some(Command::Tags {
+            image,
+            limit,
+            filter,
+        }) => return print_tags(args, &image, limit, filter.as_deref()).await,
+        Some(Command::History { service, since }) => {
+            return print_history(&args, &service, since);
+        }
+        Some(Command::Explain { service }) => {
+            // Every request is sent to show its URL, unless that isn't possible
+            args.no_cache |= !args.offline;
+            let config = AppConfig::load_config(args)?;
+            if !config.services.contains_key(&service) {
+                return Err(AppError::NotFound(format!("Service '{}'", service)).into());
+            }
+            return check_service(&config, &service).await;
+        }
+        Some(Command::Validate) => return validate(&args),
+        Some(Command::Doctor) => return run_doctor(args).await,
+        Some(Command::Login { provider }) => return login(args, provider).await,
+        Some(Command::Operator { interval, crd }) => {
+            return run_operator(args, interval, crd).await
+        }
+        Some(Command::Update { dry_run, yes }) => {
+            let config = AppConfig::load_config(args)?;
+            return run_update(&config, dry_run, yes);
+        }
+        None => {}
+    }
+
+    shutdown::install();
+    let mut config = AppConfig::load_config(args)?;
+    let state = match config.args.serve {
+        Some(addr) => {
+            let state = Arc::new(ServerState::default());
+            state
+                .set_release_webhooks(ReleaseWebhooks::new(&config)?)
+                .await;
+            server::start(addr, state.clone()).await?;
+            Some(state)
+        }
+        None => None,
+    };
+    let mut selected = config.selected_services()?;
+    if let Some(path) = &config.args.retry_failed {
+        let failed = failed_services(&config, path, selected.as_ref())?;
+        if failed.is_empty() {
+            info!("No failed services to retry in {}", path.display());
+            return Ok(());
+        }
+        info!("Retrying {} failed services", failed.len());
+        return check(&config, Some(&failed), false, None).await;
+    }
+    if !config.args.daemon && state.is_none() {
+        return check(&config, selected.as_ref(), false, None).await;
+    }
+
+    let mut scheduler = if config.args.daemon {
+        let interval = config.args.interval;
+        info!(
+            "Running as daemon, checking every {}",
+            humantime::format_duration(interval)
+        );
+        Some(Scheduler::new(
+            &config.services,
+            selected.as_ref(),
+            interval,
+        )?)
+    } else {
+        None
+    };
+    let mut watcher = FileWatcher::new(&config.sources())?;
+    let heartbeat = Heartbeat::new(config.args.heartbeat_file.clone());
+    heartbeat.ready();
+    let mut wake = Wake::Due;
+    loop {
+        let due = match (&mut scheduler, wake, &state) {
+            (Some(scheduler), Wake::Due, _) => Some(scheduler.take_due()),
+            (_, Wake::Release, Some(state)) => {
+                let mut triggered = state.take_triggered();
+                if let Some(selected) = &selected {
+                    triggered.retain(|name| selected.contains(name));
+                }
+                Some(triggered)
+            }
+            _ => selected.clone(),
+        };
+        // A failed check must not end the daemon, the next one may succeed.
+        // The cache may still hold the versions from before a webhook's release
+        let refresh = matches!(wake, Wake::Release);
+        let result = check(&config, due.as_ref(), refresh, state.as_deref()).await;
+        if let Some(state) = &state {
+            state.record_check(result.is_ok());
+        }
+        match result {
+            Err(e) if matches!(e.downcast_ref(), Some(AppError::Interrupted(_))) => return Err(e),
+            Err(e) => error!("Check failed: {:#}", e),
+            Ok(()) => {}
+        }
+
+        wake = loop {
+            beat(&heartbeat, state.as_deref());
+            match wait_for_next_check(
+                scheduler.as_ref(),
+                state.as_deref(),
+                &mut watcher,
+                heartbeat.interval(),
+            )
+            .await
+            {
+                Wake::Reload => match config.reload() {
+                    Ok(()) => {
+                        info!(
+                            "Reloaded configuration with {} services",
+                            config.services.len()
+                        );
+                        match config.selected_services() {
+                            Ok(new_selection) => selected = new_selection,
+                            Err(e) => error!("Keeping the current service selection: {}", e),
+                        }
+                        if let Some(state) = &state {
+                            match ReleaseWebhooks::new(&config) {
+                                Ok(webhooks) => state.set_release_webhooks(webhooks).await,
+                                Err(e) => error!("Keeping the current release webhooks: {}", e),
+                            }
+                        }
+                        if let Some(scheduler) = &mut scheduler {
+                            *scheduler = Scheduler::new(
+                                &config.services,
+                                selected.as_ref(),
+                                config.args.interval,
+                            )?;
+                        }
+                        // The new scheduler has every service due right away
+                        break Wake::Due;
+                    }
+                    Err(e) => error!("Keeping the current configuration, reload failed: {}", e),
+                },
+                Wake::Heartbeat => {}
+                Wake::Shutdown => {
+                    info!("Shutting down");
+                    heartbeat.stopping();
+                    return Ok(());
+                }
+                wake => break wake,
+            }
+        };
+    }
+}
+
+/// Why the check loop woke up.
+#[derive(Clone, Copy)]
+enum Wake {
+    /// Services are due according to the scheduler
+    Due,
+    /// A refresh was requested through the API
+    Refresh,
+    /// A release webhook triggered a check of some services
+    Release,
+    /// The configuration changed on disk
+    Reload,
+    /// A heartbeat is due
+    Heartbeat,
+    /// SIGINT or SIGTERM was received
+    Shutdown,
+}
+
+/// Reports that the check loop is alive.
+fn beat(heartbeat: &Heartbeat, state: Option<&ServerState>) {
+    heartbeat.beat();
+    if let Some(state) = state {
+        state.heartbeat();
+    }
+}
+
+/// Waits until the next service is due, a refresh is requested through the
+/// API, a release webhook is received, the configuration changes or the next
+/// heartbeat is due.
+async fn wait_for_next_check(
+    scheduler: Option<&Scheduler>,
+    state: Option<&ServerState>,
+    watcher: &mut FileWatcher,
+    heartbeat_interval: Duration,
+) -> Wake {
+    let next_due = async {
+        match scheduler {
+            Some(scheduler) => tokio::time::sleep(scheduler.until_next()).await,
+            None => std::future::pending().await,
+        }
+    };
+    let refresh = async {
+        match state {
+            Some(state) => state.refresh_requested().await,
+            None => std::future::pending().await,
+        }
+    };
+    let release = async {
+        match state {
+            Some(state) => state.check_triggered().await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = next_due => Wake::Due,
+        _ = refresh => Wake::Refresh,
+        _ = release => Wake::Release,
+        _ = watcher.changed() => Wake::Reload,
+        _ = tokio::time::sleep(heartbeat_interval) => Wake::Heartbeat,
+        _ = shutdown::requested() => Wake::Shutdown,
+    }
+}
+
+/// Resolves the `due` services (all if `None`), writes and publishes the output
+/// and applies the updates requested on the command line. Services that are not
+/// due or not selected keep their result from the previous output. With
+/// `refresh`, the due services are looked up without the cache.
+async fn check(
+    config: &AppConfig,
+    due: Option<&HashSet<String>>,
+    refresh: bool,
+    state: Option<&ServerState>,
+) -> Result<()> {
+    let previous = read_previous_output(&config.args);
+    let interrupt = async {
+        shutdown::requested().await;
+    };
+    let start = Instant::now();
+    let run = process_services_until(config, due, refresh, interrupt).await?;
+    let mut output = run.output;
+    if let Some(signal) = shutdown::received().filter(|_| run.interrupted) {
+        // The output keeps the last complete results, the partial ones go next to it
+        let path = files::sibling_path(Path::new(config.args.output_path()), ".partial");
+        write_output(&output, &path, &config.args)?;
+        warn!(
+            "Wrote the {} completed services to {}",
+            output.len(),
+            path.display()
+        );
+        return Err(AppError::Interrupted(signal).into());
+    }
+    if let (true, Some(previous)) = (config.args.offline, &previous) {
+        answer_from_previous(&mut output, previous, Path::new(config.args.output_path()));
+    }
+    let summary = Summary::new(&output, previous.as_ref(), start.elapsed())
+        .with_budgets(run.budgets)
+        .with_durations(&run.durations);
+    if let (Some(_), Some(previous)) = (due, &previous) {
+        for (name, result) in previous {
+            if config.services.contains_key(name) && !output.contains_key(name) {
+                output.insert(name.clone(), result.clone());
+            }
+        }
+    }
+    write_output(&output, Path::new(config.args.output_path()), &config.args)?;
+    if let Some(path) = &config.args.history_file {
+        match history::record(path, &output) {
+            Ok(0) => {}
+            Ok(added) => info!("Recorded {} changes in {}", added, path.display()),
+            Err(e) => error!("{}", e),
+        }
+    }
+    if let Some(state) = state {
+        state.publish(&output).await;
+    }
+    if let Some(previous) = &previous {
+        let events = notify::events(previous, &output);
+        report_changes(&events);
+        if config.args.offline {
+            info!("Offline, sending no notifications");
+        } else {
+            notify::send(&config.global.notifications, &config.global.http, &events).await;
+        }
+    }
+
+    apply_updates(config, &output, previous.as_ref()).await?;
+
+    // Optionally, you could check if any services failed
+    let failed_services: Vec<_> = output
+        .iter()
+        .filter_map(|(name, info)| Some((name, info.error()?)))
+        .collect();
+
+    if !failed_services.is_empty() {
+        warn!("{} services failed to process:", failed_services.len());
+        for (name, error) in failed_services {
+            warn!("  {}: {}", name, error);
+        }
+    }
+    if !config.args.quiet {
+        eprint!("{}", summary.render(config.args.color.enabled()));
+    }
+
+    Ok(())
+}
+
+/// Replaces the entries of services that did not resolve offline with their
+/// resolved entry in the previous output, marked as stale since it was written.
+fn answer_from_previous(output: &mut OutputData, previous: &OutputData, path: &Path) {
+    let written = fs::metadata(path).and_then(|m| m.modified()).ok();
+    for (name, entry) in output.iter_mut().filter(|(_, e)| e.tag().is_none()) {
+        let Some(fallback) = previous.get(name) else {
+            continue;
+        };
+        let Some(tag) = fallback.tag() else {
+            continue;
+        };
+        let stale = fallback
+            .stale
+            .map(|stale| Stale::at(stale.fetched_at))
+            .or(written.map(Stale::since));
+        warn!(
+            "{} is not cached, using {} from the previous output",
+            name, tag
+        );
+        *entry = fallback.clone().with_stale(stale);
+    }
+}
+
+/// Logs the services whose resolved tag changed since the previous output.
+fn report_changes(events: &[notify::Event]) {
+    for event in events.iter().filter(|e| e.event == EventKind::Changed) {
+        match (&event.old_tag, &event.new_tag) {
+            (Some(old), Some(new)) => info!("New tag for {}: {} -> {}", event.service, old, new),
+            (None, Some(new)) => info!("{} resolved to {}", event.service, new),
+            _ => {}
+        }
+    }
+}
+
+/// Applies the resolved versions to files, commits, pull requests, swarm services
+/// and Portainer stacks as requested on the command line.
+async fn apply_updates(
+    config: &AppConfig,
+    output: &OutputData,
+    previous: Option<&OutputData>,
+) -> Result<()> {
+    let args = &config.args;
+    if args.update {
+        let plan = updater::plan_updates(&config.services, output, previous)?;
+        info!("{} files need updating", plan.changes.len());
+        if args.dry_run {
+            for change in &plan.changes {
+                info!("Would update {}", change.path.display());
+            }
+        } else {
+            updater::apply_changes(&plan.changes, args.backup)?;
+
+            if (args.commit || args.pull_request) && args.offline {
+                warn!("Offline, the updated files are neither committed nor pushed");
+            } else if args.commit {
+                commit::commit_updates(&plan, &config.global.commit, CommitRef::Head)?;
+            } else if args.pull_request {
+                let pr_config = config.global.pull_request.as_ref().ok_or_else(|| {
+                    AppError::PullRequestError("global.pull_request is not configured".to_string())
+                })?;
+                pull_request::publish(&plan, pr_config, &config.global.commit, &config.global.http)
+                    .await?;
+            }
+        }
+    }
+
+    #[cfg(not(feature = "docker"))]
+    if args.swarm {
+        return Err(AppError::FeatureDisabled("Docker Swarm", "docker").into());
+    }
+    #[cfg(feature = "docker")]
+    if args.swarm {
+        let changes =
+            swarm::apply(&config.global.swarm, &config.services, output, args.dry_run).await?;
+        for change in &changes {
+            info!(
+                "{} swarm service {} ({}): {} -> {}",
+                if args.dry_run {
+                    "Would update"
+                } else {
+                    "Updated"
+                },
+                change.swarm_service,
+                change.service,
+                change.from,
+                change.to
+            );
+        }
+    }
+
+    if args.portainer && args.offline {
+        warn!("Offline, no Portainer stacks are redeployed");
+    } else if args.portainer {
+        let portainer_config = config.global.portainer.as_ref().ok_or_else(|| {
+            AppError::PortainerError("global.portainer is not configured".to_string())
+        })?;
+        let stacks = portainer::redeploy(
+            portainer_config,
+            &config.global.http,
+            &config.services,
+            output,
+            previous,
+            args.dry_run,
+        )
+        .await?;
+        info!("{} Portainer stacks redeployed", stacks.len());
+    }
+    Ok(())
+}
+
+/// Applies the versions of an existing output file to the update targets, printing
+/// the changes as unified diffs and asking for confirmation unless `yes` is set.
+fn run_update(config: &AppConfig, dry_run: bool, yes: bool) -> Result<()> {
+    let args = &config.args;
+    if args.output.is_none() {
+        return Err(AppError::UpdateError(
+            "--output is required to read the resolved versions".to_string(),
+        )
+        .into());
+    }
+    let content = fs::read_to_string(args.output_path())?;
+    let output = parse_output(&content, args.format).map_err(AppError::UpdateError)?;
+
+    let plan = updater::plan_updates(&config.services, &output, None)?;
+    if plan.changes.is_empty() {
+        info!("All update targets are up to date");
+        return Ok(());
+    }
+    for change in &plan.changes {
+        print!("{}", change.unified_diff());
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    if !yes {
+        if !io::stdin().is_terminal() {
+            return Err(AppError::UpdateError(
+                "Refusing to apply changes without --yes in a non-interactive session".to_string(),
+            )
+            .into());
+        }
+        eprint!("Apply changes to {} files? [y/N] ", plan.changes.len());
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            info!("No files were changed");
+            return Ok(());
+        }
+    }
+    updater::apply_changes(&plan.changes, args.backup)?;
+    info!("Updated {} files", plan.changes.len());
+    Ok(())
+}
+
+/// An HTTP client with the settings and cache of `config`.
+fn http_client(config: &AppConfig) -> Result<HttpClient> {
+    let cache = match config.args.no_cache {
+        true => None,
+        false => Cache::new(&config.global.cache),
+    };
+    Ok(HttpClient::new(&config.global.http, cache)?.with_offline(config.args.offline))
+}
+
+/// A table of `services` sorted by name, with their git source, image and tags.
+fn list_services<'a>(services: impl Iterator<Item = (&'a String, &'a ServiceConfig)>) -> String {
+    let or_dash = |value: String| match value.is_empty() {
+        true => "-".to_string(),
+        false => value,
+    };
+    let mut rows: Vec<Vec<String>> = services
+        .map(|(name, service)| {
+            let image = match service.image.is_none() {
+                true => String::new(),
+                false => format!("{}:{}", service.image.name, service.image.tag),
+            };
+            vec![
+                name.clone(),
+                service.git.git_type.name().to_string(),
+                or_dash(service.git.to_string()),
+                or_dash(image),
+                or_dash(service.tags.join(",")),
+            ]
+        })
+        .collect();
+    rows.sort();
+    table(&["NAME", "PROVIDER", "REPOSITORY", "IMAGE", "TAGS"], rows)
+}
+
+/// Aligns the columns of `rows` below `header`.
+fn table(header: &[&str], rows: Vec<Vec<String>>) -> String {
+    let header: Vec<String> = header.iter().map(|cell| cell.to_string()).collect();
+    let mut widths = vec![0; header.len()];
+    for row in rows.iter().chain([&header]) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut table = String::new();
+    for row in [&header].into_iter().chain(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+async fn run_doctor(args: Args) -> Result<()> {
+    let config = AppConfig::load_unchecked(args)?;
+    let checks = doctor::run(&config, &http_client(&config)?).await;
+    print!("{}", doctor::render(&checks, config.args.color.enabled()));
+    let failed = checks.iter().filter(|check| check.result.is_err()).count();
+    match failed {
+        0 => Ok(()),
+        failed => Err(AppError::ConfigError(format!("{} checks failed", failed)).into()),
+    }
+}
+
+/// Prints the CRD if `crd`, or else reconciles the VersionCheck resources.
+#[cfg(feature = "kubernetes")]
+async fn run_operator(args: Args, interval: Duration, crd: bool) -> Result<()> {
+    if crd {
+        print!("{}", super::operator::CRD);
+        return Ok(());
+    }
+    shutdown::install();
+    let namespaces = args.namespaces.clone();
+    let config = AppConfig::load_global(args)?;
+    let http = http_client(&config)?;
+    super::operator::run(config.global, http, &namespaces, interval).await?;
+    Ok(())
+}
+
+#[cfg(not(feature = "kubernetes"))]
+async fn run_operator(_args: Args, _interval: Duration, _crd: bool) -> Result<()> {
+    Err(AppError::FeatureDisabled("The Kubernetes operator", "kubernetes").into())
+}
+
+/// Logs in to `provider` with the device flow and stores the token.
+async fn login(args: Args, provider: LoginProvider) -> Result<()> {
+    let LoginProvider::Github {
+        client_id,
+        scope,
+        token_file,
+    } = provider;
+    let client_id = client_id
+        .or_else(|| std::env::var(login::GITHUB_CLIENT_ID_ENV).ok())
+        .ok_or_else(|| {
+            AppError::LoginError(format!(
+                "--client-id or {} is required, register an OAuth app with device flow \
+                 enabled at https://github.com/settings/developers",
+                login::GITHUB_CLIENT_ID_ENV
+            ))
+        })?;
+    let path = token_file
+        .or_else(|| login::token_path(&Provider::Github))
+        .ok_or_else(|| AppError::LoginError("no config directory, use --token-file".into()))?;
+    let config = AppConfig::load_global(args)?;
+    let http = http_client(&config)?;
+
+    let code = login::request_code(&http, &client_id, &scope).await?;
+    println!(
+        "Enter the code {} at {} within {}",
+        code.user_code,
+        code.verification_uri,
+        humantime::format_duration(Duration::from_secs(code.expires_in))
+    );
+    let token = login::poll_token(&http, &client_id, &code).await?;
+    login::store_token(&path, &token)?;
+    println!("Logged in, the token is stored in {}", path.display());
+    Ok(())
+}
+
+/// Prints the entries of `service` in the --history-file, oldest first.
+fn print_history(args: &Args, service: &str, since: Option<DateTime<Utc>>) -> Result<()> {
+    let path = args.history_file.as_deref().ok_or_else(|| {
+        AppError::HistoryError("--history-file is required to read the history".to_string())
+    })?;
+    let rows: Vec<Vec<String>> = history::read(path)?
+        .into_iter()
+        .filter(|entry| entry.service == service)
+        .filter(|entry| since.is_none_or(|since| entry.time().is_some_and(|time| time >= since)))
+        .map(|entry| {
+            let time = entry.time().map_or(entry.time.clone(), |time| {
+                time.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            });
+            vec![
+                time,
+                entry.version,
+                entry.tag,
+                entry.digest.unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+        .collect();
+    if rows.is_empty() {
+        info!("No history of {} in {}", service, path.display());
+        return Ok(());
+    }
+    print!("{}", table(&["TIME", "VERSION", "TAG", "DIGEST"], rows));
+    Ok(())
+}
+
+/// Runs the `validate` subcommand, failing if any problem was found.
+fn validate(args: &Args) -> Result<()> {
+    let (services, problems) = AppConfig::validate(args);
+    if problems.is_empty() {
+        println!("Configuration is valid ({} services)", services);
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    Err(AppError::ConfigError(format!("{} problems found", problems.len())).into())
+}
+
+/// Resolves the single service of `config` and prints its output entry.
+async fn check_service(config: &AppConfig, name: &str) -> Result<()> {
+    let selected = HashSet::from([name.to_string()]);
+    let mut output = process_services(config, Some(&selected)).await?;
+    let version = output.remove(name).context("Service was not resolved")?;
+    match config.args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&version)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&version)?),
+    }
+    match version.error() {
+        Some(error) => Err(anyhow::anyhow!(error)),
+        None => Ok(()),
+    }
+}
+
+/// Prints the tags of `image` matching `filter`, highest version first.
+async fn print_tags(
+    args: Args,
+    image: &str,
+    limit: Option<usize>,
+    filter: Option<&str>,
+) -> Result<()> {
+    let filter = filter
+        .map(Regex::new)
+        .transpose()
+        .map_err(|e| AppError::ConfigError(format!("--filter: {}", e)))?;
+    let config = AppConfig::load_global(args)?;
+    let credentials = registry::credentials_for(&config.global.registries, image);
+    let client = RegistryClient::new(image, http_client(&config)?).with_credentials(credentials);
+    let mut tags = client.list_tags().await?;
+    tags.retain(|tag| filter.as_ref().is_none_or(|filter| filter.is_match(tag)));
+    registry::sort_tags(&mut tags);
+    for tag in tags.iter().take(limit.unwrap_or(usize::MAX)) {
+        println!("{}", tag);
+    }
+    Ok(())
+}
+
+/// Prints a table of the recent releases of the repository `source`, with
+/// the versions `version_filter` extracts from their tags.
+async fn print_releases(
+    args: Args,
+    source: &str,
+    limit: usize,
+    version_filter: Option<&str>,
+) -> Result<()> {
+    let filter = version_filter
+        .map(Regex::new)
+        .transpose()
+        .map_err(|e| AppError::ConfigError(format!("--version-filter: {}", e)))?;
+    let git = GitConfig::parse_source(source)?;
+    let config = AppConfig::single(args, source, ServiceConfig::new(git, ImageConfig::none()))?;
+    let git = &config.services[source].git;
+    let releases = GitClient::releases(git, &http_client(&config)?, limit).await?;
+
+    let mut header = vec!["TAG", "PUBLISHED", "PRERELEASE"];
+    if filter.is_some() {
+        header.push("VERSION");
+    }
+    let rows = releases.into_iter().map(|release| {
+        let published = release.published_at.as_deref().unwrap_or("-");
+        let mut row = vec![
+            release.tag.clone(),
+            published.split('T').next().unwrap_or(published).to_string(),
+            if release.prerelease { "yes" } else { "no" }.to_string(),
+        ];
+        if let Some(filter) = &filter {
+            let version = filter.captures(&release.tag).and_then(|c| c.get(1));
+            row.push(version.map_or("-", |v| v.as_str()).to_string());
+        }
+        row
+    });
+    print!("{}", table(&header, rows.collect()));
+    Ok(())
+}
+
+fn parse_output(content: &str, format: OutputFormat) -> Result<OutputData, String> {
+    match format {
+        OutputFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+        OutputFormat::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+    }
+}
+
+/// Reads the output of the previous run, which the update step uses to know
+/// which versions services are updated from. With --retry-failed, that output
+/// is the one being retried.
+fn read_previous_output(args: &Args) -> Option<OutputData> {
+    let path = match &args.retry_failed {
+        Some(path) => path.as_path(),
+        None => Path::new(args.output_path()),
+    };
+    let content = fs::read_to_string(path).ok()?;
+    match parse_output(&content, args.format) {
+        Ok(previous) => Some(previous),
+        Err(e) => {
+            warn!(
+                "Ignoring unreadable previous output {}: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Returns the configured and selected services that have an error in the
+/// output at `path`, including rate-limited ones.
+fn failed_services(
+    config: &AppConfig,
+    path: &Path,
+    selected: Option<&HashSet<String>>,
+) -> Result<HashSet<String>> {
+    let content = fs::read_to_string(path)?;
+    let output = parse_output(&content, config.args.format)
+        .map_err(|e| anyhow::anyhow!("Invalid output {}: {}", path.display(), e))?;
+    Ok(output
+        .into_iter()
+        .filter(|(name, result)| {
+            result.tag().is_none()
+                && config.services.contains_key(name)
+                && selected.is_none_or(|selected| selected.contains(name))
+        })
+        .map(|(name, _)| name)
+        .collect())
+}
+
+fn write_output(output: &OutputData, path: &Path, args: &Args) -> Result<()> {
+    // Output results in requested format
+    let output_content = match args.format {
+        OutputFormat::Json => serde_json::to_string_pretty(&output)?,
+        OutputFormat::Yaml => serde_yaml::to_string(&output)?,
+    };
+
+    // Write to a temporary file and rename it so readers never see a partial file
+    info!("Writing output to file: {}", path.display());
+    files::write_atomic(path, output_content.as_bytes(), args.backup)?;
+    info!("Output written successfully");
+    Ok(())
+}
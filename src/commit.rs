@@ -0,0 +1,317 @@
+use super::error::AppError;
+use super::template;
+use super::updater::{ServiceUpdate, UpdatePlan};
+use git2::{
+    Cred, Index, IndexEntry, IndexTime, Oid, PushOptions, RemoteCallbacks, Repository, Signature,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CommitConfig {
+    #[serde(default)]
+    pub mode: CommitMode,
+    /// Defaults to `user.name`/`user.email` from the git configuration
+    #[serde(default)]
+    pub author: Option<CommitIdentity>,
+    /// Defaults to the author
+    #[serde(default)]
+    pub committer: Option<CommitIdentity>,
+    /// Message templates, see the `template` module for the available variables
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitMode {
+    /// One commit per updated service
+    #[default]
+    PerService,
+    /// A single commit containing all updates
+    Combined,
+}
+
+/// Where the commits of an update run are created.
+#[derive(Clone, Copy)]
+pub enum CommitRef<'a> {
+    /// On top of the checked out branch
+    Head,
+    /// On a branch that is (re)created from HEAD, leaving the checkout alone
+    Branch(&'a str),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommitIdentity {
+    pub name: String,
+    pub email: String,
+}
+
+/// Commits the files changed by an update run to the git repositories containing them
+/// and returns the working directories of those repositories.
+///
+/// Commits are built from HEAD plus the updated files only, so unrelated changes
+/// that happen to be staged are never picked up.
+pub fn commit_updates(
+    plan: &UpdatePlan,
+    config: &CommitConfig,
+    target: CommitRef,
+) -> Result<Vec<PathBuf>, AppError> {
+    let mut repos = Vec::new();
+    match config.mode {
+        CommitMode::PerService => {
+            for update in &plan.services {
+                commit_service_files(update, config, target, &mut repos)?;
+            }
+        }
+        CommitMode::Combined => {
+            if plan.changes.is_empty() {
+                return Ok(repos);
+            }
+            let message = template::message(
+                config
+                    .title
+                    .as_deref()
+                    .unwrap_or(template::DEFAULT_COMBINED_TITLE),
+                config
+                    .body
+                    .as_deref()
+                    .unwrap_or(template::DEFAULT_COMBINED_BODY),
+                &template::plan_vars(plan),
+            );
+            let files: Vec<(PathBuf, String)> = plan
+                .changes
+                .iter()
+                .map(|change| (change.path.clone(), change.updated.clone()))
+                .collect();
+            commit_files(&files, &message, config, target, &mut repos)?;
+        }
+    }
+    Ok(repos)
+}
+
+/// Commits the files changed by a single service and returns the working
+/// directories of the repositories containing them.
+pub fn commit_service(
+    update: &ServiceUpdate,
+    config: &CommitConfig,
+    target: CommitRef,
+) -> Result<Vec<PathBuf>, AppError> {
+    let mut repos = Vec::new();
+    commit_service_files(update, config, target, &mut repos)?;
+    Ok(repos)
+}
+
+fn commit_service_files(
+    update: &ServiceUpdate,
+    config: &CommitConfig,
+    target: CommitRef,
+    repos: &mut Vec<PathBuf>,
+) -> Result<(), AppError> {
+    let message = template::message(
+        config
+            .title
+            .as_deref()
+            .unwrap_or(template::DEFAULT_SERVICE_TITLE),
+        config
+            .body
+            .as_deref()
+            .unwrap_or(template::DEFAULT_SERVICE_BODY),
+        &template::service_vars(update),
+    );
+    commit_files(&update.files, &message, config, target, repos)
+}
+
+/// Creates one commit per repository containing the given file contents.
+fn commit_files(
+    files: &[(PathBuf, String)],
+    message: &str,
+    config: &CommitConfig,
+    target: CommitRef,
+    repos: &mut Vec<PathBuf>,
+) -> Result<(), AppError> {
+    let mut by_repo: BTreeMap<PathBuf, Vec<(PathBuf, &str)>> = BTreeMap::new();
+    for (path, content) in files {
+        let (workdir, relative) = locate(path)?;
+        by_repo
+            .entry(workdir)
+            .or_default()
+            .push((relative, content.as_str()));
+    }
+
+    for (workdir, files) in by_repo {
+        let repo = Repository::open(&workdir).map_err(commit_error)?;
+        let first_commit = !repos.contains(&workdir);
+        let oid = commit_to_repo(&repo, &files, message, config, target, first_commit)
+            .map_err(commit_error)?;
+        info!(
+            "Created commit {} in {}: {}",
+            oid,
+            workdir.display(),
+            message.lines().next().unwrap_or_default()
+        );
+        if first_commit {
+            repos.push(workdir);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the working directory of the repository containing `path` and the
+/// path relative to it.
+fn locate(path: &Path) -> Result<(PathBuf, PathBuf), AppError> {
+    let absolute = path
+        .canonicalize()
+        .map_err(|e| AppError::CommitError(format!("{}: {}", path.display(), e)))?;
+    let repo = Repository::discover(absolute.parent().unwrap_or(&absolute)).map_err(|e| {
+        AppError::CommitError(format!(
+            "{} is not in a git repository: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| AppError::CommitError("Bare repositories are not supported".to_string()))?
+        .canonicalize()
+        .map_err(|e| AppError::CommitError(e.to_string()))?;
+    let relative = absolute
+        .strip_prefix(&workdir)
+        .map_err(|e| AppError::CommitError(e.to_string()))?
+        .to_path_buf();
+    Ok((workdir, relative))
+}
+
+fn commit_to_repo(
+    repo: &Repository,
+    files: &[(PathBuf, &str)],
+    message: &str,
+    config: &CommitConfig,
+    target: CommitRef,
+    first_commit: bool,
+) -> Result<Oid, git2::Error> {
+    let author = match &config.author {
+        Some(identity) => Signature::now(&identity.name, &identity.email)?,
+        None => repo.signature()?,
+    };
+    let committer = match &config.committer {
+        Some(identity) => Signature::now(&identity.name, &identity.email)?,
+        None => author.clone(),
+    };
+
+    let update_ref = match target {
+        CommitRef::Head => "HEAD".to_string(),
+        CommitRef::Branch(branch) => {
+            let reference = format!("refs/heads/{}", branch);
+            if first_commit {
+                // Start the branch over from HEAD so reruns produce the same history
+                let head = repo.head()?.peel_to_commit()?;
+                repo.reference(&reference, head.id(), true, "version-updater: reset branch")?;
+            }
+            reference
+        }
+    };
+    let parent = match repo.find_reference(&update_ref) {
+        Ok(reference) => Some(reference.peel_to_commit()?),
+        Err(_) => None,
+    };
+
+    let mut index = Index::new()?;
+    if let Some(parent) = &parent {
+        index.read_tree(&parent.tree()?)?;
+    }
+    for (path, content) in files {
+        debug!("Staging {}", path.display());
+        let mode = index.get_path(path, 0).map_or(0o100644, |entry| entry.mode);
+        let entry = IndexEntry {
+            ctime: IndexTime::new(0, 0),
+            mtime: IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            file_size: content.len() as u32,
+            id: repo.blob(content.as_bytes())?,
+            flags: 0,
+            flags_extended: 0,
+            path: path.to_string_lossy().replace('\\', "/").into_bytes(),
+        };
+        index.add(&entry)?;
+    }
+    let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+
+    let parents: Vec<_> = parent.iter().collect();
+    let oid = repo.commit(
+        Some(&update_ref),
+        &author,
+        &committer,
+        message,
+        &tree,
+        &parents,
+    )?;
+    if let CommitRef::Branch(_) = target {
+        return Ok(oid);
+    }
+
+    // Refresh the real index for the committed files so they don't show up as modified
+    let mut repo_index = repo.index()?;
+    for (path, _) in files {
+        repo_index.add_path(path)?;
+    }
+    repo_index.write()?;
+
+    Ok(oid)
+}
+
+/// Force-pushes `branch` to `remote`, authenticating with `token` over HTTPS if given.
+pub fn push_branch(
+    workdir: &Path,
+    remote: &str,
+    branch: &str,
+    token: Option<&str>,
+) -> Result<(), AppError> {
+    let repo = Repository::open(workdir).map_err(commit_error)?;
+    let mut remote = repo.find_remote(remote).map_err(commit_error)?;
+
+    let mut rejection = None;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|_url, username, allowed| match token {
+        Some(token) if allowed.is_user_pass_plaintext() => {
+            Cred::userpass_plaintext("x-access-token", token)
+        }
+        _ => Cred::default().or_else(|_| Cred::ssh_key_from_agent(username.unwrap_or("git"))),
+    });
+    callbacks.push_update_reference(|reference, status| {
+        if let Some(status) = status {
+            rejection = Some(format!("{}: {}", reference, status));
+        }
+        Ok(())
+    });
+
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    let refspec = format!("+refs/heads/{0}:refs/heads/{0}", branch);
+    info!(
+        "Pushing {} to {}",
+        branch,
+        remote.name().unwrap_or_default()
+    );
+    remote
+        .push(&[refspec.as_str()], Some(&mut options))
+        .map_err(commit_error)?;
+    drop(options);
+
+    match rejection {
+        Some(reason) => Err(AppError::CommitError(format!("Push rejected: {}", reason))),
+        None => Ok(()),
+    }
+}
+
+fn commit_error(e: git2::Error) -> AppError {
+    AppError::CommitError(e.message().to_string())
+}
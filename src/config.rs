@@ -1,42 +1,434 @@
+use super::cache::CacheConfig;
+use super::commit::CommitConfig;
+use super::discovery;
+use super::discovery::HelmRelease;
+use super::env;
 use super::error::AppError;
-use super::git::GitConfig;
-use super::registry::ImageConfig;
+use super::git::{self, GitConfig, Provider, USER_AGENT_NAME};
+use super::history;
+use super::http::{self, HttpConfig};
+use super::logging::{self, ColorChoice, Rotation};
+use super::login;
+use super::matrix;
+use super::notify::NotificationConfig;
+use super::plugin::{ExecProvider, PluginConfig};
+use super::portainer::{PortainerConfig, PortainerTarget};
+use super::pull_request::PullRequestConfig;
+use super::registry::{self, ImageConfig, RegistryCredentials};
+use super::release_webhook::ReleaseWebhookConfig;
+use super::schedule;
+use super::service::{ResolutionOutcome, Stale, Timing, Verification, TAG_VARIABLES};
+#[cfg(feature = "docker")]
+use super::swarm::SwarmConfig;
+use super::tag_template;
+use super::updater::yaml::{display_path, key_position, Segment};
+use super::updater::UpdateTarget;
 
 use anyhow::Result;
-use clap::{Parser, ValueEnum};
-use log::{debug, error, info, trace};
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use glob::Pattern;
+use reqwest::header::USER_AGENT;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, error, info, trace, warn};
+
+#[cfg(not(feature = "docker"))]
+super::features::disabled_config!(SwarmConfig, "Docker Swarm", "docker");
 
 #[derive(Debug)]
 pub struct AppConfig {
     pub args: Args,
+    pub global: GlobalConfig,
     pub services: HashMap<String, ServiceConfig>,
 }
 
 impl AppConfig {
-    pub fn load_config() -> Result<Self, AppError> {
-        // Parse command line arguments
-        let args = Args::parse();
-        info!("Reading config file: {}", args.config);
+    pub fn load_config(args: Args) -> Result<Self, AppError> {
+        let (global, services) = Self::read(&args)?;
+        Ok(Self {
+            args,
+            global,
+            services,
+        })
+    }
+
+    /// Re-reads the config file and discovered services. The current
+    /// configuration is kept if the new one fails to parse or validate.
+    pub fn reload(&mut self) -> Result<(), AppError> {
+        let (global, services) = Self::read(&self.args)?;
+        self.global = global;
+        self.services = services;
+        Ok(())
+    }
+
+    /// Returns the services selected with --service, --service-glob, --tags and
+    /// --exclude-tags, or `None` to process all services.
+    pub fn selected_services(&self) -> Result<Option<HashSet<String>>, AppError> {
+        let args = &self.args;
+        let by_name = !args.services.is_empty() || !args.service_globs.is_empty();
+        if !by_name && args.tags.is_empty() && args.exclude_tags.is_empty() {
+            return Ok(None);
+        }
+
+        let mut selected = HashSet::new();
+        for name in &args.services {
+            if !self.services.contains_key(name) {
+                return Err(AppError::NotFound(format!("Service '{}'", name)));
+            }
+            selected.insert(name.clone());
+        }
+        for glob in &args.service_globs {
+            let pattern = Pattern::new(glob)
+                .map_err(|e| AppError::InvalidFilter(format!("'{}': {}", glob, e)))?;
+            let matches: Vec<&String> = self
+                .services
+                .keys()
+                .filter(|name| pattern.matches(name))
+                .collect();
+            if matches.is_empty() {
+                warn!("No services match '{}'", glob);
+            }
+            selected.extend(matches.into_iter().cloned());
+        }
+        if !by_name {
+            selected.extend(self.services.keys().cloned());
+        }
+
+        // Services need one of --tags and none of --exclude-tags
+        selected.retain(|name| {
+            let tags = &self.services[name].tags;
+            (args.tags.is_empty() || args.tags.iter().any(|tag| tags.contains(tag)))
+                && !args.exclude_tags.iter().any(|tag| tags.contains(tag))
+        });
+        if selected.is_empty() {
+            warn!("No services are selected");
+        }
+        Ok(Some(selected))
+    }
 
-        // Read and parse the config file
-        let config_content = fs::read_to_string(&args.config)?;
+    /// Files and directories the configuration is read from, for watching them
+    /// in daemon mode. Remote configs and stdin are not watched.
+    pub fn sources(&self) -> Vec<PathBuf> {
+        let mut sources: Vec<PathBuf> = self
+            .args
+            .config
+            .iter()
+            .filter(|config| *config != "-" && !is_url(config))
+            .map(PathBuf::from)
+            .collect();
+        sources.extend(self.args.discover_compose.iter().cloned());
+        sources
+    }
+
+    fn read_file(
+        source: &ConfigSource,
+        format: Option<ConfigFormat>,
+        http: &HttpConfig,
+    ) -> Result<ConfigFile, AppError> {
+        info!("Reading config file: {}", source);
+        let content = source.read(http).map_err(|e| match e {
+            AppError::FileRead(e) => {
+                AppError::FileRead(io::Error::new(e.kind(), format!("{}: {}", source, e)))
+            }
+            e => e,
+        })?;
         debug!("Config content read");
+        trace!("Config content is {}", content);
 
-        // Parse YAML into Config struct
-        let mut config: Config = serde_yaml::from_str(&config_content)?;
-        trace!("Config content is {}", config_content);
+        // Parse with environment variables expanded
+        let content = env::substitute(&content)?;
+        let parse_error = |e: String| AppError::ConfigError(format!("{}: {}", source, e));
+        let mut unknown = Vec::new();
+        let ignored = |path: serde_ignored::Path| unknown.push(key_path(&path));
+        let mut file: ConfigFile = match format
+            .unwrap_or_else(|| ConfigFormat::detect(source.path()))
+        {
+            ConfigFormat::Yaml => {
+                serde_ignored::deserialize(serde_yaml::Deserializer::from_str(&content), ignored)
+                    .map_err(|e| parse_error(e.to_string()))?
+            }
+            ConfigFormat::Json => {
+                let mut deserializer = serde_json::Deserializer::from_str(&content);
+                let file = serde_ignored::deserialize(&mut deserializer, ignored)
+                    .map_err(|e| parse_error(e.to_string()))?;
+                deserializer.end().map_err(|e| parse_error(e.to_string()))?;
+                file
+            }
+            ConfigFormat::Toml => toml::de::Deserializer::parse(&content)
+                .and_then(|deserializer| serde_ignored::deserialize(deserializer, ignored))
+                .map_err(|e| parse_error(e.to_string()))?,
+        };
+        file.content = content;
+        file.unknown = unknown;
+        Ok(file)
+    }
+
+    /// Reads and merges all config files and discovered services. Problems are
+    /// collected in `problems` and the affected file or service is skipped.
+    fn load(args: &Args, problems: &mut Vec<AppError>) -> Config {
+        let mut config = Config::default();
+        let mut global_file: Option<ConfigSource> = None;
+        let mut service_files: HashMap<String, ConfigSource> = HashMap::new();
+        let mut templates = HashMap::new();
+        let mut entries = Vec::new();
+        let mut contents = Vec::new();
+        let mut profiles: HashMap<String, Vec<(String, serde_yaml::Value, usize)>> = HashMap::new();
+
+        let sources = match config_sources(args) {
+            Ok(sources) => sources,
+            Err(e) => {
+                problems.push(e);
+                Vec::new()
+            }
+        };
+        for file in sources {
+            // A remote file is fetched with the HTTP settings of an earlier global
+            let content = match Self::read_file(&file, args.config_format, &config.global.http) {
+                Ok(content) => content,
+                Err(e) => {
+                    problems.push(e);
+                    continue;
+                }
+            };
+            for path in &content.unknown {
+                unknown_key(args.strict, problems, &file, &content.content, path);
+            }
+            if let Some(global) = content.global {
+                match &global_file {
+                    Some(first) => problems.push(AppError::ConfigError(format!(
+                        "global is defined in both {} and {}",
+                        first, file
+                    ))),
+                    None => {
+                        config.global = global;
+                        global_file = Some(file.clone());
+                    }
+                }
+            }
+            for (name, template) in content.templates {
+                if templates.insert(name.clone(), template).is_some() {
+                    problems.push(AppError::ConfigError(format!(
+                        "Template '{}' is defined more than once",
+                        name
+                    )));
+                }
+            }
+            for (name, service) in content.services {
+                if let Some(first) = service_files.get(&name) {
+                    problems.push(AppError::ConfigError(format!(
+                        "Service '{}' is defined in both {} and {}",
+                        name, first, file
+                    )));
+                    continue;
+                }
+                service_files.insert(name.clone(), file.clone());
+                entries.push((name, service, contents.len()));
+            }
+            for (profile, overlay) in content.profiles {
+                let overlays = profiles.entry(profile.clone()).or_default();
+                for (name, service) in overlay.services {
+                    if overlays.iter().any(|(existing, _, _)| *existing == name) {
+                        problems.push(AppError::ConfigError(format!(
+                            "Profile '{}' overrides service '{}' more than once",
+                            profile, name
+                        )));
+                        continue;
+                    }
+                    overlays.push((name, service, contents.len()));
+                }
+            }
+            contents.push((file, content.content));
+        }
+
+        for profile in &args.profiles {
+            let Some(overlays) = profiles.remove(profile) else {
+                problems.push(AppError::ConfigError(format!(
+                    "Profile '{}' is not defined",
+                    profile
+                )));
+                continue;
+            };
+            info!("Applying profile {}", profile);
+            for (name, overlay, file) in overlays {
+                match entries.iter_mut().find(|(entry, _, _)| *entry == name) {
+                    Some((_, value, _)) => matrix::merge(value, overlay),
+                    None => entries.push((name, overlay, file)),
+                }
+            }
+        }
+
+        for (entry, value, file) in entries {
+            let expanded = match matrix::expand(&entry, &value, &templates) {
+                Ok(expanded) => expanded,
+                Err(e) => {
+                    problems.push(e);
+                    continue;
+                }
+            };
+            // Expanded services share the keys of their entry, report them once
+            let mut unknown = Vec::new();
+            for (name, value) in expanded {
+                if config.services.contains_key(&name) {
+                    problems.push(AppError::ConfigError(format!(
+                        "Service '{}' from '{}' is already defined",
+                        name, entry
+                    )));
+                    continue;
+                }
+                let ignored = |path: serde_ignored::Path| {
+                    let path = key_path(&path);
+                    if !unknown.contains(&path) {
+                        unknown.push(path);
+                    }
+                };
+                match serde_ignored::deserialize(value, ignored) {
+                    Ok(service) => {
+                        config.services.insert(name, service);
+                    }
+                    Err(e) => {
+                        problems.push(AppError::ConfigError(format!("Service '{}': {}", name, e)))
+                    }
+                }
+            }
+            let (source, content) = &contents[file];
+            for path in unknown {
+                let mut full_path = vec![
+                    Segment::Key("services".to_string()),
+                    Segment::Key(entry.clone()),
+                ];
+                full_path.extend(path);
+                unknown_key(args.strict, problems, source, content, &full_path);
+            }
+        }
+
+        for file in &args.discover_compose {
+            let discovered = match discovery::compose::discover(file) {
+                Ok(discovered) => discovered,
+                Err(e) => {
+                    problems.push(e);
+                    continue;
+                }
+            };
+            for (name, service) in discovered {
+                if config.services.contains_key(&name) {
+                    warn!(
+                        "Ignoring discovered service '{}', it is defined in the config file",
+                        name
+                    );
+                    continue;
+                }
+                config.services.insert(name, service);
+            }
+        }
+
+        for source in &args.discover {
+            let discovered: Result<HashMap<String, ServiceConfig>, AppError> = match source {
+                #[cfg(feature = "docker")]
+                DiscoverySource::Docker => discovery::docker::discover(&config.services),
+                #[cfg(feature = "kubernetes")]
+                DiscoverySource::Kubernetes => {
+                    discovery::kubernetes::discover(&config.services, &args.namespaces)
+                }
+                #[cfg(feature = "kubernetes")]
+                DiscoverySource::Helm => {
+                    discovery::helm::discover(&mut config.services, &args.namespaces)
+                        .map(|()| HashMap::new())
+                }
+                #[cfg(not(feature = "docker"))]
+                DiscoverySource::Docker => {
+                    Err(AppError::FeatureDisabled("Docker discovery", "docker"))
+                }
+                #[cfg(not(feature = "kubernetes"))]
+                DiscoverySource::Kubernetes | DiscoverySource::Helm => Err(
+                    AppError::FeatureDisabled("Kubernetes discovery", "kubernetes"),
+                ),
+            };
+            match discovered {
+                Ok(discovered) => config.services.extend(discovered),
+                Err(e) => problems.push(e),
+            }
+        }
+
+        for (name, plugin) in &config.global.plugins {
+            git::register_provider(name, ExecProvider::new(name, plugin.clone()));
+        }
+        for service in config.services.values_mut() {
+            apply_global(&config.global, service);
+        }
+        if config.global.git.github.gh_cli {
+            login::gh_cli_token();
+        }
+        config
+    }
+
+    /// Reads the configuration without validating the services, for the
+    /// `list` and `doctor` subcommands.
+    pub fn load_unchecked(args: Args) -> Result<Self, AppError> {
+        let mut problems = Vec::new();
+        let config = Self::load(&args, &mut problems);
+        if let Some(problem) = problems.into_iter().next() {
+            return Err(problem);
+        }
+        Ok(Self {
+            args,
+            global: config.global,
+            services: config.services,
+        })
+    }
+
+    /// A configuration of only `service`, named `name`, with the global
+    /// settings of those config files that exist, for the `check` subcommand.
+    pub fn single(args: Args, name: &str, mut service: ServiceConfig) -> Result<Self, AppError> {
+        let mut config = Self::load_global(args)?;
+        apply_global(&config.global, &mut service);
+        service.validate()?;
+        config.services = HashMap::from([(name.to_string(), service)]);
+        Ok(config)
+    }
+
+    /// A configuration without services, with the global settings of those
+    /// config files that exist.
+    pub fn load_global(mut args: Args) -> Result<Self, AppError> {
+        args.config
+            .retain(|config| config == "-" || is_url(config) || Path::new(config).exists());
+        let mut problems = Vec::new();
+        let config = Self::load(&args, &mut problems);
+        if let Some(problem) = problems.into_iter().next() {
+            return Err(problem);
+        }
+        Ok(Self {
+            args,
+            global: config.global,
+            services: HashMap::new(),
+        })
+    }
+
+    fn read(args: &Args) -> Result<(GlobalConfig, HashMap<String, ServiceConfig>), AppError> {
+        let mut problems = Vec::new();
+        let mut config = Self::load(args, &mut problems);
+        if let Some(problem) = problems.into_iter().next() {
+            return Err(problem);
+        }
 
         // Create a new HashMap to store the updated services
         let mut updated_services = HashMap::new();
 
         for (name, service) in config.services.iter_mut() {
-            service.git = <GitConfig as Clone>::clone(&service.git)
-                .with_global_github_auth(config.global.git.github.authenticate);
+            if let Some(expression) = &service.schedule {
+                schedule::parse(expression)?;
+            }
 
-            match service.git.validate() {
+            match service.validate() {
                 Ok(()) => {
                     updated_services.insert(name.clone(), service.clone());
                 }
@@ -65,55 +457,592 @@ impl AppConfig {
             }
         }
 
-        Ok(Self {
-            args,
-            services: updated_services,
-        })
+        Ok((config.global, updated_services))
+    }
+
+    /// Checks the configuration without any network requests and returns all
+    /// problems found, for the `validate` subcommand.
+    pub fn validate(args: &Args) -> (usize, Vec<String>) {
+        let mut errors = Vec::new();
+        let config = Self::load(args, &mut errors);
+        let mut problems: Vec<String> = errors.iter().map(ToString::to_string).collect();
+
+        let mut names: Vec<&String> = config.services.keys().collect();
+        names.sort();
+        for name in names {
+            let service = &config.services[name];
+            let mut problem = |message: String| problems.push(format!("{}: {}", name, message));
+
+            if let Err(e) = service.validate() {
+                problem(e.to_string());
+            }
+            if service.git.repo.starts_with("TODO/") {
+                problem(format!("Placeholder git repository {}", service.git.repo));
+            }
+            if let Some(credentials) = &service.image.credentials {
+                if let Err(e) = credentials.resolve() {
+                    problem(e.to_string());
+                }
+            }
+            for variable in tag_template::variables(&service.image.tag) {
+                if !TAG_VARIABLES.contains(&variable) {
+                    problem(format!("Unknown variable ${{{}}} in image tag", variable));
+                }
+            }
+            if let Some(expression) = &service.schedule {
+                if let Err(e) = schedule::parse(expression) {
+                    problem(e.to_string());
+                }
+            }
+            for target in &service.targets {
+                if let Err(e) = target.validate() {
+                    problem(format!("Target {}: {}", target.file().display(), e));
+                }
+            }
+        }
+        (config.services.len(), problems)
     }
 }
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Only process this service and keep the others from the existing output (repeatable)
+    #[arg(long = "service", value_name = "NAME")]
+    pub services: Vec<String>,
+
+    /// Only process services matching a glob like 'web-*' (repeatable)
+    #[arg(long = "service-glob", value_name = "GLOB")]
+    pub service_globs: Vec<String>,
+
+    /// Only process services with one of these tags (comma separated)
+    #[arg(long, value_delimiter = ',', value_name = "TAGS")]
+    pub tags: Vec<String>,
+
+    /// Skip services with one of these tags (comma separated)
+    #[arg(long, value_delimiter = ',', value_name = "TAGS")]
+    pub exclude_tags: Vec<String>,
+
     /// Output format
     #[arg(short, long, value_enum, default_value_t = OutputFormat::Json)]
     pub format: OutputFormat,
 
-    /// Config file path
+    /// Config file, directory of config files, URL or - for stdin (repeatable)
     #[arg(short, long, default_value = "config.yaml")]
-    pub config: String,
+    pub config: Vec<String>,
+
+    /// Config file format, detected from the file extension by default
+    #[arg(long, value_enum)]
+    pub config_format: Option<ConfigFormat>,
+
+    /// Profile whose service overlays are merged over the config (repeatable, applied in order)
+    #[arg(long = "profile", value_name = "NAME")]
+    pub profiles: Vec<String>,
+
+    /// Fail on config keys that no setting matches instead of ignoring them
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Log more (-v for debug, -vv for trace), `RUST_LOG` takes precedence
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only log warnings and errors, `RUST_LOG` takes precedence
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Color log levels and the summary, `auto` unless `NO_COLOR` is set or stderr isn't a terminal
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto, global = true)]
+    pub color: ColorChoice,
 
     /// Output file path
     #[arg(short = 'o', long, required = true)]
-    pub output: String,
+    pub output: Option<String>,
+
+    /// Discover services from `version-updater.*` labels in a compose file (repeatable)
+    #[arg(long, value_name = "FILE")]
+    pub discover_compose: Vec<PathBuf>,
+
+    /// Discover services from `version-updater.*` labels of running workloads (repeatable)
+    #[arg(long, value_enum, value_name = "SOURCE")]
+    pub discover: Vec<DiscoverySource>,
+
+    /// Kubernetes namespace to discover services and Helm releases in (repeatable, default: all namespaces)
+    #[arg(long = "namespace", value_name = "NAMESPACE")]
+    pub namespaces: Vec<String>,
+
+    /// Keep running and check services every --interval or on their schedule
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// Time between checks in daemon mode, e.g. 30m or 6h
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "6h", requires = "daemon")]
+    pub interval: Duration,
+
+    /// Serve the results, a refresh endpoint and Prometheus metrics on this address
+    #[arg(long, value_name = "ADDR")]
+    pub serve: Option<SocketAddr>,
+
+    /// Only process the services that failed in this previous output and merge
+    /// the new results into it
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["daemon", "serve"])]
+    pub retry_failed: Option<PathBuf>,
+
+    /// Abort the run as soon as a service fails because of its configuration or
+    /// credentials, without writing the output
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Exit if another instance holds this lock file, e.g. one writing the same output
+    #[arg(long, value_name = "FILE")]
+    pub lock_file: Option<PathBuf>,
+
+    /// Wait for the --lock-file to be released instead of exiting
+    #[arg(long, requires = "lock_file")]
+    pub wait_for_lock: bool,
+
+    /// Append the versions services resolve to to this file when they change, for
+    /// the history subcommand
+    #[arg(long, value_name = "FILE")]
+    pub history_file: Option<PathBuf>,
+
+    /// Write the current time to this file while the daemon or server is alive
+    #[arg(long, value_name = "FILE")]
+    pub heartbeat_file: Option<PathBuf>,
+
+    /// Also write the logs to this file
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Start a new --log-file every hour or day
+    #[arg(long, value_enum, default_value_t = Rotation::Never, requires = "log_file")]
+    pub log_rotate: Rotation,
+
+    /// Start a new --log-file once it would exceed this size, e.g. 10M
+    #[arg(long, value_parser = logging::parse_size, value_name = "SIZE", requires = "log_file")]
+    pub log_max_size: Option<u64>,
+
+    /// Rotated log files to keep as <log-file>.1, <log-file>.2, ...
+    #[arg(long, default_value_t = 5, value_name = "N", requires = "log_file")]
+    pub log_keep: usize,
+
+    /// Export traces and metrics to this OTLP/HTTP collector, e.g. http://localhost:4318
+    #[arg(long, value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Don't read or write the response cache
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Send no requests and answer from the cache, however old, or else from
+    /// the previous output, marking such entries as stale. Notifications,
+    /// commits, pull requests and Portainer redeploys are skipped
+    #[arg(long, conflicts_with = "no_cache")]
+    pub offline: bool,
+
+    /// Add how long each service took and how many HTTP requests it sent to the output
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Also check that all blobs of each resolved image exist, not only its manifest
+    #[arg(long)]
+    pub deep_verify: bool,
+
+    /// Keep the previous output file as <output>.bak
+    #[arg(long)]
+    pub backup: bool,
+
+    /// Apply resolved tags to the update targets of each service
+    #[arg(long)]
+    pub update: bool,
+
+    /// Commit the updated files to the git repositories containing them (requires --update)
+    #[arg(long, requires = "update")]
+    pub commit: bool,
+
+    /// Commit the updates to a branch, push it and open a pull request (requires --update)
+    #[arg(long, requires = "update", conflicts_with = "commit")]
+    pub pull_request: bool,
+
+    /// Update the image of Docker Swarm services running outdated tags
+    #[arg(long)]
+    pub swarm: bool,
+
+    /// Redeploy the Portainer stacks of services with new tags
+    #[arg(long)]
+    pub portainer: bool,
+
+    /// Only report what --update, --swarm and --portainer would change
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+impl Args {
+    /// The output file path, which clap requires unless a subcommand is given.
+    pub fn output_path(&self) -> &str {
+        self.output.as_deref().unwrap_or_default()
+    }
+
+    /// The log level selected with `--verbose` and `--quiet`.
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::WARN,
+            (false, 0) => LevelFilter::INFO,
+            (false, 1) => LevelFilter::DEBUG,
+            (false, _) => LevelFilter::TRACE,
+        }
+    }
+
+    /// The log level of version-updater's own log lines, at least debug for
+    /// the explain subcommand.
+    pub fn own_log_level(&self) -> Option<LevelFilter> {
+        match self.command {
+            Some(Command::Explain { .. }) => Some(self.log_level().max(LevelFilter::DEBUG)),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Apply the versions of an existing output file (-o) to the update targets
+    Update {
+        /// Only print the diffs of the files that would change
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Apply the changes without asking for confirmation
+        #[arg(long, conflicts_with = "dry_run")]
+        yes: bool,
+    },
+    /// Resolve a single service given on the command line and print its output entry,
+    /// using the global settings of the config file if it exists
+    Check {
+        /// Repository of the release, e.g. nginx/nginx
+        #[arg(long)]
+        repo: Option<String>,
+
+        /// Git provider: github, gitlab, codeberg, none or a plugin
+        #[arg(long = "type", value_name = "PROVIDER", default_value = "github")]
+        git_type: String,
+
+        /// GitLab project ID
+        #[arg(long)]
+        project_id: Option<u64>,
+
+        /// Regex extracting the version from the release tag
+        #[arg(long, default_value = "(.*)")]
+        version_filter: String,
+
+        /// Authenticate to the git provider
+        #[arg(long)]
+        private: bool,
+
+        /// Image to validate the tag of, e.g. nginx or ghcr.io/owner/image
+        #[arg(long)]
+        image: Option<String>,
+
+        /// Image tag template
+        #[arg(long, default_value = "${RELEASE_VERSION}")]
+        tag: String,
+    },
+    /// Print a table of the configured services and their sources
+    List {
+        /// Only list services with one of these tags (comma separated)
+        #[arg(long, value_delimiter = ',', value_name = "TAGS")]
+        tags: Vec<String>,
+
+        /// Only list services of this git provider, e.g. github
+        #[arg(long)]
+        provider: Option<String>,
+
+        /// Only list services with images in this registry, e.g. ghcr.io
+        #[arg(long)]
+        registry: Option<String>,
+    },
+    /// List recent releases of a repository, to find the right version_filter
+    Releases {
+        /// Repository as <type>:<repo>, e.g. github:owner/repo or gitlab:<project id>
+        source: String,
+
+        /// Print at most this many releases, up to 100
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        limit: usize,
+
+        /// Also print the version this regex extracts from each tag
+        #[arg(long, value_name = "REGEX")]
+        version_filter: Option<String>,
+    },
+    /// List the tags of an image in its registry, highest version first, using
+    /// the registry credentials of the config file if it exists
+    Tags {
+        /// Image, e.g. nginx or ghcr.io/owner/image
+        image: String,
+
+        /// Print at most this many tags
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+
+        /// Only print tags matching this regex
+        #[arg(long, value_name = "REGEX")]
+        filter: Option<String>,
+    },
+    /// Show the versions a service resolved to over time, from the --history-file
+    History {
+        service: String,
+
+        /// Only show changes since a date (2024-05-01), time (RFC 3339) or duration ago (7d)
+        #[arg(long, value_parser = history::parse_since, value_name = "WHEN")]
+        since: Option<DateTime<Utc>>,
+    },
+    /// Resolve a single configured service without the cache, logging each step:
+    /// the API URLs called, the tag found, what the filter matched, the tag the
+    /// template gives and the manifest formats tried
+    Explain { service: String },
+    /// Check the config files without any network requests and report all problems
+    Validate,
+    /// Check the tokens, registry credentials and registries the configured services
+    /// need and report each as passed or failed
+    Doctor,
+    /// Run as Kubernetes operator, resolving the service in the spec of each
+    /// VersionCheck resource (in --namespace, default: all) into its status
+    Operator {
+        /// Time between checks of each resource, e.g. 30m or 6h
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "6h")]
+        interval: Duration,
+
+        /// Print the VersionCheck CustomResourceDefinition and exit
+        #[arg(long)]
+        crd: bool,
+    },
+    /// Log in to a git provider and store the token for later runs
+    Login {
+        #[command(subcommand)]
+        provider: LoginProvider,
+    },
+    /// Generate a config file from the config of another tool and print it
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LoginProvider {
+    /// Authorize with the GitHub device flow: enter the printed code on github.com
+    Github {
+        /// Client ID of the OAuth app to authorize, defaults to
+        /// $VERSION_UPDATER_GITHUB_CLIENT_ID
+        #[arg(long)]
+        client_id: Option<String>,
+
+        /// OAuth scopes to ask for, e.g. `repo` for private repositories
+        #[arg(long, default_value = "")]
+        scope: String,
+
+        /// Store the token in this file instead of the user's config directory
+        #[arg(long, value_name = "FILE")]
+        token_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ImportSource {
+    /// Import the docker and docker-compose ecosystems of a Dependabot config
+    Dependabot {
+        /// Path to the Dependabot config
+        #[arg(default_value = ".github/dependabot.yml")]
+        file: PathBuf,
+
+        /// Repository root the directories are relative to (default: two levels above the file)
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Import the images watched by the file provider of a Diun config
+    Diun {
+        /// Path to the Diun config or to a file provider images file
+        #[arg(default_value = "diun.yml")]
+        file: PathBuf,
+    },
+    /// Import the images of the services in a docker-compose file
+    Compose {
+        /// Path to the compose file
+        #[arg(default_value = "docker-compose.yml")]
+        file: PathBuf,
+    },
+    /// Import the images of the Deployments, StatefulSets and DaemonSets in a Kubernetes cluster
+    Kubernetes {
+        /// Namespace to import (repeatable, default: all namespaces)
+        #[arg(short, long)]
+        namespace: Vec<String>,
+    },
+}
+
+/// The output entry of a service.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(try_from = "StoredServiceVersion")]
 pub struct ServiceVersion {
+    /// Empty for services without image
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub image: String,
-    pub tag: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<String>,
+    #[serde(flatten)]
+    pub outcome: ResolutionOutcome,
+    /// How the image of a resolved service was checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<Verification>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub helm: Option<HelmRelease>,
+    /// Set with --timings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<Timing>,
+    /// Set with --offline if the entry was answered from expired data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale: Option<Stale>,
 }
 
 pub type OutputData = BTreeMap<String, ServiceVersion>;
 
 impl ServiceVersion {
-    pub fn error(image: String, error: &str) -> Self {
+    pub fn new(image: String, outcome: ResolutionOutcome) -> Self {
         Self {
             image,
-            tag: "<ERROR>".to_string(),
-            error: Some(error.to_string()),
+            outcome,
+            verified: None,
+            helm: None,
+            timing: None,
+            stale: None,
         }
     }
+
+    pub fn failed(image: String, error: &str) -> Self {
+        Self::new(
+            image,
+            ResolutionOutcome::Error {
+                error: error.to_string(),
+            },
+        )
+    }
+
+    pub fn with_verified(mut self, verified: Option<Verification>) -> Self {
+        self.verified = verified;
+        self
+    }
+
+    pub fn with_helm(mut self, helm: Option<HelmRelease>) -> Self {
+        self.helm = helm;
+        self
+    }
+
+    pub fn with_timing(mut self, timing: Option<Timing>) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn with_stale(mut self, stale: Option<Stale>) -> Self {
+        self.stale = stale;
+        self
+    }
+
+    /// The tag to deploy, if the service resolved.
+    pub fn tag(&self) -> Option<&str> {
+        match &self.outcome {
+            ResolutionOutcome::Resolved { tag, .. } => Some(tag),
+            _ => None,
+        }
+    }
+
+    /// The digest of the resolved tag, if the registry reported one.
+    pub fn digest(&self) -> Option<&str> {
+        match &self.outcome {
+            ResolutionOutcome::Resolved { digest, .. } => digest.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The latest upstream version, if it is known.
+    pub fn version(&self) -> Option<&str> {
+        match &self.outcome {
+            ResolutionOutcome::Resolved { version, .. }
+            | ResolutionOutcome::TagMissing { version, .. } => Some(version),
+            _ => None,
+        }
+    }
+
+    /// Why the service did not resolve, if it didn't.
+    pub fn error(&self) -> Option<String> {
+        match &self.outcome {
+            ResolutionOutcome::Resolved { .. } => None,
+            ResolutionOutcome::TagMissing { tag, .. } => Some(format!(
+                "Image {}:{} does not exist in the registry",
+                self.image, tag
+            )),
+            ResolutionOutcome::RateLimited { source } => Some(format!("Rate limited: {}", source)),
+            ResolutionOutcome::Error { error } => Some(error.clone()),
+        }
+    }
+}
+
+/// An output entry as read back from a previous run. Outputs written before
+/// `status` was added mark failures with placeholder tags instead.
+#[derive(Deserialize)]
+struct StoredServiceVersion {
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    verified: Option<Verification>,
+    #[serde(default)]
+    helm: Option<HelmRelease>,
+    #[serde(default)]
+    timing: Option<Timing>,
+    #[serde(default)]
+    stale: Option<Stale>,
+    #[serde(flatten)]
+    fields: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ServiceInfo {
-    pub container_image: String,
-    pub image_tag: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<String>,
+impl TryFrom<StoredServiceVersion> for ServiceVersion {
+    type Error = String;
+
+    fn try_from(stored: StoredServiceVersion) -> Result<Self, String> {
+        let mut fields = stored.fields;
+        let outcome = if fields.contains_key("status") {
+            serde_json::from_value(serde_json::Value::Object(fields)).map_err(|e| e.to_string())?
+        } else {
+            let mut field = |name: &str| match fields.remove(name) {
+                Some(serde_json::Value::String(value)) => Some(value),
+                _ => None,
+            };
+            let (tag, version, error) = (field("tag"), field("version"), field("error"));
+            match (tag.as_deref(), error) {
+                (Some("<RATE_LIMITED>"), error) => ResolutionOutcome::RateLimited {
+                    source: error.unwrap_or_default(),
+                },
+                (_, Some(error)) => ResolutionOutcome::Error { error },
+                (Some("<NOT_FOUND>"), None) => ResolutionOutcome::TagMissing {
+                    version: version.unwrap_or_default(),
+                    tag: String::new(),
+                    candidates: Vec::new(),
+                },
+                (Some(tag), None) => ResolutionOutcome::Resolved {
+                    version: version.unwrap_or_else(|| tag.to_string()),
+                    tag: tag.to_string(),
+                    digest: None,
+                },
+                (None, None) => return Err("missing field `status`".to_string()),
+            }
+        };
+        Ok(Self {
+            image: stored.image,
+            outcome,
+            verified: stored.verified,
+            helm: stored.helm,
+            timing: stored.timing,
+            stale: stored.stale,
+        })
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
@@ -122,31 +1051,432 @@ pub enum OutputFormat {
     Yaml,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum DiscoverySource {
+    /// Containers of the Docker daemon at `DOCKER_HOST` or the local socket
+    Docker,
+    /// Deployments, StatefulSets and DaemonSets of the current kubeconfig context or the cluster it runs in
+    Kubernetes,
+    /// Deployed Helm releases of the current kubeconfig context or the cluster it runs in
+    Helm,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Detects the format from the file extension, defaulting to YAML.
+    fn detect(file: &Path) -> Self {
+        match file.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => ConfigFormat::Json,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Config {
     pub global: GlobalConfig,
     pub services: HashMap<String, ServiceConfig>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A single config file. Services can be split across files, `global` may
+/// only be set in one of them.
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    global: Option<GlobalConfig>,
+    /// Partial service definitions that services can start from, see `matrix`
+    #[serde(default)]
+    templates: HashMap<String, serde_yaml::Value>,
+    /// Kept raw until templates from all files are known
+    #[serde(default)]
+    services: HashMap<String, serde_yaml::Value>,
+    /// Overlays merged over the services with --profile
+    #[serde(default)]
+    profiles: HashMap<String, ProfileFile>,
+    /// The parsed text, for locating unknown keys
+    #[serde(skip)]
+    content: String,
+    /// Keys outside of `templates` and `services` that no setting matched
+    #[serde(skip)]
+    unknown: Vec<Vec<Segment>>,
+}
+
+/// A profile, e.g. `prod` or `staging`. Its services are merged over the
+/// service entries of the same name, or added if there is none.
+#[derive(Deserialize)]
+struct ProfileFile {
+    #[serde(default)]
+    services: HashMap<String, serde_yaml::Value>,
+}
+
+/// Where a config file is read from.
+#[derive(Clone)]
+enum ConfigSource {
+    File(PathBuf),
+    /// Fetched over HTTPS, with `CONFIG_TOKEN` as bearer token if set
+    Url(String),
+    /// `-`, read once and reused on reloads
+    Stdin,
+}
+
+impl ConfigSource {
+    fn read(&self, http: &HttpConfig) -> Result<String, AppError> {
+        match self {
+            ConfigSource::File(path) => Ok(fs::read_to_string(path)?),
+            ConfigSource::Url(url) => fetch(url, http),
+            ConfigSource::Stdin => {
+                static STDIN: OnceLock<String> = OnceLock::new();
+                if let Some(content) = STDIN.get() {
+                    return Ok(content.clone());
+                }
+                let content = io::read_to_string(io::stdin())?;
+                Ok(STDIN.get_or_init(|| content).clone())
+            }
+        }
+    }
+
+    /// The path used to detect the format from its extension.
+    fn path(&self) -> &Path {
+        match self {
+            ConfigSource::File(path) => path,
+            ConfigSource::Url(url) => Path::new(url.split(['?', '#']).next().unwrap_or(url)),
+            ConfigSource::Stdin => Path::new(""),
+        }
+    }
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::File(path) => write!(f, "{}", path.display()),
+            ConfigSource::Url(url) => write!(f, "{}", url),
+            ConfigSource::Stdin => write!(f, "<stdin>"),
+        }
+    }
+}
+
+fn is_url(config: &str) -> bool {
+    config.starts_with("https://") || config.starts_with("http://")
+}
+
+/// Downloads a remote config file over HTTPS, as it may define hooks and
+/// plugins, with the HTTP settings of the config files read before it.
+fn fetch(url: &str, http: &HttpConfig) -> Result<String, AppError> {
+    if !url.starts_with("https://") {
+        return Err(AppError::ConfigError(format!(
+            "{}: Remote configs must be fetched over https://",
+            url
+        )));
+    }
+    let fetch_error = |e: reqwest::Error| AppError::ConfigError(format!("{}: {}", url, e));
+    let client = http::client(http)?;
+    let mut request = client.get(url).header(USER_AGENT, USER_AGENT_NAME);
+    if let Ok(token) = std::env::var("CONFIG_TOKEN") {
+        request = request.bearer_auth(token);
+    }
+    block_on(async {
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(fetch_error)?
+            .text()
+            .await
+            .map_err(fetch_error)
+    })
+}
+
+/// Runs `future` from the synchronous config loading, e.g. to fetch a remote
+/// config or discover services, on a thread with a runtime of its own, so it
+/// works within any runtime or none.
+pub(crate) fn block_on<F>(future: F) -> F::Output
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    std::thread::scope(|scope| {
+        let thread = scope.spawn(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("a runtime for config loading can be created")
+                .block_on(future)
+        });
+        thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Reports a key that no setting matched, with its position in `content` if it
+/// can be found. Unknown keys are problems in strict mode and warnings otherwise.
+fn unknown_key(
+    strict: bool,
+    problems: &mut Vec<AppError>,
+    source: &ConfigSource,
+    content: &str,
+    path: &[Segment],
+) {
+    let key = display_path(path);
+    let key = key.trim_start_matches('.');
+    let message = match key_position(content, path) {
+        Some((line, column)) => format!("{}:{}:{}: Unknown key {}", source, line, column, key),
+        None => format!("{}: Unknown key {}", source, key),
+    };
+    if strict {
+        problems.push(AppError::ConfigError(message));
+    } else {
+        warn!("{}, ignoring it", message);
+    }
+}
+
+/// Converts the path of an ignored key into segments. Paths relative to a
+/// service start at the service.
+fn key_path(path: &serde_ignored::Path) -> Vec<Segment> {
+    let mut segments = match path {
+        serde_ignored::Path::Root => return Vec::new(),
+        serde_ignored::Path::Seq { parent, .. }
+        | serde_ignored::Path::Map { parent, .. }
+        | serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => key_path(parent),
+    };
+    match path {
+        serde_ignored::Path::Seq { index, .. } => segments.push(Segment::Index(*index)),
+        serde_ignored::Path::Map { key, .. } => segments.push(Segment::Key(key.clone())),
+        _ => {}
+    }
+    segments
+}
+
+/// Expands the --config arguments into the sources to read, in order. Files in
+/// directories are read in alphabetical order. Missing files are skipped when
+/// services are discovered from labels.
+fn config_sources(args: &Args) -> Result<Vec<ConfigSource>, AppError> {
+    let mut sources = Vec::new();
+    for config in &args.config {
+        if config == "-" {
+            sources.push(ConfigSource::Stdin);
+            continue;
+        }
+        if is_url(config) {
+            sources.push(ConfigSource::Url(config.clone()));
+            continue;
+        }
+        let path = PathBuf::from(config);
+        if path.is_dir() {
+            let mut entries = fs::read_dir(&path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            entries.retain(|entry| {
+                entry.is_file()
+                    && entry.extension().is_some_and(|extension| {
+                        ["yaml", "yml", "json", "toml"]
+                            .iter()
+                            .any(|known| extension == *known)
+                    })
+            });
+            entries.sort();
+            sources.extend(entries.into_iter().map(ConfigSource::File));
+        } else if (args.discover_compose.is_empty() && args.discover.is_empty()) || path.exists() {
+            sources.push(ConfigSource::File(path));
+        }
+    }
+    Ok(sources)
+}
+
+/// Fills in the settings `service` takes from the global config.
+pub(crate) fn apply_global(global: &GlobalConfig, service: &mut ServiceConfig) {
+    service.git = <GitConfig as Clone>::clone(&service.git)
+        .with_global_github_auth(global.git.github.authenticate)
+        .with_gh_cli(global.git.github.gh_cli);
+    let host_token = match service.git.git_type {
+        Provider::Gitlab => global.git.gitlab.hosts.get(service.git.gitlab_host()),
+        _ => None,
+    };
+    if service.git.token_file.is_none() && service.git.token_env.is_none() {
+        match host_token {
+            Some(host_token) => {
+                service.git.token_file = host_token.token_file.clone();
+                service.git.token_env = host_token.token_env.clone();
+            }
+            None => service.git.token_file = global.git.token_file(&service.git.git_type).cloned(),
+        }
+    }
+    if service.image.credentials.is_none() && !service.image.is_none() {
+        service.image.credentials =
+            registry::credentials_for(&global.registries, &service.image.name);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct GlobalConfig {
     pub git: GlobalGitConfig,
+    /// Timeouts and retries for git provider and registry requests
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// On-disk cache of git provider and registry responses, see --no-cache
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Registry credentials by host, instead of ~/.docker/config.json
+    #[serde(default)]
+    pub registries: BTreeMap<String, RegistryCredentials>,
+    /// Settings for commits created with --commit
+    #[serde(default)]
+    pub commit: CommitConfig,
+    /// Settings for pull requests opened with --pull-request
+    #[serde(default)]
+    pub pull_request: Option<PullRequestConfig>,
+    /// Swarm manager used with --swarm
+    #[serde(default)]
+    pub swarm: SwarmConfig,
+    /// Portainer instance used with --portainer
+    #[serde(default)]
+    pub portainer: Option<PortainerConfig>,
+    /// Where changed and failing services are reported
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    /// Plugins usable as `git.type`, by name
+    #[serde(default)]
+    pub plugins: BTreeMap<String, PluginConfig>,
+    /// Secrets of the release webhooks received with --serve
+    #[serde(default)]
+    pub release_webhooks: ReleaseWebhookConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct GlobalGitConfig {
     pub github: GlobalGithubConfig,
+    #[serde(default)]
+    pub gitlab: GlobalGitlabConfig,
+    #[serde(default)]
+    pub codeberg: GlobalProviderConfig,
+    #[serde(default)]
+    pub azure_devops: GlobalProviderConfig,
+    #[serde(default)]
+    pub bitbucket_server: GlobalProviderConfig,
+    #[serde(default)]
+    pub gogs: GlobalProviderConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl GlobalGitConfig {
+    /// The token file configured for `provider`, if any.
+    fn token_file(&self, provider: &Provider) -> Option<&PathBuf> {
+        match provider {
+            Provider::Github => self.github.token_file.as_ref(),
+            Provider::Gitlab => self.gitlab.token_file.as_ref(),
+            Provider::Codeberg => self.codeberg.token_file.as_ref(),
+            Provider::AzureDevops => self.azure_devops.token_file.as_ref(),
+            Provider::BitbucketServer => self.bitbucket_server.token_file.as_ref(),
+            Provider::Gogs => self.gogs.token_file.as_ref(),
+            Provider::None | Provider::Custom(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 /// Set this to true and provide a GITHUB_TOKEN env variable
 /// to make authenticated GitHub API requests to avoid rate limiting (higher amount of API requests are allowed)
 pub struct GlobalGithubConfig {
     pub authenticate: bool,
+    /// File containing the token, instead of GITHUB_TOKEN
+    #[serde(default)]
+    pub token_file: Option<PathBuf>,
+    /// Use the token of the gh CLI if no other token is set
+    #[serde(default)]
+    pub gh_cli: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GlobalProviderConfig {
+    /// File containing the token, instead of the token variable of the provider
+    #[serde(default)]
+    pub token_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GlobalGitlabConfig {
+    /// File containing the token, instead of GITLAB_TOKEN
+    #[serde(default)]
+    pub token_file: Option<PathBuf>,
+    /// The tokens of GitLab instances by host, for services with a `host`
+    #[serde(default)]
+    pub hosts: BTreeMap<String, HostToken>,
+}
+
+/// Where the token of a GitLab instance is read from.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HostToken {
+    /// Name of the environment variable holding the token
+    #[serde(default)]
+    pub token_env: Option<String>,
+    #[serde(default)]
+    pub token_file: Option<PathBuf>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ServiceConfig {
+    /// Defaults to `type: none`, resolving the version from the registry tags
+    #[serde(default = "GitConfig::none")]
     pub git: GitConfig,
+    /// `none` (or no image) for services that only report the upstream version
+    #[serde(
+        default = "ImageConfig::none",
+        deserialize_with = "registry::deserialize_image"
+    )]
     pub image: ImageConfig,
+    /// Files to rewrite with the resolved tag when running with --update
+    #[serde(default)]
+    pub targets: Vec<UpdateTarget>,
+    /// Docker Swarm services to update with --swarm. Defaults to all swarm
+    /// services running the service image
+    #[serde(default)]
+    pub swarm: Vec<String>,
+    /// Portainer stack to redeploy with --portainer
+    #[serde(default)]
+    pub portainer: Option<PortainerTarget>,
+    /// Cron expression for checks in daemon mode, defaults to every --interval
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// Free-form tags for selecting services with --tags and --exclude-tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The Helm release deploying the image, set by `--discover helm`
+    #[serde(skip)]
+    pub helm: Option<HelmRelease>,
+}
+
+impl ServiceConfig {
+    /// A service without targets, schedule or tags, for services built in code.
+    pub fn new(git: GitConfig, image: ImageConfig) -> Self {
+        Self {
+            git,
+            image,
+            targets: Vec::new(),
+            swarm: Vec::new(),
+            portainer: None,
+            schedule: None,
+            tags: Vec::new(),
+            helm: None,
+        }
+    }
+
+    /// Checks the git source, and that the service has a git source or an
+    /// image whose tags the version can be resolved from.
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.git.git_type == Provider::None && self.image.is_none() {
+            return Err(AppError::ConfigError(
+                "A service needs a git source, an image or both".to_string(),
+            ));
+        }
+        self.image.validate()?;
+        self.git.validate()
+    }
 }
@@ -0,0 +1,77 @@
+use super::{discovery_error, name_from_labels, service_from_labels};
+use crate::config::ServiceConfig;
+use crate::error::AppError;
+use crate::updater::UpdateTarget;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use tracing::{debug, info};
+
+/// Builds services from the labels of the services in a compose file. Each
+/// discovered service gets the compose file as update target.
+///
+/// Compose interpolates `$` in labels, so templates are written as
+/// `$${RELEASE_VERSION}` there; the escaped form is accepted as well.
+pub fn discover(file: &Path) -> Result<HashMap<String, ServiceConfig>, AppError> {
+    info!("Discovering services in {}", file.display());
+    let compose: Value = serde_yaml::from_str(&fs::read_to_string(file)?)?;
+    let source = file.display().to_string();
+
+    let mut services = HashMap::new();
+    let Some(compose_services) = compose.get("services").and_then(Value::as_mapping) else {
+        return Ok(services);
+    };
+    for (name, service) in compose_services {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        let labels = labels(service);
+        let image = service.get("image").and_then(Value::as_str);
+        let Some(mut config) =
+            service_from_labels(&labels, image).map_err(|e| discovery_error(&source, name, e))?
+        else {
+            debug!("Compose service {} has not opted in", name);
+            continue;
+        };
+        config.targets.push(UpdateTarget::Compose {
+            file: file.to_path_buf(),
+        });
+        let name = name_from_labels(&labels).unwrap_or(name).to_string();
+        info!("Discovered service {} in {}", name, source);
+        services.insert(name, config);
+    }
+    Ok(services)
+}
+
+/// Reads the labels of a compose service, given either as mapping or as `key=value` list.
+fn labels(service: &Value) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::new();
+    match service.get("labels") {
+        Some(Value::Mapping(mapping)) => {
+            for (key, value) in mapping {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    Value::Bool(b) => b.to_string(),
+                    Value::Number(n) => n.to_string(),
+                    _ => continue,
+                };
+                if let Some(key) = key.as_str() {
+                    labels.insert(key.to_string(), unescape(&value));
+                }
+            }
+        }
+        Some(Value::Sequence(items)) => {
+            for item in items.iter().filter_map(Value::as_str) {
+                let (key, value) = item.split_once('=').unwrap_or((item, ""));
+                labels.insert(key.to_string(), unescape(value));
+            }
+        }
+        _ => {}
+    }
+    labels
+}
+
+fn unescape(value: &str) -> String {
+    value.replace("$$", "$")
+}
@@ -0,0 +1,77 @@
+use super::{block_on, discovery_error, name_from_labels, service_from_labels};
+use crate::config::ServiceConfig;
+use crate::error::AppError;
+use crate::updater::split_image_ref;
+use bollard::container::ListContainersOptions;
+use bollard::Docker;
+use std::collections::{BTreeMap, HashMap};
+use tracing::{debug, info, warn};
+
+/// Builds services from the labels of the running containers of the Docker
+/// daemon at `DOCKER_HOST` or the local socket. Images that `existing`
+/// services already track are skipped.
+pub fn discover(
+    existing: &HashMap<String, ServiceConfig>,
+) -> Result<HashMap<String, ServiceConfig>, AppError> {
+    info!("Discovering services from running containers");
+    let options = ListContainersOptions {
+        filters: HashMap::from([("label", vec!["version-updater.git"])]),
+        ..Default::default()
+    };
+    // The client belongs to the runtime of the discovery thread
+    let containers = block_on(async {
+        let docker = Docker::connect_with_defaults()?;
+        docker.list_containers(Some(options)).await
+    })
+    .map_err(docker_error)?;
+
+    let mut services: HashMap<String, ServiceConfig> = HashMap::new();
+    for container in containers {
+        let labels: BTreeMap<String, String> =
+            container.labels.unwrap_or_default().into_iter().collect();
+        let container_name = container
+            .names
+            .and_then(|names| names.into_iter().next())
+            .map(|name| name.trim_start_matches('/').to_string())
+            .or(container.id)
+            .unwrap_or_default();
+        let Some(config) = service_from_labels(&labels, container.image.as_deref())
+            .map_err(|e| discovery_error("docker", &container_name, e))?
+        else {
+            continue;
+        };
+
+        let image = split_image_ref(&config.image.name).0;
+        let tracked = existing
+            .values()
+            .chain(services.values())
+            .any(|service| split_image_ref(&service.image.name).0 == image);
+        if tracked {
+            debug!(
+                "Image {} of container {} is already tracked",
+                image, container_name
+            );
+            continue;
+        }
+        let name = name_from_labels(&labels)
+            .unwrap_or(&container_name)
+            .to_string();
+        if existing.contains_key(&name) || services.contains_key(&name) {
+            warn!(
+                "Ignoring container {}, service '{}' is already defined",
+                container_name, name
+            );
+            continue;
+        }
+        info!(
+            "Discovered service {} from container {}",
+            name, container_name
+        );
+        services.insert(name, config);
+    }
+    Ok(services)
+}
+
+fn docker_error(e: bollard::errors::Error) -> AppError {
+    AppError::DiscoveryError(format!("Docker: {}", e))
+}
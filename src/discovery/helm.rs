@@ -0,0 +1,245 @@
+use super::kubernetes::{client, kube_error};
+use super::{block_on, discovery_error, name_from_labels, service_from_labels, HelmRelease};
+use crate::config::ServiceConfig;
+use crate::error::AppError;
+use crate::import::tag_template;
+use crate::updater::split_image_ref;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use k8s_openapi::api::core::v1::Secret;
+use kube::api::ListParams;
+use kube::Api;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use tracing::{debug, info, warn};
+
+/// A release as stored by Helm 3 in a `helm.sh/release.v1` secret, reduced to
+/// the fields needed here.
+#[derive(Deserialize)]
+struct StoredRelease {
+    name: String,
+    namespace: String,
+    version: u32,
+    chart: StoredChart,
+    #[serde(default)]
+    manifest: String,
+}
+
+#[derive(Deserialize)]
+struct StoredChart {
+    metadata: ChartMetadata,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChartMetadata {
+    name: String,
+    version: String,
+    #[serde(default)]
+    app_version: Option<String>,
+    #[serde(default)]
+    home: Option<String>,
+    #[serde(default)]
+    sources: Vec<String>,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+}
+
+/// Maps the deployed Helm releases in `namespaces` (all if empty) to services.
+///
+/// Releases deploying an image that a service in `services` already tracks are
+/// attached to that service. Other releases become new services named after
+/// the release, using the `version-updater.*` annotations of the chart or the
+/// first source repository of the chart that isn't a chart repository itself.
+pub fn discover(
+    services: &mut HashMap<String, ServiceConfig>,
+    namespaces: &[String],
+) -> Result<(), AppError> {
+    info!("Discovering services from Helm releases");
+    for release in releases(namespaces)? {
+        let id = format!("{}/{}", release.namespace, release.name);
+        let metadata = &release.chart.metadata;
+        let images = manifest_images(&release.manifest);
+        let info = |image: Option<&str>| HelmRelease {
+            release: release.name.clone(),
+            namespace: release.namespace.clone(),
+            revision: release.version,
+            chart: metadata.name.clone(),
+            chart_version: metadata.version.clone(),
+            app_version: metadata.app_version.clone(),
+            deployed_tag: image.and_then(|image| split_image_ref(image).1.map(str::to_string)),
+        };
+
+        let tracked = services.iter_mut().find_map(|(name, service)| {
+            let tracked = split_image_ref(&service.image.name).0;
+            images
+                .iter()
+                .find(|image| split_image_ref(image).0 == tracked)
+                .map(|image| (name, service, image))
+        });
+        if let Some((name, service, image)) = tracked {
+            debug!("Release {} deploys image {} of service {}", id, image, name);
+            service.helm = Some(info(Some(image)));
+            continue;
+        }
+
+        let image = main_image(&images, metadata.app_version.as_deref());
+        let config = match service_from_labels(&metadata.annotations, image) {
+            Ok(Some(config)) => Some(config),
+            Ok(None) => guess_service(metadata, image),
+            Err(e) => return Err(discovery_error("helm", &id, e)),
+        };
+        let Some(mut config) = config else {
+            debug!("Skipping release {}, no source repository found", id);
+            continue;
+        };
+        let name = name_from_labels(&metadata.annotations)
+            .unwrap_or(&release.name)
+            .to_string();
+        if services.contains_key(&name) {
+            warn!(
+                "Ignoring release {}, service '{}' is already defined",
+                id, name
+            );
+            continue;
+        }
+        info!(
+            "Discovered service {} from release {} (chart {}-{})",
+            name, id, metadata.name, metadata.version
+        );
+        config.helm = Some(info(image));
+        services.insert(name, config);
+    }
+    Ok(())
+}
+
+/// Reads the latest deployed revision of every release from the Helm secrets.
+fn releases(namespaces: &[String]) -> Result<Vec<StoredRelease>, AppError> {
+    let secrets = block_on(async {
+        let client = client().await?;
+        let params = ListParams::default().labels("owner=helm,status=deployed");
+        let mut secrets = Vec::new();
+        if namespaces.is_empty() {
+            let api: Api<Secret> = Api::all(client.clone());
+            secrets.extend(api.list(&params).await.map_err(kube_error)?.items);
+        }
+        for namespace in namespaces {
+            let api: Api<Secret> = Api::namespaced(client.clone(), namespace);
+            secrets.extend(api.list(&params).await.map_err(kube_error)?.items);
+        }
+        Ok::<_, AppError>(secrets)
+    })?;
+
+    let mut latest: BTreeMap<(String, String), StoredRelease> = BTreeMap::new();
+    for secret in secrets {
+        let secret_name = secret.metadata.name.unwrap_or_default();
+        let Some(data) = secret.data.and_then(|mut data| data.remove("release")) else {
+            continue;
+        };
+        let release =
+            decode_release(&data.0).map_err(|e| discovery_error("helm", &secret_name, e))?;
+        let key = (release.namespace.clone(), release.name.clone());
+        if latest
+            .get(&key)
+            .is_none_or(|current| current.version < release.version)
+        {
+            latest.insert(key, release);
+        }
+    }
+    Ok(latest.into_values().collect())
+}
+
+/// Decodes the base64 encoded, gzipped JSON Helm stores releases as.
+fn decode_release(data: &[u8]) -> Result<StoredRelease, String> {
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| e.to_string())?;
+    let json = if decoded.starts_with(&[0x1f, 0x8b]) {
+        let mut json = Vec::new();
+        GzDecoder::new(decoded.as_slice())
+            .read_to_end(&mut json)
+            .map_err(|e| e.to_string())?;
+        json
+    } else {
+        decoded
+    };
+    serde_json::from_slice(&json).map_err(|e| e.to_string())
+}
+
+/// Collects the `image` values of all resources in a rendered manifest.
+fn manifest_images(manifest: &str) -> Vec<String> {
+    fn collect(value: &Value, images: &mut Vec<String>) {
+        match value {
+            Value::Mapping(mapping) => {
+                for (key, value) in mapping {
+                    match (key.as_str(), value) {
+                        (Some("image"), Value::String(image)) => {
+                            if !images.contains(image) {
+                                images.push(image.clone());
+                            }
+                        }
+                        _ => collect(value, images),
+                    }
+                }
+            }
+            Value::Sequence(values) => values.iter().for_each(|value| collect(value, images)),
+            _ => {}
+        }
+    }
+
+    let mut images = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(manifest) {
+        if let Ok(value) = Value::deserialize(document) {
+            collect(&value, &mut images);
+        }
+    }
+    images
+}
+
+/// The image whose tag contains the app version of the chart, or the first one.
+fn main_image<'a>(images: &'a [String], app_version: Option<&str>) -> Option<&'a str> {
+    let app_version = app_version.map(|version| version.trim_start_matches('v'));
+    images
+        .iter()
+        .find(|image| {
+            let tag = split_image_ref(image).1.unwrap_or_default();
+            app_version.is_some_and(|version| !version.is_empty() && tag.contains(version))
+        })
+        .or(images.first())
+        .map(String::as_str)
+}
+
+/// Builds a service from the first GitHub or Codeberg repository in the chart
+/// sources (or its home page) that isn't a chart repository.
+fn guess_service(metadata: &ChartMetadata, image: Option<&str>) -> Option<ServiceConfig> {
+    let image = image?;
+    let (git_type, repo) = metadata
+        .sources
+        .iter()
+        .chain(metadata.home.iter())
+        .find_map(|url| {
+            let url = url.trim_end_matches('/').trim_end_matches(".git");
+            let (git_type, path) = if let Some(path) = url.strip_prefix("https://github.com/") {
+                ("github", path)
+            } else {
+                ("codeberg", url.strip_prefix("https://codeberg.org/")?)
+            };
+            let mut parts = path.split('/');
+            let (owner, name) = (parts.next()?, parts.next()?);
+            (!name.contains("chart")).then(|| (git_type, format!("{}/{}", owner, name)))
+        })?;
+
+    let (name, tag) = split_image_ref(image);
+    let mut git = Mapping::new();
+    git.insert("type".into(), git_type.into());
+    git.insert("repo".into(), repo.into());
+    let mut image_config = Mapping::new();
+    image_config.insert("name".into(), name.into());
+    image_config.insert("tag".into(), tag_template(tag.unwrap_or("latest")).into());
+    let mut service = Mapping::new();
+    service.insert("git".into(), Value::Mapping(git));
+    service.insert("image".into(), Value::Mapping(image_config));
+    serde_yaml::from_value(Value::Mapping(service)).ok()
+}
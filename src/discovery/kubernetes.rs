@@ -0,0 +1,152 @@
+use super::{block_on, discovery_error, name_from_labels, service_from_labels};
+use crate::config::ServiceConfig;
+use crate::error::AppError;
+use crate::updater::split_image_ref;
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use k8s_openapi::api::core::v1::PodTemplateSpec;
+use k8s_openapi::NamespaceResourceScope;
+use kube::api::ListParams;
+use kube::{Api, Client, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use tracing::{debug, info, warn};
+
+/// A Deployment, StatefulSet or DaemonSet with the images of its containers.
+pub struct Workload {
+    pub kind: &'static str,
+    pub namespace: String,
+    pub name: String,
+    /// Annotations of the workload, merged over those of its pod template
+    pub annotations: BTreeMap<String, String>,
+    /// Container names and images, init containers excluded
+    pub containers: Vec<(String, String)>,
+}
+
+impl Workload {
+    fn id(&self) -> String {
+        format!("{}/{}/{}", self.namespace, self.kind, self.name)
+    }
+}
+
+/// Lists the workloads in `namespaces`, or in all namespaces if none are given,
+/// using the kubeconfig or the in-cluster service account.
+pub fn workloads(namespaces: &[String]) -> Result<Vec<Workload>, AppError> {
+    block_on(async {
+        let client = client().await?;
+        let mut workloads = Vec::new();
+        let scopes: Vec<Option<&str>> = if namespaces.is_empty() {
+            vec![None]
+        } else {
+            namespaces.iter().map(|ns| Some(ns.as_str())).collect()
+        };
+        for namespace in scopes {
+            list::<Deployment>(&client, namespace, "Deployment", &mut workloads, |d| {
+                d.spec.as_ref().map(|spec| &spec.template)
+            })
+            .await?;
+            list::<StatefulSet>(&client, namespace, "StatefulSet", &mut workloads, |s| {
+                s.spec.as_ref().map(|spec| &spec.template)
+            })
+            .await?;
+            list::<DaemonSet>(&client, namespace, "DaemonSet", &mut workloads, |d| {
+                d.spec.as_ref().map(|spec| &spec.template)
+            })
+            .await?;
+        }
+        Ok(workloads)
+    })
+}
+
+/// Builds services from the `version-updater.*` annotations of the workloads
+/// in `namespaces`. Images that `existing` services already track are skipped.
+pub fn discover(
+    existing: &HashMap<String, ServiceConfig>,
+    namespaces: &[String],
+) -> Result<HashMap<String, ServiceConfig>, AppError> {
+    info!("Discovering services from Kubernetes workloads");
+    let mut services: HashMap<String, ServiceConfig> = HashMap::new();
+    for workload in workloads(namespaces)? {
+        let id = workload.id();
+        let image = workload.containers.first().map(|(_, image)| image.as_str());
+        let Some(config) = service_from_labels(&workload.annotations, image)
+            .map_err(|e| discovery_error("kubernetes", &id, e))?
+        else {
+            continue;
+        };
+
+        let image = split_image_ref(&config.image.name).0;
+        let tracked = existing
+            .values()
+            .chain(services.values())
+            .any(|service| split_image_ref(&service.image.name).0 == image);
+        if tracked {
+            debug!("Image {} of {} is already tracked", image, id);
+            continue;
+        }
+        let name = name_from_labels(&workload.annotations)
+            .unwrap_or(&workload.name)
+            .to_string();
+        if existing.contains_key(&name) || services.contains_key(&name) {
+            warn!("Ignoring {}, service '{}' is already defined", id, name);
+            continue;
+        }
+        info!("Discovered service {} from {}", name, id);
+        services.insert(name, config);
+    }
+    Ok(services)
+}
+
+pub(super) async fn client() -> Result<Client, AppError> {
+    Client::try_default().await.map_err(kube_error)
+}
+
+async fn list<K>(
+    client: &Client,
+    namespace: Option<&str>,
+    kind: &'static str,
+    workloads: &mut Vec<Workload>,
+    template: impl Fn(&K) -> Option<&PodTemplateSpec>,
+) -> Result<(), AppError>
+where
+    K: Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Debug,
+    K::DynamicType: Default,
+{
+    let api: Api<K> = match namespace {
+        Some(namespace) => Api::namespaced(client.clone(), namespace),
+        None => Api::all(client.clone()),
+    };
+    let resources = api.list(&ListParams::default()).await.map_err(kube_error)?;
+    debug!("Found {} {}s", resources.items.len(), kind);
+    for resource in resources.items {
+        let template = template(&resource);
+        let mut annotations = template
+            .and_then(|template| template.metadata.as_ref())
+            .and_then(|metadata| metadata.annotations.clone())
+            .unwrap_or_default();
+        annotations.extend(resource.annotations().clone());
+        let containers = template
+            .and_then(|template| template.spec.as_ref())
+            .map(|spec| {
+                spec.containers
+                    .iter()
+                    .filter_map(|container| {
+                        Some((container.name.clone(), container.image.clone()?))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        workloads.push(Workload {
+            kind,
+            namespace: resource.namespace().unwrap_or_default(),
+            name: resource.name_any(),
+            annotations,
+            containers,
+        });
+    }
+    Ok(())
+}
+
+pub(super) fn kube_error(e: kube::Error) -> AppError {
+    AppError::DiscoveryError(format!("Kubernetes: {}", e))
+}
@@ -0,0 +1,107 @@
+//! Builds service definitions from labels instead of the config file.
+//!
+//! A service opts in with a `version-updater.git` label (an annotation on Kubernetes):
+//!
+//! - `version-updater.git`: `<type>:<repo>`, e.g. `github:owner/repo` or `gitlab:12345`
+//! - `version-updater.tag`: tag template, defaults to `${RELEASE_VERSION}`
+//! - `version-updater.image`: image name, defaults to the image of the service
+//! - `version-updater.version_filter`, `version-updater.private`: as in the config file
+//! - `version-updater.name`: service name, defaults to the name of the service
+
+pub mod compose;
+#[cfg(feature = "docker")]
+pub mod docker;
+#[cfg(feature = "kubernetes")]
+pub mod helm;
+#[cfg(feature = "kubernetes")]
+pub mod kubernetes;
+
+#[cfg(any(feature = "docker", feature = "kubernetes"))]
+use super::config::block_on;
+use super::config::ServiceConfig;
+use super::error::AppError;
+use super::updater::split_image_ref;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+
+const LABEL_PREFIX: &str = "version-updater.";
+
+/// The deployed state of a Helm release, reported next to the resolved version
+/// of the service tracking it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HelmRelease {
+    pub release: String,
+    pub namespace: String,
+    pub revision: u32,
+    pub chart: String,
+    pub chart_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_version: Option<String>,
+    /// Tag of the service image in the release manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployed_tag: Option<String>,
+}
+
+/// Builds a service from the `version-updater.*` labels of a container, returning
+/// `None` if it has not opted in. `image` is the image reference the container runs.
+pub fn service_from_labels(
+    labels: &BTreeMap<String, String>,
+    image: Option<&str>,
+) -> Result<Option<ServiceConfig>, String> {
+    let label = |name: &str| labels.get(&format!("{}{}", LABEL_PREFIX, name));
+    let Some(git) = label("git") else {
+        return Ok(None);
+    };
+
+    let (git_type, repo) = git
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid git label '{}', expected <type>:<repo>", git))?;
+    let mut git_config = Mapping::new();
+    git_config.insert("type".into(), git_type.into());
+    if git_type == "gitlab" {
+        let project_id: u64 = repo
+            .parse()
+            .map_err(|_| format!("Invalid GitLab project ID '{}'", repo))?;
+        git_config.insert("project_id".into(), project_id.into());
+    }
+    git_config.insert("repo".into(), repo.into());
+    if let Some(filter) = label("version_filter") {
+        git_config.insert("version_filter".into(), filter.as_str().into());
+    }
+    if let Some(private) = label("private") {
+        git_config.insert("private".into(), (private == "true").into());
+    }
+
+    let name = match (label("image"), image) {
+        (Some(name), _) => name.as_str(),
+        (None, Some(image)) => split_image_ref(image).0,
+        (None, None) => return Err("No image and no image label".to_string()),
+    };
+    let mut image_config = Mapping::new();
+    image_config.insert("name".into(), name.into());
+    image_config.insert(
+        "tag".into(),
+        label("tag")
+            .map_or("${RELEASE_VERSION}", String::as_str)
+            .into(),
+    );
+
+    let mut service = Mapping::new();
+    service.insert("git".into(), Value::Mapping(git_config));
+    service.insert("image".into(), Value::Mapping(image_config));
+    serde_yaml::from_value(Value::Mapping(service))
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+/// Returns the service name set with `version-updater.name`, if any.
+pub fn name_from_labels(labels: &BTreeMap<String, String>) -> Option<&str> {
+    labels
+        .get(&format!("{}name", LABEL_PREFIX))
+        .map(String::as_str)
+}
+
+fn discovery_error(source: &str, service: &str, error: String) -> AppError {
+    AppError::DiscoveryError(format!("{} ({}): {}", source, service, error))
+}
@@ -0,0 +1,107 @@
+use super::error::AppError;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::ClientBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// Name resolution for the git provider and registry APIs, e.g. for
+/// split-horizon DNS that doesn't resolve internal hosts inside containers.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DnsConfig {
+    /// Addresses of hosts, used instead of resolving them
+    #[serde(default)]
+    pub hosts: BTreeMap<String, Addresses>,
+    /// Only connect over IPv4 or IPv6, defaults to both
+    #[serde(default)]
+    pub ip_version: Option<IpVersion>,
+}
+
+/// A single address or a list of them.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Addresses {
+    One(IpAddr),
+    Many(Vec<IpAddr>),
+}
+
+impl Addresses {
+    fn to_vec(&self) -> Vec<IpAddr> {
+        match self {
+            Addresses::One(address) => vec![*address],
+            Addresses::Many(addresses) => addresses.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IpVersion {
+    Ipv4,
+    Ipv6,
+}
+
+impl IpVersion {
+    fn matches(self, address: IpAddr) -> bool {
+        match self {
+            IpVersion::Ipv4 => address.is_ipv4(),
+            IpVersion::Ipv6 => address.is_ipv6(),
+        }
+    }
+}
+
+impl fmt::Display for IpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpVersion::Ipv4 => write!(f, "IPv4"),
+            IpVersion::Ipv6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// Configures the host addresses and IP version of a client. The port of the
+/// request URLs is kept for overridden hosts.
+pub fn apply(mut builder: ClientBuilder, config: &DnsConfig) -> Result<ClientBuilder, AppError> {
+    for (host, addresses) in &config.hosts {
+        let addresses = addresses.to_vec();
+        if let Some(version) = config.ip_version {
+            if let Some(address) = addresses.iter().find(|a| !version.matches(**a)) {
+                return Err(AppError::ConfigError(format!(
+                    "dns.hosts.{}: {} is not an {} address",
+                    host, address, version
+                )));
+            }
+        }
+        let addresses: Vec<SocketAddr> = addresses
+            .into_iter()
+            .map(|address| SocketAddr::new(address, 0))
+            .collect();
+        builder = builder.resolve_to_addrs(host, &addresses);
+    }
+    if let Some(version) = config.ip_version {
+        builder = builder.dns_resolver(Arc::new(VersionResolver(version)));
+    }
+    Ok(builder)
+}
+
+/// Resolves with the system resolver, keeping the addresses of one IP version.
+struct VersionResolver(IpVersion);
+
+impl Resolve for VersionResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let version = self.0;
+        Box::pin(async move {
+            let host = name.as_str();
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+                .await?
+                .filter(|address| version.matches(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(format!("{} has no {} address", host, version).into());
+            }
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
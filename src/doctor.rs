@@ -0,0 +1,302 @@
+//! Checks of the credentials and connectivity the configured services need,
+//! for the `doctor` subcommand. Nothing is resolved or written.
+
+use super::config::AppConfig;
+use super::git::{GitConfig, Provider, USER_AGENT_NAME};
+use super::http::HttpClient;
+use super::netrc;
+use super::registry::{self, RegistryCredentials};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use nu_ansi_term::Color;
+use reqwest::header::USER_AGENT;
+use reqwest::StatusCode;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// How long a credential helper may take to list its credentials.
+const HELPER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The result of a single check: what passed, or why it failed.
+pub struct Check {
+    pub name: String,
+    pub result: Result<String, String>,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, result: Result<String, String>) -> Self {
+        Self {
+            name: name.into(),
+            result,
+        }
+    }
+}
+
+/// Runs all checks for the services of `config`.
+pub async fn run(config: &AppConfig, http: &HttpClient) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for git in git_sources(config) {
+        checks.push(check_token(&git, http).await);
+    }
+
+    // The credentials configured for each registry, by the first service having any
+    let mut registries: BTreeMap<String, Option<&RegistryCredentials>> = BTreeMap::new();
+    for service in config.services.values() {
+        if service.image.is_none() {
+            continue;
+        }
+        let credentials = registries.entry(service.image.registry()).or_default();
+        if credentials.is_none() {
+            *credentials = service.image.credentials.as_ref();
+        }
+    }
+    for (registry, credentials) in &registries {
+        checks.push(check_credentials(registry, *credentials));
+    }
+    checks.extend(check_helpers(&registries).await);
+    for registry in registries.keys() {
+        checks.push(check_registry(registry, http).await);
+    }
+    checks
+}
+
+/// The pass/fail list, with the failures in red if `color`.
+pub fn render(checks: &[Check], color: bool) -> String {
+    let mut report = String::new();
+    for check in checks {
+        let (status, highlight, detail) = match &check.result {
+            Ok(detail) => ("ok", Color::Green, detail),
+            Err(reason) => ("FAIL", Color::Red, reason),
+        };
+        let status = format!("{:<4}", status);
+        let status = match color {
+            true => highlight.bold().paint(status).to_string(),
+            false => status,
+        };
+        report.push_str(&format!("{}  {}: {}\n", status, check.name, detail));
+    }
+    report
+}
+
+/// One git config per provider, host and token source used by the services,
+/// marking it private if any of its services needs a token.
+fn git_sources(config: &AppConfig) -> Vec<GitConfig> {
+    type Key = (String, Option<String>, Option<String>, Option<String>);
+    let mut sources: BTreeMap<Key, GitConfig> = BTreeMap::new();
+    for service in config.services.values() {
+        let git = &service.git;
+        if !matches!(
+            git.git_type,
+            Provider::Github
+                | Provider::Gitlab
+                | Provider::Codeberg
+                | Provider::AzureDevops
+                | Provider::BitbucketServer
+                | Provider::Gogs
+        ) {
+            continue;
+        }
+        let needs_token =
+            git.private || (git.git_type == Provider::Github && git.global_github_auth);
+        let key = (
+            git.git_type.name().to_string(),
+            git.host.clone(),
+            git.token_file
+                .as_ref()
+                .map(|path| path.display().to_string()),
+            git.token_env.clone(),
+        );
+        let source = sources.entry(key).or_insert_with(|| git.clone());
+        source.private |= needs_token;
+    }
+    sources.into_values().collect()
+}
+
+/// Checks that the token of `git` is present if needed and accepted by the provider.
+async fn check_token(git: &GitConfig, http: &HttpClient) -> Check {
+    let mut name = format!("{} token", git.git_type.name());
+    if let Some(host) = &git.host {
+        name.push_str(&format!(" for {}", host));
+    }
+    if let Some(path) = &git.token_file {
+        name.push_str(&format!(" ({})", path.display()));
+    }
+    let token = match git.token() {
+        Ok(Some(token)) => token,
+        Ok(None) if git.job_token().is_some() => {
+            return Check::new(name, Ok("CI job token".to_string()));
+        }
+        Ok(None) if git.private => {
+            let variable = git.token_env.as_deref().or(git.git_type.token_env());
+            let variable = variable.unwrap_or_default();
+            return Check::new(name, Err(format!("missing, set {}", variable)));
+        }
+        Ok(None) => return Check::new(name, Ok("not needed".to_string())),
+        Err(e) => return Check::new(name, Err(e.to_string())),
+    };
+    Check::new(name, whoami(git, &token, http).await)
+}
+
+/// Asks the provider of `git` who `token` belongs to.
+async fn whoami(git: &GitConfig, token: &str, http: &HttpClient) -> Result<String, String> {
+    let gitlab_url = format!("https://{}/api/v4/user", git.gitlab_host());
+    // The connection data of the organization tells who the token belongs to
+    let organization = git.repo.split('/').next().unwrap_or_default();
+    let azure_devops_url = format!(
+        "https://{}/{}/_apis/connectionData",
+        git.azure_devops_host(),
+        organization
+    );
+    let bitbucket_server_url = format!(
+        "https://{}/rest/api/1.0/application-properties",
+        git.host.as_deref().unwrap_or_default()
+    );
+    let gogs_url = format!(
+        "https://{}/api/v1/user",
+        git.host.as_deref().unwrap_or_default()
+    );
+    let (url, header, value, field) = match git.git_type {
+        Provider::Github => (
+            "https://api.github.com/user",
+            "Authorization",
+            format!("Bearer {}", token),
+            "/login",
+        ),
+        Provider::Gitlab => (
+            gitlab_url.as_str(),
+            "PRIVATE-TOKEN",
+            token.to_string(),
+            "/username",
+        ),
+        Provider::AzureDevops => (
+            azure_devops_url.as_str(),
+            "Authorization",
+            format!("Basic {}", STANDARD.encode(format!(":{}", token))),
+            "/authenticatedUser/providerDisplayName",
+        ),
+        Provider::Gogs => (
+            gogs_url.as_str(),
+            "Authorization",
+            format!("token {}", token),
+            "/username",
+        ),
+        // Names the user in the X-AUSERNAME header only
+        Provider::BitbucketServer => (
+            bitbucket_server_url.as_str(),
+            "Authorization",
+            format!("Bearer {}", token),
+            "",
+        ),
+        _ => (
+            "https://codeberg.org/api/v1/user",
+            "Authorization",
+            format!("Bearer {}", token),
+            "/login",
+        ),
+    };
+    let request = http
+        .get(url)
+        .header(USER_AGENT, USER_AGENT_NAME)
+        .header(header, value);
+    let response = http.send(request).await.map_err(|e| e.to_string())?;
+    match response.status() {
+        status if status.is_success() => {
+            if let Some(user) = response.headers().get("X-AUSERNAME") {
+                let user = String::from_utf8_lossy(user.as_bytes()).to_string();
+                return Ok(format!("authenticated as {}", user));
+            }
+            let user: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+            Ok(format!(
+                "authenticated as {}",
+                user.pointer(field)
+                    .and_then(|user| user.as_str())
+                    .unwrap_or("unknown user")
+            ))
+        }
+        StatusCode::UNAUTHORIZED => Err("rejected by the provider".to_string()),
+        status => Err(format!("{} returned {}", url, status)),
+    }
+}
+
+/// Checks that the credentials of `registry` can be read: the configured
+/// `credentials`, or else those of ~/.docker/config.json or ~/.netrc.
+fn check_credentials(registry: &str, credentials: Option<&RegistryCredentials>) -> Check {
+    let name = format!("{} credentials", registry);
+    if let Some(credentials) = credentials {
+        let result = credentials
+            .resolve()
+            .map(|(username, _)| format!("user {} from the config", username))
+            .map_err(|e| e.to_string());
+        return Check::new(name, result);
+    }
+    let result = match registry::get_docker_credentials(registry) {
+        Ok(Some((username, _))) => Ok(format!("user {} from ~/.docker/config.json", username)),
+        Ok(None) => Ok(match netrc::lookup(registry) {
+            Some((username, _)) => format!("user {} from ~/.netrc", username),
+            None => "anonymous".to_string(),
+        }),
+        Err(e) => Err(e.to_string()),
+    };
+    Check::new(name, result)
+}
+
+/// Checks that the credential helpers configured for `registries` in
+/// ~/.docker/config.json respond.
+async fn check_helpers<T>(registries: &BTreeMap<String, T>) -> Vec<Check> {
+    let helpers = match registry::docker_credential_helpers() {
+        Ok(helpers) => helpers,
+        Err(e) => return vec![Check::new("docker credential helpers", Err(e.to_string()))],
+    };
+    let used: BTreeSet<&String> = helpers
+        .iter()
+        .filter(|(host, _)| {
+            *host == "*" || registries.contains_key(registry::canonical_registry(host))
+        })
+        .map(|(_, helper)| helper)
+        .collect();
+    let mut checks = Vec::new();
+    for helper in used {
+        let program = format!("docker-credential-{}", helper);
+        let result = list_credentials(&program).await;
+        checks.push(Check::new(program, result));
+    }
+    checks
+}
+
+async fn list_credentials(program: &str) -> Result<String, String> {
+    let output = Command::new(program)
+        .arg("list")
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(HELPER_TIMEOUT, output).await {
+        Err(_) => Err(format!("no response within {}s", HELPER_TIMEOUT.as_secs())),
+        Ok(Err(e)) if e.kind() == io::ErrorKind::NotFound => Err("not found in PATH".to_string()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Ok(Ok(output)) if output.status.success() => Ok("responds".to_string()),
+        Ok(Ok(output)) => Err(format!(
+            "{}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+/// Checks that the registry API of `registry` answers, with or without
+/// asking for authentication.
+async fn check_registry(registry: &str, http: &HttpClient) -> Check {
+    let url = format!("https://{}/v2/", registry);
+    let request = http.get(&url).header(USER_AGENT, USER_AGENT_NAME);
+    let result = match http.send(request).await {
+        Ok(response) => match response.status() {
+            StatusCode::OK | StatusCode::UNAUTHORIZED => Ok("reachable".to_string()),
+            status => Err(format!("{} returned {}", url, status)),
+        },
+        Err(e) => Err(e.to_string()),
+    };
+    Check::new(format!("{} registry", registry), result)
+}
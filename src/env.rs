@@ -0,0 +1,73 @@
+//! `${VAR}` expansion of environment variables in the configuration file, and
+//! secrets read from the environment or from files.
+
+use super::error::AppError;
+use regex::{Captures, Regex};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+static VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$(\$)?\{([A-Za-z_][A-Za-z0-9_]*)(?:(:?-)([^}]*))?\}").unwrap());
+
+/// Placeholders of tag and key templates, which are filled in later.
+const PLACEHOLDERS: &[&str] = &[
+    "RELEASE_VERSION",
+    "MAJOR",
+    "MINOR",
+    "PATCH",
+    "MAJOR_MINOR",
+    "SERVICE",
+];
+
+/// Expands `${VAR}`, `${VAR:-default}` (default if unset or empty) and
+/// `${VAR-default}` (default if unset). `$${VAR}` is kept as literal `${VAR}`,
+/// as are the template placeholders. Unset variables without default are an
+/// error, so a missing token doesn't silently end up empty.
+pub fn substitute(content: &str) -> Result<String, AppError> {
+    let mut missing = Vec::new();
+    let expanded = VARIABLE.replace_all(content, |captures: &Captures| {
+        let name = &captures[2];
+        if captures.get(1).is_some() || PLACEHOLDERS.contains(&name) {
+            return captures[0].replacen("$$", "$", 1);
+        }
+        let value = env::var(name).ok();
+        match (captures.get(3).map(|m| m.as_str()), value) {
+            (Some(":-"), Some(value)) if value.is_empty() => captures[4].to_string(),
+            (_, Some(value)) => value,
+            (Some(_), None) => captures[4].to_string(),
+            (None, None) => {
+                missing.push(name.to_string());
+                String::new()
+            }
+        }
+    });
+    if !missing.is_empty() {
+        return Err(AppError::ConfigError(format!(
+            "Environment variables not set: {}",
+            missing.join(", ")
+        )));
+    }
+    Ok(expanded.into_owned())
+}
+
+/// Reads a secret from the `name` environment variable, or from the file named
+/// by `<name>_FILE`, the convention for Docker and Kubernetes secrets.
+pub fn secret(name: &str) -> Result<Option<String>, AppError> {
+    if let Ok(value) = env::var(name) {
+        return Ok(Some(value));
+    }
+    match env::var(format!("{}_FILE", name)) {
+        Ok(path) => read_secret(Path::new(&path)).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Reads a secret file, without the trailing newline most editors and
+/// `echo` add.
+pub fn read_secret(path: &Path) -> Result<String, AppError> {
+    let secret = fs::read_to_string(path)
+        .map_err(|e| AppError::SecretError(format!("{}: {}", path.display(), e)))?;
+    Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+}
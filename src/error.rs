@@ -1,3 +1,5 @@
+use super::shutdown::Signal;
+use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -16,8 +18,16 @@ pub enum AppError {
     MissingGitlabToken,
     #[error("Missing Codeberg token for private repository")]
     MissingCodebergToken,
+    #[error("Missing Azure DevOps token for private repository")]
+    MissingAzureDevopsToken,
+    #[error("Missing Bitbucket Server token for private repository")]
+    MissingBitbucketServerToken,
+    #[error("Missing Gogs token for private repository")]
+    MissingGogsToken,
     #[error("Failed to read Docker credentials: {0}")]
     CredentialsError(String),
+    #[error("Failed to read secret: {0}")]
+    SecretError(String),
 
     #[error("Registry authentication failed: {0}")]
     AuthenticationError(String),
@@ -33,4 +43,103 @@ pub enum AppError {
 
     #[error("Invalid registry response: {0}")]
     InvalidResponse(String),
+
+    #[error("Failed to update file: {0}")]
+    UpdateError(String),
+
+    #[error("Failed to commit updates: {0}")]
+    CommitError(String),
+
+    #[error("Failed to open pull request: {0}")]
+    PullRequestError(String),
+
+    #[error("Docker Swarm request failed: {0}")]
+    SwarmError(String),
+
+    #[error("Portainer request failed: {0}")]
+    PortainerError(String),
+
+    #[error("Import failed: {0}")]
+    ImportError(String),
+
+    #[error("Service discovery failed: {0}")]
+    DiscoveryError(String),
+
+    #[error("Invalid schedule {0}")]
+    InvalidSchedule(String),
+
+    #[error("HTTP server error: {0}")]
+    ServerError(String),
+
+    #[error("Failed to watch files: {0}")]
+    WatchError(String),
+
+    #[error("Invalid service filter {0}")]
+    InvalidFilter(String),
+
+    #[error("Failed to acquire lock: {0}")]
+    LockError(String),
+
+    #[error("Authentication failed: {0}")]
+    Unauthorized(String),
+
+    #[error("Invalid configuration: {0}")]
+    ConfigError(String),
+
+    #[error("Plugin failed: {0}")]
+    PluginError(String),
+
+    #[error("Version history failed: {0}")]
+    HistoryError(String),
+
+    #[error("Operator failed: {0}")]
+    OperatorError(String),
+
+    #[error("Login failed: {0}")]
+    LoginError(String),
+
+    #[error("Not available offline: {0}")]
+    Offline(String),
+
+    #[error("Interrupted by {0}")]
+    Interrupted(Signal),
+
+    /// The failure of a lookup shared by several services, see `HttpClient::coalesce`
+    #[error("{0:#}")]
+    Coalesced(Arc<anyhow::Error>),
+
+    #[error("Not supported by this build: {0} (enable the `{1}` cargo feature)")]
+    FeatureDisabled(&'static str, &'static str),
+}
+
+impl AppError {
+    /// Whether the error is caused by the configuration or credentials rather
+    /// than a transient problem, so retrying won't help.
+    pub fn is_fatal(&self) -> bool {
+        if let AppError::Coalesced(e) = self {
+            return e.downcast_ref::<AppError>().is_some_and(AppError::is_fatal);
+        }
+        matches!(
+            self,
+            AppError::MissingGitlabProjectId
+                | AppError::MissingGithubToken
+                | AppError::MissingGitlabToken
+                | AppError::MissingCodebergToken
+                | AppError::MissingAzureDevopsToken
+                | AppError::MissingBitbucketServerToken
+                | AppError::MissingGogsToken
+                | AppError::CredentialsError(_)
+                | AppError::SecretError(_)
+                | AppError::AuthenticationError(_)
+                | AppError::Unauthorized(_)
+        )
+    }
+
+    /// The error itself, or for `Coalesced` the error of the shared lookup.
+    pub fn root(&self) -> &AppError {
+        match self {
+            AppError::Coalesced(e) => e.downcast_ref::<AppError>().map_or(self, AppError::root),
+            _ => self,
+        }
+    }
 }
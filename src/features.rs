@@ -0,0 +1,29 @@
+//! Stand-ins for integrations left out of the build by cargo features.
+
+/// Defines a placeholder for the config struct of a disabled integration.
+/// Configs using it fail to load with an error naming the missing feature
+/// instead of silently ignoring the section. Unused when all features are enabled.
+#[allow(unused_macros)]
+macro_rules! disabled_config {
+    ($name:ident, $what:literal, $feature:literal) => {
+        #[derive(Debug, Clone, Default)]
+        pub struct $name;
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(_: D) -> Result<Self, D::Error> {
+                Err(serde::de::Error::custom(
+                    $crate::error::AppError::FeatureDisabled($what, $feature),
+                ))
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_unit()
+            }
+        }
+    };
+}
+
+#[allow(unused_imports)]
+pub(crate) use disabled_config;
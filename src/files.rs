@@ -0,0 +1,76 @@
+use super::error::AppError;
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, trace};
+
+/// Writes `content` to `path` without ever exposing a partially written file.
+///
+/// The data is written to a temporary file next to the target, synced to disk
+/// and then renamed over the target. When `backup` is set, the previous file
+/// (if any) is kept as `<path>.bak`.
+pub fn write_atomic(path: &Path, content: &[u8], backup: bool) -> io::Result<()> {
+    let tmp_path = sibling_path(path, &format!(".tmp.{}", std::process::id()));
+    trace!("Writing temporary file {}", tmp_path.display());
+
+    let result = (|| {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+
+        if backup && path.exists() {
+            let backup_path = sibling_path(path, ".bak");
+            debug!(
+                "Keeping backup of {} at {}",
+                path.display(),
+                backup_path.display()
+            );
+            fs::copy(path, &backup_path)?;
+        }
+
+        fs::rename(&tmp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Takes an exclusive lock on `path`, creating the file if needed. The lock
+/// is held until the returned file is dropped or the process exits.
+///
+/// If another process holds the lock, this fails unless `wait` is set, in
+/// which case it blocks until the lock is released.
+pub fn lock(path: &Path, wait: bool) -> Result<File, AppError> {
+    let lock_error = |e: io::Error| AppError::LockError(format!("{}: {}", path.display(), e));
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .map_err(lock_error)?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) if wait => {
+            info!("Waiting for another instance to release {}", path.display());
+            file.lock().map_err(lock_error)?;
+        }
+        Err(TryLockError::WouldBlock) => {
+            return Err(AppError::LockError(format!(
+                "{} is held by another instance",
+                path.display()
+            )))
+        }
+        Err(TryLockError::Error(e)) => return Err(lock_error(e)),
+    }
+    debug!("Acquired lock {}", path.display());
+    Ok(file)
+}
+
+/// `path` with `suffix` appended to its file name.
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
@@ -1,103 +1,537 @@
+use super::env;
 use super::error::AppError;
+use super::http::HttpClient;
+use super::login;
+use super::netrc;
+use super::registry;
 use anyhow::Result;
-use log::{debug, error, info, trace};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures::future::BoxFuture;
 use regex::Regex;
 use reqwest::header::USER_AGENT;
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::collections::HashMap;
 use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, RwLock};
+use tracing::{debug, error, info, trace, warn};
 
 pub const USER_AGENT_NAME: &str = "version-updater";
 const DEFAULT_VERSION_FILTER: &str = "(.*)";
+/// Most releases listed at once, the page size limit of the GitHub and GitLab APIs
+const MAX_RELEASES: usize = 100;
+/// The variable GitLab CI sets to the token of the running job
+const JOB_TOKEN_ENV: &str = "CI_JOB_TOKEN";
+/// The variable Azure Pipelines sets to the token of the running job, if
+/// mapped into the environment of the step
+const AZURE_JOB_TOKEN_ENV: &str = "SYSTEM_ACCESSTOKEN";
 
 fn default_version_filter() -> String {
     DEFAULT_VERSION_FILTER.to_string()
 }
+/// A source of release versions, selected by the `type` of a service's `git`
+/// section. Providers are looked up in a process-wide registry, which holds the
+/// built-in GitHub, GitLab, Codeberg, Azure DevOps, Bitbucket Server and Gogs
+/// providers and any added with
+/// [`register_provider`].
+pub trait VersionProvider: Send + Sync {
+    /// Name used in log and error messages, e.g. `GitHub`
+    fn name(&self) -> &str;
+
+    /// Returns the tag of the latest release of the repository in `config`.
+    /// The `version_filter` of the config is applied to it afterwards.
+    fn latest_tag<'a>(
+        &'a self,
+        config: &'a GitConfig,
+        http: &'a HttpClient,
+    ) -> BoxFuture<'a, Result<String>>;
+
+    /// Returns up to `limit` recent releases of the repository, newest first.
+    /// Providers that can't list releases return an error.
+    fn releases<'a>(
+        &'a self,
+        _config: &'a GitConfig,
+        _http: &'a HttpClient,
+        _limit: usize,
+    ) -> BoxFuture<'a, Result<Vec<Release>>> {
+        Box::pin(async move {
+            Err(AppError::ConfigError(format!("{} can't list releases", self.name())).into())
+        })
+    }
+}
+
+/// A release as listed by [`VersionProvider::releases`].
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Release {
+    pub tag: String,
+    /// As reported by the provider, e.g. `2024-05-28T14:02:11Z`
+    pub published_at: Option<String>,
+    pub prerelease: bool,
+}
+
+static PROVIDERS: LazyLock<RwLock<HashMap<String, Arc<dyn VersionProvider>>>> =
+    LazyLock::new(|| {
+        let builtin: [(&str, Arc<dyn VersionProvider>); 6] = [
+            ("github", Arc::new(LatestRelease::GITHUB)),
+            ("gitlab", Arc::new(LatestRelease::GITLAB)),
+            ("codeberg", Arc::new(LatestRelease::CODEBERG)),
+            ("azure_devops", Arc::new(LatestRelease::AZURE_DEVOPS)),
+            (
+                "bitbucket_server",
+                Arc::new(LatestRelease::BITBUCKET_SERVER),
+            ),
+            ("gogs", Arc::new(LatestRelease::GOGS)),
+        ];
+        let providers = builtin
+            .into_iter()
+            .map(|(name, provider)| (name.to_string(), provider))
+            .collect();
+        RwLock::new(providers)
+    });
+
+/// Makes `provider` available as `type: <name>`, replacing the provider
+/// registered under that name before, if any.
+pub fn register_provider(name: &str, provider: impl VersionProvider + 'static) {
+    PROVIDERS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(name.to_string(), Arc::new(provider));
+}
+
+/// The provider registered under `name`.
+pub fn provider(name: &str) -> Option<Arc<dyn VersionProvider>> {
+    PROVIDERS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(name)
+        .cloned()
+}
+
 pub struct GitClient;
 
 impl GitClient {
-    pub async fn get_version(config: &GitConfig) -> Result<String> {
-        match config.git_type {
-            Provider::Codeberg => {
-                Self::get_version_from_api(
-                    ApiType::Codeberg { repo: &config.repo },
-                    if config.private || config.global_github_auth {
-                        env::var("CODEBERG_TOKEN").ok()
-                    } else {
-                        None
-                    },
-                    &config.filter,
-                )
-                .await
-            }
-            Provider::Github => {
-                Self::get_version_from_api(
-                    ApiType::Github { repo: &config.repo },
-                    if config.private || config.global_github_auth {
-                        env::var("GITHUB_TOKEN").ok()
-                    } else {
-                        None
-                    },
-                    &config.filter,
-                )
-                .await
-            }
-            Provider::Gitlab => {
-                Self::get_version_from_api(
-                    ApiType::Gitlab {
-                        project_id: config.project_id.unwrap(),
-                    },
-                    if config.private {
-                        env::var("GITLAB_TOKEN").ok()
-                    } else {
-                        None
-                    },
-                    &config.filter,
-                )
-                .await
-            }
-            Provider::None => Ok(String::new()),
+    pub async fn get_version(config: &GitConfig, http: &HttpClient) -> Result<String> {
+        if config.git_type == Provider::None {
+            return Ok(String::new());
         }
+        let provider =
+            provider(config.git_type.name()).ok_or_else(|| unknown_provider(&config.git_type))?;
+        // Everything but the filter, which is applied to the shared tag, unless
+        // the provider picks the tag by the filter
+        let filter = match config.git_type {
+            Provider::AzureDevops | Provider::BitbucketServer => config.filter.as_str(),
+            _ => "",
+        };
+        let key = format!(
+            "latest-tag:{}:{}:{:?}:{}:{:?}:{}:{:?}:{:?}:{}",
+            config.git_type.name(),
+            filter,
+            config.host,
+            config.repo,
+            config.project_id,
+            config.private,
+            config.token_file,
+            config.token_env,
+            config.global_github_auth
+        );
+        let tag_name = http
+            .coalesce(&key, provider.latest_tag(config, http))
+            .await?;
+        debug!("Latest release tag is {:?}", tag_name);
+        extract_version(&tag_name, &config.filter, provider.name(), config)
+    }
+
+    /// Lists up to `limit` recent releases of the repository, newest first.
+    pub async fn releases(
+        config: &GitConfig,
+        http: &HttpClient,
+        limit: usize,
+    ) -> Result<Vec<Release>> {
+        let provider =
+            provider(config.git_type.name()).ok_or_else(|| unknown_provider(&config.git_type))?;
+        provider.releases(config, http, limit).await
+    }
+}
+
+/// A provider resolving the latest release through a REST API, by default one
+/// returning its `tag_name`.
+struct LatestRelease {
+    name: &'static str,
+    url: fn(&GitConfig) -> String,
+    /// The tag of the latest release in the response of `url`
+    latest: fn(&GitConfig, &serde_json::Value) -> String,
+    /// The URL listing up to the given number of releases
+    releases_url: fn(&GitConfig, usize) -> String,
+    /// The releases in the response of `releases_url`, newest first
+    releases: fn(&serde_json::Value) -> Vec<Release>,
+    /// The header carrying the token
+    auth: fn(String) -> (String, String),
+    /// The header carrying the credentials used without token, if any
+    fallback_auth: fn(&GitConfig) -> Option<(String, String)>,
+    /// Whether `global.git.github.authenticate` applies to public repositories
+    global_auth: bool,
+    /// Whether public repositories are read without token. Otherwise the
+    /// token is sent whenever there is one.
+    anonymous: bool,
+}
+
+impl LatestRelease {
+    const GITHUB: Self = Self {
+        name: "GitHub",
+        url: |config| {
+            format!(
+                "https://api.github.com/repos/{}/releases/latest",
+                config.repo
+            )
+        },
+        latest: release_tag,
+        releases_url: |config, limit| {
+            format!(
+                "https://api.github.com/repos/{}/releases?per_page={}",
+                config.repo, limit
+            )
+        },
+        releases: release_list,
+        auth: bearer,
+        fallback_auth: |_| None,
+        global_auth: true,
+        anonymous: true,
+    };
+    const GITLAB: Self = Self {
+        name: "GitLab",
+        url: |config| {
+            format!(
+                "https://{}/api/v4/projects/{}/releases/permalink/latest",
+                config.gitlab_host(),
+                config.project_id.unwrap_or_default()
+            )
+        },
+        latest: release_tag,
+        releases_url: |config, limit| {
+            format!(
+                "https://{}/api/v4/projects/{}/releases?per_page={}",
+                config.gitlab_host(),
+                config.project_id.unwrap_or_default(),
+                limit
+            )
+        },
+        releases: release_list,
+        auth: |token| ("PRIVATE-TOKEN".to_string(), token),
+        fallback_auth: |config| {
+            config
+                .job_token()
+                .map(|token| ("JOB-TOKEN".to_string(), token))
+        },
+        global_auth: false,
+        anonymous: true,
+    };
+    const CODEBERG: Self = Self {
+        name: "Codeberg",
+        url: |config| {
+            format!(
+                "https://codeberg.org/api/v1/repos/{}/releases/latest",
+                config.repo
+            )
+        },
+        latest: release_tag,
+        releases_url: |config, limit| {
+            format!(
+                "https://codeberg.org/api/v1/repos/{}/releases?limit={}",
+                config.repo, limit
+            )
+        },
+        releases: release_list,
+        auth: bearer,
+        fallback_auth: |_| None,
+        global_auth: true,
+        anonymous: true,
+    };
+    /// Azure DevOps repositories have no releases, so the latest release is
+    /// the highest tag matching the `version_filter`.
+    const AZURE_DEVOPS: Self = Self {
+        name: "Azure DevOps",
+        url: azure_devops_tags_url,
+        latest: |config, data| highest_tag(config, azure_devops_tags(data)),
+        releases_url: |config, _| azure_devops_tags_url(config),
+        releases: |data| tag_releases(azure_devops_tags(data)),
+        auth: |token| {
+            let credentials = STANDARD.encode(format!(":{}", token));
+            (
+                "Authorization".to_string(),
+                format!("Basic {}", credentials),
+            )
+        },
+        fallback_auth: |config| config.job_token().map(bearer),
+        global_auth: false,
+        anonymous: false,
+    };
+    /// Like Azure DevOps, Bitbucket Server has tags only. Up to 1000 tags are
+    /// considered, the most recently created first.
+    const BITBUCKET_SERVER: Self = Self {
+        name: "Bitbucket Server",
+        url: bitbucket_server_tags_url,
+        latest: |config, data| highest_tag(config, bitbucket_server_tags(data)),
+        releases_url: |config, _| bitbucket_server_tags_url(config),
+        releases: |data| tag_releases(bitbucket_server_tags(data)),
+        auth: bearer,
+        fallback_auth: |_| None,
+        global_auth: false,
+        anonymous: false,
+    };
+    /// Gogs lists releases, but has no endpoint for the latest one.
+    const GOGS: Self = Self {
+        name: "Gogs",
+        url: gogs_releases_url,
+        latest: |_, data| {
+            let releases = data.as_array().map(Vec::as_slice).unwrap_or_default();
+            releases
+                .iter()
+                .find(|release| release["draft"] != true && release["prerelease"] != true)
+                .and_then(|release| release["tag_name"].as_str())
+                .unwrap_or("")
+                .to_string()
+        },
+        releases_url: |config, _| gogs_releases_url(config),
+        releases: release_list,
+        auth: |token| ("Authorization".to_string(), format!("token {}", token)),
+        fallback_auth: |_| None,
+        global_auth: false,
+        anonymous: true,
+    };
+}
+
+/// The releases of a Gogs repository, newest first, `host` being the host of
+/// the server and its context path, if any.
+fn gogs_releases_url(config: &GitConfig) -> String {
+    format!(
+        "https://{}/api/v1/repos/{}/releases",
+        config.host.as_deref().unwrap_or_default(),
+        config.repo
+    )
+}
+
+/// The highest of `tags` from which the `version_filter` extracts a version.
+fn highest_tag(config: &GitConfig, mut tags: Vec<String>) -> String {
+    if let Ok(filter) = Regex::new(&config.filter) {
+        tags.retain(|tag| {
+            filter
+                .captures(tag)
+                .and_then(|captures| captures.get(1))
+                .is_some_and(|version| !version.is_empty())
+        });
+    }
+    registry::sort_tags(&mut tags);
+    tags.into_iter().next().unwrap_or_default()
+}
+
+/// `tags` as releases, highest version first.
+fn tag_releases(mut tags: Vec<String>) -> Vec<Release> {
+    registry::sort_tags(&mut tags);
+    tags.into_iter()
+        .map(|tag| Release {
+            tag,
+            published_at: None,
+            prerelease: false,
+        })
+        .collect()
+}
+
+/// The tag refs of an Azure DevOps repository, `repo` being
+/// `organization/project/repository`.
+fn azure_devops_tags_url(config: &GitConfig) -> String {
+    let (project, repository) = config.repo.rsplit_once('/').unwrap_or_default();
+    format!(
+        "https://{}/{}/_apis/git/repositories/{}/refs?filter=tags/&api-version=7.1",
+        config.azure_devops_host(),
+        project,
+        repository
+    )
+}
+
+/// The tags in a list of Azure DevOps refs.
+fn azure_devops_tags(data: &serde_json::Value) -> Vec<String> {
+    let refs = data["value"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    refs.iter()
+        .filter_map(|r| r["name"].as_str()?.strip_prefix("refs/tags/"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The tags of a Bitbucket Server repository, `repo` being `PROJECT/repository`
+/// and `host` the host of the server and its context path, if any.
+fn bitbucket_server_tags_url(config: &GitConfig) -> String {
+    let (project, repository) = config.repo.split_once('/').unwrap_or_default();
+    format!(
+        "https://{}/rest/api/1.0/projects/{}/repos/{}/tags?limit=1000&orderBy=MODIFICATION",
+        config.host.as_deref().unwrap_or_default(),
+        project,
+        repository
+    )
+}
+
+/// The tags in a page of Bitbucket Server tags.
+fn bitbucket_server_tags(data: &serde_json::Value) -> Vec<String> {
+    let values = data["values"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    values
+        .iter()
+        .filter_map(|tag| tag["displayId"].as_str())
+        .map(str::to_string)
+        .collect()
+}
+
+fn release_tag(_: &GitConfig, data: &serde_json::Value) -> String {
+    data["tag_name"].as_str().unwrap_or("").to_string()
+}
+
+fn release_list(data: &serde_json::Value) -> Vec<Release> {
+    let releases = data.as_array().map(Vec::as_slice).unwrap_or_default();
+    releases
+        .iter()
+        .map(|release| Release {
+            tag: release["tag_name"].as_str().unwrap_or("").to_string(),
+            // GitLab reports `released_at` and no prerelease flag, Gogs `created_at`
+            published_at: release["published_at"]
+                .as_str()
+                .or(release["released_at"].as_str())
+                .or(release["created_at"].as_str())
+                .map(str::to_string),
+            prerelease: release["prerelease"]
+                .as_bool()
+                .or(release["upcoming_release"].as_bool())
+                .unwrap_or(false),
+        })
+        .collect()
+}
+
+impl VersionProvider for LatestRelease {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn latest_tag<'a>(
+        &'a self,
+        config: &'a GitConfig,
+        http: &'a HttpClient,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let url = (self.url)(config);
+            info!(
+                "Getting latest version from {}({}) for {}",
+                self.name, config, url
+            );
+            let data = self.get(config, http, &url).await?;
+            Ok((self.latest)(config, &data))
+        })
+    }
+
+    fn releases<'a>(
+        &'a self,
+        config: &'a GitConfig,
+        http: &'a HttpClient,
+        limit: usize,
+    ) -> BoxFuture<'a, Result<Vec<Release>>> {
+        Box::pin(async move {
+            if limit > MAX_RELEASES {
+                warn!(
+                    "{} lists at most {} releases, not {}",
+                    self.name, MAX_RELEASES, limit
+                );
+            }
+            let limit = limit.min(MAX_RELEASES);
+            let url = (self.releases_url)(config, limit);
+            info!("Listing releases of {}({}) at {}", self.name, config, url);
+            let data = self.get(config, http, &url).await?;
+            let mut releases = (self.releases)(&data);
+            releases.truncate(limit);
+            Ok(releases)
+        })
     }
+}
 
-    async fn get_version_from_api(
-        api_type: ApiType<'_>,
-        token: Option<String>,
-        filter: &str,
-    ) -> Result<String> {
-        let (url, auth_header) = api_type.get_request_details(token);
-        info!("Getting latest version from {} for {}", api_type, url);
+impl LatestRelease {
+    /// Sends an authenticated API request for `url`, or answers it from the cache.
+    async fn get(
+        &self,
+        config: &GitConfig,
+        http: &HttpClient,
+        url: &str,
+    ) -> Result<serde_json::Value> {
+        let auth =
+            if config.private || !self.anonymous || (self.global_auth && config.global_github_auth)
+            {
+                config
+                    .token()?
+                    .map(self.auth)
+                    .or_else(|| (self.fallback_auth)(config))
+            } else {
+                None
+            };
+        let api = format!("{}({})", self.name, config);
         debug!("API query url {}", url);
 
-        let client = reqwest::Client::new();
-        let mut request = client.get(url).header(USER_AGENT, USER_AGENT_NAME);
+        let body = match http.cached(url) {
+            Some(body) => body,
+            None => {
+                let mut request = http.get(url).header(USER_AGENT, USER_AGENT_NAME);
 
-        if let Some((header_name, header_value)) = auth_header {
-            request = request.header(header_name, header_value);
-        }
+                if let Some((header_name, header_value)) = auth {
+                    request = request.header(header_name, header_value);
+                }
 
-        trace!("Request is {:?}", request);
-        let response = request.send().await?;
-        trace!("Response is {:?}", response);
+                trace!("Request is {:?}", request);
+                http.ensure_online(url)?;
+                let response = http.send(request).await?;
+                trace!("Response is {:?}", response);
 
-        if response.status() == StatusCode::TOO_MANY_REQUESTS
-            || response.status() == StatusCode::FORBIDDEN
-        {
-            error!("{}: Failed to get version: Rate limited", api_type);
-            return Err(AppError::RateLimited(format!("{} API", api_type)).into());
-        }
+                if response.status() == StatusCode::TOO_MANY_REQUESTS
+                    || response.status() == StatusCode::FORBIDDEN
+                {
+                    error!("{}: Failed to get version: Rate limited", api);
+                    return Err(AppError::RateLimited(format!("{} API", api)).into());
+                }
+                if response.status() == StatusCode::UNAUTHORIZED {
+                    error!("{}: Failed to get version: Unauthorized", api);
+                    return Err(AppError::Unauthorized(format!("{} API", api)).into());
+                }
+                if response.status() == StatusCode::NOT_FOUND {
+                    error!("{}: Failed to get version: Not found", api);
+                    return Err(AppError::NotFound(format!("{} API at {}", api, url)).into());
+                }
+                if !response.status().is_success() {
+                    error!("{}: Failed to get version: {}", api, response.status());
+                    return Err(AppError::RequestError(format!(
+                        "{} API returned {}",
+                        api,
+                        response.status()
+                    ))
+                    .into());
+                }
 
-        let body = response.text().await?;
+                let body = response.text().await?;
+                http.store(url, &body);
+                body
+            }
+        };
         trace!("Body is {:?}", body);
         let data: serde_json::Value = serde_json::from_str(&body)?;
         trace!("Data is {:?}", data);
+        Ok(data)
+    }
+}
 
-        let tag_name = data["tag_name"].as_str().unwrap_or("");
-        trace!("Tag is {:?}", tag_name);
+fn bearer(token: String) -> (String, String) {
+    ("Authorization".to_string(), format!("Bearer {}", token))
+}
 
-        extract_version(tag_name, filter, api_type)
-    }
+fn unknown_provider(provider: &Provider) -> AppError {
+    AppError::ConfigError(format!("Unknown git provider type '{}'", provider.name()))
 }
 
 impl fmt::Display for GitConfig {
@@ -111,6 +545,7 @@ impl fmt::Display for GitConfig {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GitConfig {
+    #[serde(default)]
     pub repo: String,
     #[serde(rename = "type")]
     pub git_type: Provider,
@@ -120,104 +555,324 @@ pub struct GitConfig {
     pub filter: String,
     #[serde(default)]
     pub private: bool,
+    /// Host of a self-managed GitLab instance, defaults to gitlab.com, of an
+    /// Azure DevOps Server, defaults to dev.azure.com, or of a Bitbucket Server
+    /// or Gogs server, required for them
+    #[serde(default)]
+    pub host: Option<String>,
+    /// File containing the API token, defaults to `global.git.<provider>.token_file`
+    #[serde(default)]
+    pub token_file: Option<PathBuf>,
+    /// Environment variable holding the API token, instead of the provider's
+    #[serde(default)]
+    pub token_env: Option<String>,
     #[serde(skip)]
     pub global_github_auth: bool,
+    /// Whether the token of the gh CLI may be used, see `global.git.github.gh_cli`
+    #[serde(skip)]
+    pub gh_cli: bool,
 }
 
 impl GitConfig {
+    /// No git source (`type: none`), for services resolved from registry tags.
+    pub fn none() -> Self {
+        Self {
+            repo: String::new(),
+            git_type: Provider::None,
+            project_id: None,
+            filter: default_version_filter(),
+            private: false,
+            host: None,
+            token_file: None,
+            token_env: None,
+            global_github_auth: false,
+            gh_cli: false,
+        }
+    }
+
+    /// A repository of `provider`, for services built in code.
+    pub fn new(provider: Provider, repo: &str) -> Self {
+        Self {
+            repo: repo.to_string(),
+            git_type: provider,
+            ..Self::none()
+        }
+    }
+
+    /// A GitLab project, which is addressed by its ID.
+    pub fn gitlab(project_id: u64) -> Self {
+        Self {
+            project_id: Some(project_id),
+            ..Self::new(Provider::Gitlab, "")
+        }
+    }
+
+    /// A repository given as `<type>:<repo>`, e.g. `github:owner/repo` or
+    /// `gitlab:278964` with the project ID. Without type, GitHub is assumed.
+    /// Self-hosted servers without default host take it as first segment, as
+    /// in `gogs:git.example.com/owner/repo`.
+    pub fn parse_source(source: &str) -> Result<Self, AppError> {
+        let (provider, repo) = source.split_once(':').unwrap_or(("github", source));
+        match Provider::from(provider.to_string()) {
+            Provider::Gitlab => {
+                let project_id = repo.parse().map_err(|_| {
+                    AppError::ConfigError(format!("GitLab project ID expected, got '{}'", repo))
+                })?;
+                Ok(Self::gitlab(project_id))
+            }
+            provider @ (Provider::BitbucketServer | Provider::Gogs) => {
+                let (host, repo) = repo.split_once('/').ok_or_else(|| {
+                    AppError::ConfigError(format!("<host>/<repo> expected, got '{}'", repo))
+                })?;
+                Ok(Self {
+                    host: Some(host.to_string()),
+                    ..Self::new(provider, repo)
+                })
+            }
+            provider => Ok(Self::new(provider, repo)),
+        }
+    }
+
+    pub fn with_filter(mut self, filter: &str) -> Self {
+        self.filter = filter.to_string();
+        self
+    }
+
+    pub fn with_private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
     // Add a method to set the global authentication
     pub fn with_global_github_auth(mut self, auth: bool) -> Self {
         self.global_github_auth = auth;
         self
     }
+
+    /// Falls back to the token of the gh CLI for GitHub repositories.
+    pub fn with_gh_cli(mut self, gh_cli: bool) -> Self {
+        self.gh_cli = gh_cli;
+        self
+    }
     // Validation method
     pub fn validate(&self) -> Result<(), AppError> {
+        version_filter(&self.filter)?;
+        if let Provider::Custom(_) = self.git_type {
+            return match provider(self.git_type.name()) {
+                Some(_) => Ok(()),
+                None => Err(unknown_provider(&self.git_type)),
+            };
+        }
         if self.git_type == Provider::Gitlab && self.project_id.is_none() {
             return Err(AppError::MissingGitlabProjectId);
         }
+        if self.git_type == Provider::AzureDevops && self.repo.split('/').count() != 3 {
+            return Err(AppError::ConfigError(format!(
+                "Azure DevOps repo must be organization/project/repository, got '{}'",
+                self.repo
+            )));
+        }
+        if matches!(self.git_type, Provider::BitbucketServer | Provider::Gogs)
+            && self.host.is_none()
+        {
+            return Err(AppError::ConfigError(format!(
+                "Type {} needs the host of the server",
+                self.git_type.name()
+            )));
+        }
+        if self.git_type == Provider::BitbucketServer && self.repo.split('/').count() != 2 {
+            return Err(AppError::ConfigError(format!(
+                "Bitbucket Server repo must be PROJECT/repository, got '{}'",
+                self.repo
+            )));
+        }
 
         if self.private || (self.git_type == Provider::Github && self.global_github_auth) {
+            if self.token()?.is_some() || self.job_token().is_some() {
+                return Ok(());
+            }
             match self.git_type {
-                Provider::Github => {
-                    if env::var("GITHUB_TOKEN").is_err() {
-                        return Err(AppError::MissingGithubToken);
-                    }
-                }
-                Provider::Gitlab => {
-                    if env::var("GITLAB_TOKEN").is_err() {
-                        return Err(AppError::MissingGitlabToken);
-                    }
-                }
-                Provider::Codeberg => {
-                    if env::var("CODEBERG_TOKEN").is_err() {
-                        return Err(AppError::MissingCodebergToken);
-                    }
-                }
-                Provider::None => {}
+                Provider::Github => return Err(AppError::MissingGithubToken),
+                Provider::Gitlab => return Err(AppError::MissingGitlabToken),
+                Provider::Codeberg => return Err(AppError::MissingCodebergToken),
+                Provider::AzureDevops => return Err(AppError::MissingAzureDevopsToken),
+                Provider::BitbucketServer => return Err(AppError::MissingBitbucketServerToken),
+                Provider::Gogs => return Err(AppError::MissingGogsToken),
+                Provider::None | Provider::Custom(_) => {}
             }
         }
         Ok(())
     }
+
+    /// The host of a GitLab project, gitlab.com unless `host` is set.
+    pub fn gitlab_host(&self) -> &str {
+        self.host.as_deref().unwrap_or("gitlab.com")
+    }
+
+    /// The host of an Azure DevOps repository, dev.azure.com unless `host` is set.
+    pub fn azure_devops_host(&self) -> &str {
+        self.host.as_deref().unwrap_or("dev.azure.com")
+    }
+
+    /// The API token from `token_file`, or else from `token_env` or the token
+    /// variable of the provider (or the file named by `<VARIABLE>_FILE`), or
+    /// else the token stored by `login`, or else that of the gh CLI if enabled,
+    /// or else the password of the provider's API host in `~/.netrc`.
+    pub fn token(&self) -> Result<Option<String>, AppError> {
+        if let Some(path) = &self.token_file {
+            return env::read_secret(path).map(Some);
+        }
+        let Some(name) = self.token_env.as_deref().or(self.git_type.token_env()) else {
+            return Ok(None);
+        };
+        if let Some(token) = env::secret(name)? {
+            return Ok(Some(token));
+        }
+        if let Some(token) = login::stored_token(&self.git_type)? {
+            return Ok(Some(token));
+        }
+        if self.gh_cli && self.git_type == Provider::Github {
+            if let Some(token) = login::gh_cli_token() {
+                return Ok(Some(token));
+            }
+        }
+        // Without the context path of a server like `example.com/bitbucket`
+        let hosts = match &self.host {
+            Some(host) => vec![host.split('/').next().unwrap_or(host)],
+            None => self.git_type.hosts().to_vec(),
+        };
+        Ok(hosts
+            .iter()
+            .find_map(|host| netrc::lookup(host))
+            .map(|(_, password)| password))
+    }
+
+    /// The token of the CI job running this, for repositories on the instance
+    /// running the job: `CI_JOB_TOKEN` of GitLab CI for GitLab projects, or
+    /// `SYSTEM_ACCESSTOKEN` of Azure Pipelines for Azure DevOps repositories.
+    pub fn job_token(&self) -> Option<String> {
+        let (variable, server, host) = match self.git_type {
+            Provider::Gitlab => (
+                JOB_TOKEN_ENV,
+                std::env::var("CI_SERVER_HOST").ok(),
+                self.gitlab_host(),
+            ),
+            Provider::AzureDevops => (
+                AZURE_JOB_TOKEN_ENV,
+                std::env::var("SYSTEM_COLLECTIONURI")
+                    .ok()
+                    .and_then(|uri| reqwest::Url::parse(&uri).ok())
+                    .and_then(|uri| uri.host_str().map(str::to_string)),
+                self.azure_devops_host(),
+            ),
+            _ => return None,
+        };
+        let token = std::env::var(variable).ok().filter(|t| !t.is_empty())?;
+        server
+            .is_none_or(|server| host.split('/').next() == Some(server.as_str()))
+            .then_some(token)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
-#[serde(rename_all = "lowercase")]
+#[serde(from = "String", into = "String")]
 pub enum Provider {
     Github,
     Gitlab,
     Codeberg,
+    AzureDevops,
+    BitbucketServer,
+    Gogs,
     None,
+    /// A provider added with [`register_provider`]
+    Custom(String),
 }
 
-enum ApiType<'a> {
-    Github { repo: &'a str },
-    Codeberg { repo: &'a str },
-    Gitlab { project_id: u64 },
+impl From<String> for Provider {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "github" => Provider::Github,
+            "gitlab" => Provider::Gitlab,
+            "codeberg" => Provider::Codeberg,
+            "azure_devops" => Provider::AzureDevops,
+            "bitbucket_server" => Provider::BitbucketServer,
+            "gogs" => Provider::Gogs,
+            "none" => Provider::None,
+            _ => Provider::Custom(name),
+        }
+    }
+}
+
+impl From<Provider> for String {
+    fn from(provider: Provider) -> Self {
+        provider.name().to_string()
+    }
 }
 
-impl ApiType<'_> {
-    fn get_request_details(&self, token: Option<String>) -> (String, Option<(String, String)>) {
+impl Provider {
+    /// The `type` the provider is configured and registered with.
+    pub fn name(&self) -> &str {
         match self {
-            ApiType::Codeberg { repo } => (
-                format!("https://codeberg.org/api/v1/repos/{}/releases/latest", repo),
-                token.map(|t| ("Authorization".to_string(), format!("Bearer {}", t))),
-            ),
-            ApiType::Github { repo } => (
-                format!("https://api.github.com/repos/{}/releases/latest", repo),
-                token.map(|t| ("Authorization".to_string(), format!("Bearer {}", t))),
-            ),
-            ApiType::Gitlab { project_id } => (
-                format!(
-                    "https://gitlab.com/api/v4/projects/{}/releases/permalink/latest",
-                    project_id
-                ),
-                token.map(|t| ("PRIVATE-TOKEN".to_string(), t)),
-            ),
+            Provider::Github => "github",
+            Provider::Gitlab => "gitlab",
+            Provider::Codeberg => "codeberg",
+            Provider::AzureDevops => "azure_devops",
+            Provider::BitbucketServer => "bitbucket_server",
+            Provider::Gogs => "gogs",
+            Provider::None => "none",
+            Provider::Custom(name) => name,
         }
     }
-}
 
-impl fmt::Display for ApiType<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    /// The API host and the web host of the provider.
+    fn hosts(&self) -> &'static [&'static str] {
+        match self {
+            Provider::Github => &["api.github.com", "github.com"],
+            Provider::Gitlab => &["gitlab.com"],
+            Provider::Codeberg => &["codeberg.org"],
+            Provider::AzureDevops => &["dev.azure.com"],
+            Provider::BitbucketServer | Provider::Gogs | Provider::None | Provider::Custom(_) => {
+                &[]
+            }
+        }
+    }
+
+    /// The environment variable holding the API token.
+    pub fn token_env(&self) -> Option<&'static str> {
         match self {
-            ApiType::Github { repo } => write!(f, "GitHub({})", repo),
-            ApiType::Codeberg { repo } => write!(f, "Codeberg({})", repo),
-            ApiType::Gitlab { project_id } => write!(f, "GitLab({})", project_id),
+            Provider::Github => Some("GITHUB_TOKEN"),
+            Provider::Gitlab => Some("GITLAB_TOKEN"),
+            Provider::Codeberg => Some("CODEBERG_TOKEN"),
+            Provider::AzureDevops => Some("AZURE_DEVOPS_TOKEN"),
+            Provider::BitbucketServer => Some("BITBUCKET_SERVER_TOKEN"),
+            Provider::Gogs => Some("GOGS_TOKEN"),
+            Provider::None | Provider::Custom(_) => None,
         }
     }
 }
 
-fn extract_version(tag_name: &str, filter: &str, api_type: ApiType<'_>) -> Result<String> {
-    let re = Regex::new(filter).unwrap();
-    let version = re
+/// The compiled `version_filter` of a service.
+fn version_filter(filter: &str) -> Result<Regex, AppError> {
+    Regex::new(filter).map_err(|e| AppError::ConfigError(format!("Invalid version_filter: {}", e)))
+}
+
+fn extract_version(
+    tag_name: &str,
+    filter: &str,
+    provider: &str,
+    config: &GitConfig,
+) -> Result<String> {
+    let version = version_filter(filter)?
         .captures(tag_name)
         .and_then(|cap| cap.get(1))
         .map(|m| m.as_str().to_string())
         .unwrap_or_default();
 
+    debug!("Filter {:?} matched {:?}", filter, version);
     if version.is_empty() {
-        error!("No matching version for {}", api_type);
-        return Err(AppError::NotFound(format!("No matching version for {}", api_type)).into());
+        let api = format!("{}({})", provider, config);
+        error!("No matching version for {}", api);
+        return Err(AppError::NotFound(format!("No matching version for {}", api)).into());
     }
     Ok(version)
 }
@@ -0,0 +1,66 @@
+use sd_notify::NotifyState;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// Default time between heartbeats while waiting for the next check.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Liveness reporting of the check loop through a heartbeat file and the
+/// systemd notify protocol (`Type=notify`, `WatchdogSec=`).
+///
+/// Heartbeats are only sent by the loop itself, so a wedged check stops them.
+pub struct Heartbeat {
+    file: Option<PathBuf>,
+    interval: Duration,
+}
+
+impl Heartbeat {
+    pub fn new(file: Option<PathBuf>) -> Self {
+        let mut usec = 0;
+        let interval = if sd_notify::watchdog_enabled(false, &mut usec) {
+            // systemd recommends pinging at half the watchdog timeout
+            HEARTBEAT_INTERVAL.min(Duration::from_micros(usec) / 2)
+        } else {
+            HEARTBEAT_INTERVAL
+        };
+        Self { file, interval }
+    }
+
+    /// Time until the next heartbeat is due.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Tells systemd that the service finished starting up.
+    pub fn ready(&self) {
+        if let Err(e) = sd_notify::notify(false, &[NotifyState::Ready]) {
+            warn!("Failed to notify systemd: {}", e);
+        }
+    }
+
+    /// Tells systemd that the service is shutting down.
+    pub fn stopping(&self) {
+        if let Err(e) = sd_notify::notify(false, &[NotifyState::Stopping]) {
+            warn!("Failed to notify systemd: {}", e);
+        }
+    }
+
+    /// Writes the current Unix time to the heartbeat file and pings the
+    /// systemd watchdog.
+    pub fn beat(&self) {
+        if let Some(file) = &self.file {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            if let Err(e) = fs::write(file, format!("{}\n", now)) {
+                warn!("Failed to write heartbeat file {}: {}", file.display(), e);
+            }
+        }
+        if let Err(e) = sd_notify::notify(false, &[NotifyState::Watchdog]) {
+            debug!("Failed to ping the systemd watchdog: {}", e);
+        }
+    }
+}
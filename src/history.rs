@@ -0,0 +1,123 @@
+//! The timeline of the versions each service resolved to, kept in the
+//! `--history-file` as JSON lines with one entry per change.
+
+use super::config::OutputData;
+use super::error::AppError;
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Entry {
+    /// When the change was seen, in RFC 3339
+    pub time: String,
+    pub service: String,
+    pub version: String,
+    pub tag: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+}
+
+impl Entry {
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.time)
+            .ok()
+            .map(|time| time.with_timezone(&Utc))
+    }
+}
+
+/// Reads all entries, oldest first. A missing file has none.
+pub fn read(path: &Path) -> Result<Vec<Entry>, AppError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(history_error(path, e)),
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(number, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                AppError::HistoryError(format!("{}:{}: {}", path.display(), number + 1, e))
+            })
+        })
+        .collect()
+}
+
+/// Appends an entry for each resolved service whose version, tag or digest
+/// differs from its latest entry, and returns how many were added.
+pub fn record(path: &Path, output: &OutputData) -> Result<usize, AppError> {
+    let mut latest: HashMap<String, Entry> = HashMap::new();
+    for entry in read(path)? {
+        latest.insert(entry.service.clone(), entry);
+    }
+
+    let time = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let mut lines = String::new();
+    let mut added = 0;
+    for (service, version) in output {
+        let Some(tag) = version.tag() else {
+            continue;
+        };
+        let entry = Entry {
+            time: time.clone(),
+            service: service.clone(),
+            version: version.version().unwrap_or(tag).to_string(),
+            tag: tag.to_string(),
+            digest: version.digest().map(str::to_string),
+        };
+        let unchanged = latest.get(service).is_some_and(|last| {
+            (&last.version, &last.tag, &last.digest) == (&entry.version, &entry.tag, &entry.digest)
+        });
+        if unchanged {
+            continue;
+        }
+        let line = serde_json::to_string(&entry).map_err(|e| history_error(path, e))?;
+        lines.push_str(&line);
+        lines.push('\n');
+        added += 1;
+    }
+    if added > 0 {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .map_err(|e| history_error(path, e))?;
+    }
+    Ok(added)
+}
+
+fn history_error(path: &Path, e: impl std::fmt::Display) -> AppError {
+    AppError::HistoryError(format!("{}: {}", path.display(), e))
+}
+
+/// Parses a `--since` value: a date like `2024-05-01`, an RFC 3339 time or a
+/// duration before now like `7d`.
+pub fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+        return midnight
+            .and_local_timezone(Local)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+            .ok_or_else(|| format!("{} does not exist in the local time zone", value));
+    }
+    let duration = humantime::parse_duration(value).map_err(|_| {
+        format!(
+            "expected a date, RFC 3339 time or duration, got '{}'",
+            value
+        )
+    })?;
+    chrono::Duration::from_std(duration)
+        .ok()
+        .and_then(|duration| Utc::now().checked_sub_signed(duration))
+        .ok_or_else(|| format!("{} is too long ago", value))
+}
@@ -0,0 +1,385 @@
+use super::cache::Cache;
+use super::dns::{self, DnsConfig};
+use super::error::AppError;
+use super::proxy::{self, ProxyConfig};
+use super::rate_limit::{default_rate_limits, Budget, BudgetTracker, RateLimiter};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::OnceCell;
+use tracing::{debug, warn};
+
+/// Waits longer than this (e.g. from `Retry-After`) are not worth retrying for.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Why `HttpClient::send` returned no response.
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    /// The client is offline, no request is sent
+    #[error("Not available offline: {0}")]
+    Offline(String),
+}
+
+/// Results of the lookups of a run by key, see `HttpClient::coalesce`.
+type Lookups = Mutex<HashMap<String, Arc<OnceCell<Result<String, Arc<anyhow::Error>>>>>>;
+
+tokio::task_local! {
+    /// Requests sent and cache entries used within `track_requests`.
+    static STATS: Cell<RequestStats>;
+}
+
+/// What answering the requests of a future took, see `track_requests`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RequestStats {
+    /// Requests sent, counting each retry
+    pub requests: u32,
+    /// When the oldest expired cache entry used offline was written
+    pub oldest_cached: Option<SystemTime>,
+}
+
+/// Runs `future` and returns its output with the requests it sent and the
+/// expired cache entries it used.
+pub async fn track_requests<F: Future>(future: F) -> (F::Output, RequestStats) {
+    STATS
+        .scope(Cell::new(RequestStats::default()), async {
+            let output = future.await;
+            (output, STATS.with(Cell::get))
+        })
+        .await
+}
+
+fn record(update: impl FnOnce(&mut RequestStats)) {
+    let _ = STATS.try_with(|stats| {
+        let mut current = stats.get();
+        update(&mut current);
+        stats.set(current);
+    });
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HttpConfig {
+    /// Limit for a whole request, including reading the response
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+    #[serde(default = "default_connect_timeout", with = "humantime_serde")]
+    pub connect_timeout: Duration,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// Maximum requests per second by host, shared by all services
+    #[serde(default = "default_rate_limits")]
+    pub rate_limits: BTreeMap<String, f64>,
+    /// Base URLs replacing `https://<host>` in requests to a host, e.g. for
+    /// GitHub Enterprise, a registry mirror or a mock server in tests
+    #[serde(default)]
+    pub base_urls: BTreeMap<String, String>,
+    #[serde(default)]
+    pub dns: DnsConfig,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout: default_timeout(),
+            connect_timeout: default_connect_timeout(),
+            retry: RetryConfig::default(),
+            proxy: ProxyConfig::default(),
+            rate_limits: default_rate_limits(),
+            base_urls: BTreeMap::new(),
+            dns: DnsConfig::default(),
+        }
+    }
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_connect_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetryConfig {
+    /// Total attempts per request, 1 disables retries
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further one
+    #[serde(default = "default_backoff", with = "humantime_serde")]
+    pub backoff: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            backoff: default_backoff(),
+        }
+    }
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_backoff() -> Duration {
+    Duration::from_millis(500)
+}
+
+/// HTTP client for the git provider and registry APIs that retries transient
+/// failures: connection errors, timeouts, 5xx and 429 responses.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    retry: RetryConfig,
+    limiter: RateLimiter,
+    budgets: BudgetTracker,
+    cache: Option<Cache>,
+    base_urls: Arc<BTreeMap<String, String>>,
+    offline: bool,
+    refresh: bool,
+    lookups: Arc<Lookups>,
+}
+
+/// A client with the timeouts, name resolution and proxy of `config`, for the
+/// requests that don't go through `HttpClient`, like webhook notifications.
+pub(crate) fn client(config: &HttpConfig) -> Result<Client, AppError> {
+    let builder = Client::builder()
+        .timeout(config.timeout)
+        .connect_timeout(config.connect_timeout);
+    let builder = dns::apply(builder, &config.dns)?;
+    proxy::apply(builder, &config.proxy)?
+        .build()
+        .map_err(|e| AppError::RequestError(format!("Failed to create HTTP client: {}", e)))
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig, cache: Option<Cache>) -> Result<Self, AppError> {
+        Ok(Self {
+            client: client(config)?,
+            retry: config.retry.clone(),
+            limiter: RateLimiter::new(&config.rate_limits),
+            budgets: BudgetTracker::default(),
+            cache,
+            base_urls: Arc::new(config.base_urls.clone()),
+            offline: false,
+            refresh: false,
+            lookups: Arc::default(),
+        })
+    }
+
+    /// Sends requests for `https://<host>` to `base_url` instead.
+    pub fn with_base_url(mut self, host: &str, base_url: &str) -> Self {
+        Arc::make_mut(&mut self.base_urls).insert(host.to_string(), base_url.to_string());
+        self
+    }
+
+    /// Sends no requests, answering only from the cache, where expired
+    /// entries are used as well.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Sends the requests instead of answering them from the cache, which
+    /// still stores the responses. Has no effect offline.
+    pub fn with_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Fails offline, to be called before sending a request for `url`.
+    pub fn ensure_online(&self, url: &str) -> Result<(), AppError> {
+        match self.offline {
+            true => Err(AppError::Offline(url.to_string())),
+            false => Ok(()),
+        }
+    }
+
+    /// A clone that shares the results of `coalesce` only with its own
+    /// clones, for a new run.
+    pub fn new_run(&self) -> Self {
+        Self {
+            lookups: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Runs `lookup` once for all clones of the client, so that services
+    /// sharing a repository or image tag share its result. Concurrent calls
+    /// with the same `key` wait for the first; failures are shared as well,
+    /// wrapped in `AppError::Coalesced`.
+    pub async fn coalesce<E>(
+        &self,
+        key: &str,
+        lookup: impl Future<Output = Result<String, E>>,
+    ) -> Result<String, AppError>
+    where
+        E: Into<anyhow::Error>,
+    {
+        let cell = self
+            .lookups
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        let mut shared = true;
+        let result = cell
+            .get_or_init(|| {
+                shared = false;
+                async { lookup.await.map_err(|e| Arc::new(e.into())) }
+            })
+            .await;
+        if shared {
+            debug!("Sharing the result of {}", key);
+        }
+        result.clone().map_err(AppError::Coalesced)
+    }
+
+    /// Returns a cached response body or result stored under `key`.
+    pub fn cached(&self, key: &str) -> Option<String> {
+        let cache = self.cache.as_ref()?;
+        if self.refresh && !self.offline {
+            return None;
+        }
+        if let Some(value) = cache.get(key) {
+            return Some(value);
+        }
+        if !self.offline {
+            return None;
+        }
+        let (value, written) = cache.get_expired(key)?;
+        record(|stats| {
+            stats.oldest_cached = Some(stats.oldest_cached.map_or(written, |t| t.min(written)));
+        });
+        Some(value)
+    }
+
+    pub fn store(&self, key: &str, value: &str) {
+        if let Some(cache) = &self.cache {
+            cache.put(key, value);
+        }
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.client.get(self.resolve(url))
+    }
+
+    pub fn head(&self, url: &str) -> RequestBuilder {
+        self.client.head(self.resolve(url))
+    }
+
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.client.post(self.resolve(url))
+    }
+
+    /// Applies the base URL configured for the host of `url`, if any.
+    fn resolve(&self, url: &str) -> String {
+        for (host, base_url) in self.base_urls.iter() {
+            let Some(rest) = url
+                .strip_prefix("https://")
+                .and_then(|url| url.strip_prefix(host.as_str()))
+            else {
+                continue;
+            };
+            if rest.is_empty() || rest.starts_with(['/', '?']) {
+                return format!("{}{}", base_url.trim_end_matches('/'), rest);
+            }
+        }
+        url.to_string()
+    }
+
+    /// Sends a request within the rate limit of its host, retrying with
+    /// exponential backoff and jitter. A `Retry-After` header takes precedence
+    /// over the backoff. Offline, nothing is sent.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, SendError> {
+        if self.offline {
+            let url = request
+                .try_clone()
+                .and_then(|request| request.build().ok())
+                .map_or_else(String::new, |request| request.url().to_string());
+            return Err(SendError::Offline(url));
+        }
+        let mut attempt = 1;
+        loop {
+            // Requests with streaming bodies can't be cloned and are sent once
+            let Some(current) = request.try_clone() else {
+                return Ok(self.execute(request).await?);
+            };
+            let last_attempt = attempt >= self.retry.max_attempts;
+
+            let (reason, delay) = match self.execute(current).await {
+                Ok(response) if !last_attempt && is_transient(response.status()) => {
+                    let delay = retry_after(&response).unwrap_or_else(|| self.backoff(attempt));
+                    if delay > MAX_RETRY_DELAY {
+                        return Ok(response);
+                    }
+                    (
+                        format!("{} returned {}", response.url(), response.status()),
+                        delay,
+                    )
+                }
+                Err(e) if !last_attempt && (e.is_timeout() || e.is_connect()) => {
+                    (e.to_string(), self.backoff(attempt))
+                }
+                result => return Ok(result?),
+            };
+
+            warn!(
+                "{}, retrying in {}ms (attempt {}/{})",
+                reason,
+                delay.as_millis(),
+                attempt + 1,
+                self.retry.max_attempts
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn execute(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        self.limiter.acquire(request.url()).await;
+        record(|stats| stats.requests += 1);
+        let url = request.url().clone();
+        let response = client.execute(request).await?;
+        self.budgets.observe(&url, response.headers());
+        Ok(response)
+    }
+
+    /// The lowest remaining request quota each host reported so far.
+    pub fn budgets(&self) -> BTreeMap<String, Budget> {
+        self.budgets.budgets()
+    }
+
+    /// Exponential backoff with up to 50% random jitter, so parallel requests
+    /// failing together don't retry in lockstep.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.retry.backoff * 2u32.pow(attempt.saturating_sub(1).min(10));
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        let jitter = delay.mul_f64(f64::from(nanos % 1000) / 2000.0);
+        (delay + jitter).min(MAX_RETRY_DELAY)
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Parses a `Retry-After` header given in seconds.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    seconds.trim().parse().ok().map(Duration::from_secs)
+}
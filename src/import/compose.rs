@@ -0,0 +1,38 @@
+use super::{ImportedTarget, Importer};
+use crate::error::AppError;
+use serde_yaml::Value;
+use std::fs;
+use std::path::Path;
+use tracing::{debug, info};
+
+/// Imports the image of every service in a compose file, named after the
+/// compose service and with the compose file as update target. Services that
+/// are only built locally are skipped.
+pub fn import(file: &Path, importer: &mut Importer) -> Result<(), AppError> {
+    info!("Importing compose file {}", file.display());
+    let compose: Value = serde_yaml::from_str(&fs::read_to_string(file)?)?;
+    let Some(services) = compose.get("services").and_then(Value::as_mapping) else {
+        return Err(AppError::ImportError(format!(
+            "{} has no services",
+            file.display()
+        )));
+    };
+    for (name, service) in services {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        let Some(image) = service.get("image").and_then(Value::as_str) else {
+            debug!("Skipping compose service {} without image", name);
+            continue;
+        };
+        importer.add_service(
+            Some(name),
+            image,
+            Some(ImportedTarget {
+                target_type: "compose",
+                file: file.to_path_buf(),
+            }),
+        );
+    }
+    Ok(())
+}
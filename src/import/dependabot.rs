@@ -0,0 +1,135 @@
+use super::{ImportedTarget, Importer};
+use crate::error::AppError;
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use tracing::{debug, info, warn};
+
+// `FROM [--platform=...] image[:tag] [AS name]`
+static FROM_LINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)^\s*FROM\s+(?:--\S+\s+)*(\S+)(?:\s+AS\s+(\S+))?").unwrap());
+
+#[derive(Deserialize)]
+struct DependabotConfig {
+    #[serde(default)]
+    updates: Vec<UpdateEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct UpdateEntry {
+    package_ecosystem: String,
+    #[serde(default)]
+    directory: Option<String>,
+    #[serde(default)]
+    directories: Vec<String>,
+}
+
+/// Imports the `docker` and `docker-compose` ecosystems of a Dependabot config.
+/// Like Dependabot, only the listed directories are scanned, not their subdirectories.
+pub fn import(file: &Path, root: &Path, importer: &mut Importer) -> Result<(), AppError> {
+    info!("Importing Dependabot config {}", file.display());
+    let config: DependabotConfig = serde_yaml::from_str(&fs::read_to_string(file)?)?;
+
+    for entry in config.updates {
+        let compose = match entry.package_ecosystem.as_str() {
+            "docker" => false,
+            "docker-compose" => true,
+            other => {
+                debug!("Skipping {} ecosystem", other);
+                continue;
+            }
+        };
+        for directory in entry.directory.iter().chain(&entry.directories) {
+            if directory.contains('*') {
+                warn!("Skipping directory glob {}", directory);
+                continue;
+            }
+            let dir = root.join(directory.trim_start_matches('/'));
+            for path in scan(&dir, compose)? {
+                if compose {
+                    import_compose(&path, importer)?;
+                } else {
+                    import_dockerfile(&path, importer)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Lists the Dockerfiles or compose files directly inside `dir`.
+fn scan(dir: &Path, compose: bool) -> Result<Vec<PathBuf>, AppError> {
+    let mut files = Vec::new();
+    let entries = fs::read_dir(dir)
+        .map_err(|e| AppError::ImportError(format!("{}: {}", dir.display(), e)))?;
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let matches = if compose {
+            (name.starts_with("docker-compose") || name.starts_with("compose"))
+                && (name.ends_with(".yml") || name.ends_with(".yaml"))
+        } else {
+            name.starts_with("Dockerfile")
+                || name.ends_with(".Dockerfile")
+                || name.starts_with("Containerfile")
+        };
+        if matches && path.is_file() {
+            files.push(path.strip_prefix("./").unwrap_or(&path).to_path_buf());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn import_dockerfile(path: &Path, importer: &mut Importer) -> Result<(), AppError> {
+    debug!("Scanning {}", path.display());
+    let mut stages = Vec::new();
+    for line in fs::read_to_string(path)?.lines() {
+        let Some(captures) = FROM_LINE.captures(line) else {
+            continue;
+        };
+        let reference = &captures[1];
+        // `FROM builder` refers to an earlier stage, not an image
+        if !stages
+            .iter()
+            .any(|stage: &String| stage.eq_ignore_ascii_case(reference))
+        {
+            importer.add_image(
+                reference,
+                Some(ImportedTarget {
+                    target_type: "dockerfile",
+                    file: path.to_path_buf(),
+                }),
+            );
+        }
+        if let Some(stage) = captures.get(2) {
+            stages.push(stage.as_str().to_string());
+        }
+    }
+    Ok(())
+}
+
+fn import_compose(path: &Path, importer: &mut Importer) -> Result<(), AppError> {
+    debug!("Scanning {}", path.display());
+    let compose: serde_yaml::Value = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    let Some(services) = compose.get("services").and_then(|s| s.as_mapping()) else {
+        return Ok(());
+    };
+    for service in services.values() {
+        if let Some(image) = service.get("image").and_then(|image| image.as_str()) {
+            importer.add_image(
+                image,
+                Some(ImportedTarget {
+                    target_type: "compose",
+                    file: path.to_path_buf(),
+                }),
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use super::Importer;
+use crate::error::AppError;
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+#[derive(Deserialize)]
+struct DiunConfig {
+    #[serde(default)]
+    providers: Providers,
+}
+
+#[derive(Deserialize, Default)]
+struct Providers {
+    #[serde(default)]
+    file: Option<FileProvider>,
+    #[serde(flatten)]
+    other: serde_yaml::Mapping,
+}
+
+#[derive(Deserialize)]
+struct FileProvider {
+    #[serde(default)]
+    filename: Option<PathBuf>,
+    #[serde(default)]
+    directory: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct WatchedImage {
+    name: String,
+}
+
+/// Imports the images of a Diun file provider. `file` is either the Diun config,
+/// whose `providers.file` entry is followed, or an images file itself.
+pub fn import(file: &Path, importer: &mut Importer) -> Result<(), AppError> {
+    info!("Importing Diun config {}", file.display());
+    let content: Value = serde_yaml::from_str(&fs::read_to_string(file)?)?;
+    if content.is_sequence() {
+        return import_images(file, importer);
+    }
+
+    let config: DiunConfig = serde_yaml::from_value(content)?;
+    for provider in config.providers.other.keys().filter_map(Value::as_str) {
+        warn!(
+            "Skipping {} provider, only the file provider can be imported",
+            provider
+        );
+    }
+    let Some(provider) = config.providers.file else {
+        return Err(AppError::ImportError(format!(
+            "{} has no file provider",
+            file.display()
+        )));
+    };
+
+    // Relative paths are resolved against the config file
+    let base = file.parent().unwrap_or(Path::new(""));
+    if let Some(filename) = provider.filename {
+        import_images(&base.join(filename), importer)?;
+    }
+    if let Some(directory) = provider.directory {
+        let directory = base.join(directory);
+        let entries = fs::read_dir(&directory)
+            .map_err(|e| AppError::ImportError(format!("{}: {}", directory.display(), e)))?;
+        let mut files = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "yml" || extension == "yaml")
+            {
+                files.push(path);
+            }
+        }
+        files.sort();
+        for path in files {
+            import_images(&path, importer)?;
+        }
+    }
+    Ok(())
+}
+
+fn import_images(file: &Path, importer: &mut Importer) -> Result<(), AppError> {
+    debug!("Reading images from {}", file.display());
+    let images: Vec<WatchedImage> = serde_yaml::from_str(&fs::read_to_string(file)?)?;
+    for image in images {
+        importer.add_image(&image.name, None);
+    }
+    Ok(())
+}
@@ -0,0 +1,23 @@
+use super::Importer;
+use crate::discovery::kubernetes;
+use crate::error::AppError;
+use tracing::info;
+
+/// Imports the container images of the workloads in `namespaces`, or in all
+/// namespaces if none are given. Services are named after their workload, or
+/// after workload and container for pods with several containers.
+pub fn import(namespaces: &[String], importer: &mut Importer) -> Result<(), AppError> {
+    info!("Importing Kubernetes workloads");
+    for workload in kubernetes::workloads(namespaces)? {
+        let single = workload.containers.len() == 1;
+        for (container, image) in &workload.containers {
+            let name = if single {
+                workload.name.clone()
+            } else {
+                format!("{}-{}", workload.name, container)
+            };
+            importer.add_service(Some(&name), image, None);
+        }
+    }
+    Ok(())
+}
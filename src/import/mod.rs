@@ -0,0 +1,231 @@
+//! Generates version-updater configuration from the configs of other tools.
+
+mod compose;
+mod dependabot;
+mod diun;
+#[cfg(feature = "kubernetes")]
+mod kubernetes;
+
+use super::config::ImportSource;
+use super::error::AppError;
+use super::updater::split_image_ref;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use tracing::{info, warn};
+
+static VERSION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d+(?:\.\d+)*").unwrap());
+
+#[derive(Serialize)]
+struct ImportedConfig {
+    global: ImportedGlobal,
+    services: BTreeMap<String, ImportedService>,
+}
+
+#[derive(Serialize)]
+struct ImportedGlobal {
+    git: serde_yaml::Value,
+}
+
+#[derive(Serialize)]
+pub struct ImportedService {
+    git: ImportedGit,
+    image: ImportedImage,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    targets: Vec<ImportedTarget>,
+}
+
+#[derive(Serialize)]
+struct ImportedGit {
+    #[serde(rename = "type")]
+    git_type: &'static str,
+    repo: String,
+}
+
+#[derive(Serialize)]
+struct ImportedImage {
+    name: String,
+    tag: String,
+}
+
+#[derive(Serialize)]
+pub struct ImportedTarget {
+    #[serde(rename = "type")]
+    pub target_type: &'static str,
+    pub file: PathBuf,
+}
+
+/// Collects the imported services, merging references to the same image.
+#[derive(Default)]
+pub struct Importer {
+    services: BTreeMap<String, ImportedService>,
+    /// Write `TODO/<name>` instead of guessed GitHub repositories
+    placeholder_repos: bool,
+}
+
+impl Importer {
+    /// An importer that leaves placeholders for git repositories that can't
+    /// be derived from the image name.
+    pub fn with_placeholder_repos() -> Self {
+        Self {
+            placeholder_repos: true,
+            ..Self::default()
+        }
+    }
+
+    /// Adds an image reference like `nginx:1.25-alpine`, optionally found in `target`.
+    pub fn add_image(&mut self, reference: &str, target: Option<ImportedTarget>) {
+        self.add_service(None, reference, target);
+    }
+
+    /// Adds an image reference, naming the service `name` instead of after the image.
+    pub fn add_service(
+        &mut self,
+        service: Option<&str>,
+        reference: &str,
+        target: Option<ImportedTarget>,
+    ) {
+        let (name, tag) = split_image_ref(reference);
+        if name.contains('$') || name == "scratch" {
+            return;
+        }
+
+        if let Some(service) = self
+            .services
+            .values_mut()
+            .find(|service| service.image.name == name)
+        {
+            if let Some(target) = target {
+                if !service.targets.iter().any(|t| t.file == target.file) {
+                    service.targets.push(target);
+                }
+            }
+            return;
+        }
+
+        let base = service_name(service.unwrap_or(name));
+        let mut service_name = base.clone();
+        let mut suffix = 2;
+        while self.services.contains_key(&service_name) {
+            service_name = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+
+        let (git_type, mut repo) = guess_repo(name);
+        if git_type == "github" && !name.starts_with("ghcr.io/") {
+            if self.placeholder_repos {
+                let repo_name = repo.rsplit('/').next().unwrap_or(&repo);
+                repo = format!("TODO/{}", repo_name);
+                warn!(
+                    "Set the git repository of service {} for image {}",
+                    service_name, name
+                );
+            } else {
+                warn!(
+                    "Guessed git repository {} for image {}, please review",
+                    repo, name
+                );
+            }
+        }
+        info!("Imported service {} for image {}", service_name, name);
+        self.services.insert(
+            service_name,
+            ImportedService {
+                git: ImportedGit { git_type, repo },
+                image: ImportedImage {
+                    name: name.to_string(),
+                    tag: tag_template(tag.unwrap_or("latest")),
+                },
+                targets: target.into_iter().collect(),
+            },
+        );
+    }
+
+    /// Renders the imported services as a config file.
+    pub fn render(self) -> Result<String, AppError> {
+        let config = ImportedConfig {
+            global: ImportedGlobal {
+                git: serde_yaml::from_str("github:\n  authenticate: false")?,
+            },
+            services: self.services,
+        };
+        Ok(serde_yaml::to_string(&config)?)
+    }
+}
+
+/// Runs an importer and returns the generated config file.
+pub fn run(source: &ImportSource) -> Result<String, AppError> {
+    let mut importer = match source {
+        ImportSource::Compose { .. } | ImportSource::Kubernetes { .. } => {
+            Importer::with_placeholder_repos()
+        }
+        _ => Importer::default(),
+    };
+    match source {
+        ImportSource::Dependabot { file, root } => {
+            let root = match root {
+                Some(root) => root.clone(),
+                // `.github/dependabot.yml` lives two levels below the repository root
+                None => file
+                    .parent()
+                    .and_then(Path::parent)
+                    .filter(|root| !root.as_os_str().is_empty())
+                    .unwrap_or(Path::new("."))
+                    .to_path_buf(),
+            };
+            dependabot::import(file, &root, &mut importer)?;
+        }
+        ImportSource::Diun { file } => diun::import(file, &mut importer)?,
+        ImportSource::Compose { file } => compose::import(file, &mut importer)?,
+        #[cfg(feature = "kubernetes")]
+        ImportSource::Kubernetes { namespace } => kubernetes::import(namespace, &mut importer)?,
+        #[cfg(not(feature = "kubernetes"))]
+        ImportSource::Kubernetes { .. } => {
+            return Err(AppError::FeatureDisabled("Kubernetes import", "kubernetes"))
+        }
+    }
+    importer.render()
+}
+
+/// Derives a service name from the last path segment of an image name.
+fn service_name(image: &str) -> String {
+    image
+        .rsplit('/')
+        .next()
+        .unwrap_or(image)
+        .to_lowercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "-")
+}
+
+/// Guesses the source repository of an image. Images on ghcr.io and codeberg.org
+/// map to their owner and name, anything else is assumed to live on GitHub.
+fn guess_repo(image: &str) -> (&'static str, String) {
+    let parts: Vec<&str> = image.split('/').collect();
+    match parts.as_slice() {
+        ["ghcr.io", owner, name, ..] => ("github", format!("{}/{}", owner, name)),
+        ["codeberg.org", owner, name, ..] => ("codeberg", format!("{}/{}", owner, name)),
+        [registry, rest @ ..] if registry.contains('.') && rest.len() >= 2 => (
+            "github",
+            format!("{}/{}", rest[rest.len() - 2], rest[rest.len() - 1]),
+        ),
+        [owner, name] => ("github", format!("{}/{}", owner, name)),
+        _ => {
+            let name = parts.last().copied().unwrap_or(image);
+            ("github", format!("{0}/{0}", name))
+        }
+    }
+}
+
+/// Turns a current tag like `v1.25.3-alpine` into a template like `v${RELEASE_VERSION}-alpine`.
+pub(crate) fn tag_template(tag: &str) -> String {
+    match VERSION.find(tag) {
+        Some(version) => format!(
+            "{}${{RELEASE_VERSION}}{}",
+            &tag[..version.start()],
+            &tag[version.end()..]
+        ),
+        None => "${RELEASE_VERSION}".to_string(),
+    }
+}
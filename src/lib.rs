@@ -0,0 +1,77 @@
+//! Resolves the latest released versions of services from their git hosting
+//! providers and container registries, and updates the files referencing them.
+//!
+//! The `version-updater` binary is a thin CLI over this crate. To resolve
+//! versions from your own code, load an [`AppConfig`] from the same arguments
+//! the binary accepts and process its services:
+//!
+//! ```no_run
+//! use clap::Parser;
+//! use version_updater::{service, AppConfig, Args};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let args = Args::parse_from(["version-updater", "-c", "services.yaml", "-o", "-"]);
+//! let config = AppConfig::load_config(args)?;
+//! for (name, version) in service::process_services(&config, None).await? {
+//!     println!("{}: {:?}", name, version.version());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Single services are resolved with a [`ServiceProcessor`], which needs a
+//! [`ServiceConfig`] and an [`HttpClient`]. Services defined in code rather
+//! than in config files are run with a [`VersionUpdater`].
+
+pub mod builder;
+pub mod cache;
+pub mod cli;
+mod commit;
+pub mod config;
+mod discovery;
+mod dns;
+mod doctor;
+mod env;
+pub mod error;
+mod features;
+mod files;
+pub mod git;
+mod health;
+mod history;
+pub mod http;
+mod import;
+mod logging;
+mod login;
+mod matrix;
+mod netrc;
+mod notify;
+#[cfg(feature = "kubernetes")]
+mod operator;
+mod plugin;
+mod portainer;
+mod progress;
+mod proxy;
+mod pull_request;
+mod rate_limit;
+pub mod registry;
+mod release_webhook;
+mod schedule;
+mod server;
+pub mod service;
+mod shutdown;
+mod summary;
+#[cfg(feature = "docker")]
+mod swarm;
+mod tag_template;
+mod telemetry;
+mod template;
+mod updater;
+mod watch;
+
+pub use builder::{VersionUpdater, VersionUpdaterBuilder};
+pub use config::{AppConfig, Args, OutputData, ServiceConfig, ServiceVersion};
+pub use error::AppError;
+pub use git::{GitClient, GitConfig};
+pub use http::HttpClient;
+pub use registry::{ImageConfig, RegistryClient};
+pub use service::{ResolutionOutcome, ServiceProcessor, Stale, Timing, Verification};
@@ -1,20 +1,353 @@
-use env_logger::Builder;
-use std::io::Write;
-
-pub fn init_logging(level: Option<log::LevelFilter>) {
-    let level = level.unwrap_or(log::LevelFilter::Info);
-
-    Builder::new()
-        .format(|buf, record| {
-            writeln!(
-                buf,
-                "[{}][{}] {}",
-                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                record.level(),
-                record.args()
-            )
-        })
-        .filter_level(level)
-        .parse_env("RUST_LOG")
+use crate::progress::LogWriter;
+use crate::telemetry::Telemetry;
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use nu_ansi_term::{Color, Style};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Logs to stderr, above the progress bar if one is shown, and to `file` if
+/// given, at `level` unless `RUST_LOG` sets other levels, or at `own_level`
+/// for the log lines of version-updater itself if given. Records of
+/// dependencies using the `log` crate are logged as well. Spans are exported
+/// with `telemetry`, if given. Levels are colored on the console if `color`.
+pub fn init_logging(
+    level: Option<LevelFilter>,
+    own_level: Option<LevelFilter>,
+    color: bool,
+    file: Option<RotatingFile>,
+    telemetry: Option<&Telemetry>,
+) {
+    let mut filter = EnvFilter::builder()
+        .with_default_directive(level.unwrap_or(LevelFilter::INFO).into())
+        .from_env_lossy();
+    if let Some(own_level) = own_level {
+        let directive = format!("version_updater={}", own_level);
+        filter = filter.add_directive(directive.parse().expect("valid log directive"));
+    }
+    let console = tracing_subscriber::fmt::layer()
+        .with_writer(LogWriter)
+        .with_ansi(false)
+        .event_format(LineFormat { color });
+    let file = file.map(|file| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(Mutex::new(file))
+            .with_ansi(false)
+            .event_format(LineFormat { color: false })
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(console)
+        .with(file)
+        .with(telemetry.map(Telemetry::layer))
         .init();
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ColorChoice {
+    /// Color if stderr is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color the output on stderr.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && io::stderr().is_terminal()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Rotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl Rotation {
+    /// The period `time` falls into, a new one starts a new file.
+    fn period(self, time: DateTime<Local>) -> Option<String> {
+        match self {
+            Rotation::Never => None,
+            Rotation::Hourly => Some(time.format("%Y-%m-%d %H").to_string()),
+            Rotation::Daily => Some(time.format("%Y-%m-%d").to_string()),
+        }
+    }
+}
+
+/// A log file that is rotated each period or once it would exceed a size.
+/// Rotated files are renamed to `<file>.1`, `<file>.2` and so on, keeping
+/// the newest `keep` of them.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    period: Option<String>,
+    rotation: Rotation,
+    max_size: Option<u64>,
+    keep: usize,
+}
+
+impl RotatingFile {
+    /// Opens `path` for appending, continuing an existing file of the current period.
+    pub fn open(
+        path: &Path,
+        rotation: Rotation,
+        max_size: Option<u64>,
+        keep: usize,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        let modified = metadata
+            .modified()
+            .map(DateTime::from)
+            .unwrap_or(Local::now());
+        let mut log_file = Self {
+            path: path.to_path_buf(),
+            file,
+            size: metadata.len(),
+            period: rotation.period(modified),
+            rotation,
+            max_size,
+            keep,
+        };
+        if log_file.size > 0 && log_file.period != rotation.period(Local::now()) {
+            log_file.rotate()?;
+        }
+        Ok(log_file)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                match fs::rename(rotated(n), rotated(n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.period = self.rotation.period(Local::now());
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    /// Writes whole log lines, rotating before a line that starts a new period
+    /// or would exceed the size limit.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let new_period = self.period != self.rotation.period(Local::now());
+        let too_large = self
+            .max_size
+            .is_some_and(|max_size| self.size + buf.len() as u64 > max_size);
+        if self.size > 0 && (new_period || too_large) {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Parses a size like `10M`, `512K` or `1G` (powers of 1024) or plain bytes.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, ""),
+    };
+    let factor: u64 = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches(['B', 'I'])
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("unknown size unit '{}'", unit)),
+    };
+    let number = number
+        .parse::<u64>()
+        .map_err(|e| format!("invalid size '{}': {}", value, e))?;
+    number
+        .checked_mul(factor)
+        .ok_or_else(|| format!("size '{}' is too large", value))
+}
+
+/// `[<time>][<level>] <spans>: <message>`, where spans are listed with their
+/// fields, e.g. `service{name=nginx provider=github}`.
+struct LineFormat {
+    /// Color the level
+    color: bool,
+}
+
+impl<S, N> FormatEvent<S, N> for LineFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let level = *event.metadata().level();
+        let style = match (self.color, level) {
+            (false, _) => Style::new(),
+            (true, Level::ERROR) => Color::Red.bold(),
+            (true, Level::WARN) => Color::Yellow.bold(),
+            (true, Level::INFO) => Color::Green.normal(),
+            (true, Level::DEBUG) => Color::Blue.normal(),
+            (true, Level::TRACE) => Color::Purple.normal(),
+        };
+        write!(
+            writer,
+            "[{}][{}] ",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            style.paint(level.as_str())
+        )?;
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                write!(writer, "{}", span.name())?;
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                    if !fields.is_empty() {
+                        write!(writer, "{{{}}}", fields)?;
+                    }
+                }
+                write!(writer, ": ")?;
+            }
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn sizes_are_parsed() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512K"), Ok(512 << 10));
+        assert_eq!(parse_size("10M"), Ok(10 << 20));
+        assert_eq!(parse_size("1 GiB"), Ok(1 << 30));
+        assert_eq!(parse_size("2kb"), Ok(2 << 10));
+    }
+
+    #[test]
+    fn invalid_sizes_are_rejected() {
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("10T").is_err());
+        assert!(parse_size("-1K").is_err());
+        assert!(parse_size("99999999999G").is_err());
+        assert!(parse_size("99999999999999999999").is_err());
+    }
+
+    fn log_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "version-updater-test-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn files_are_rotated_by_size_keeping_the_newest() {
+        let dir = log_dir("rotate-size");
+        let path = dir.join("app.log");
+        let mut file = RotatingFile::open(&path, Rotation::Never, Some(10), 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("app.log"), "fourth\n");
+        assert_eq!(read("app.log.1"), "third\n");
+        assert_eq!(read("app.log.2"), "second\n");
+        assert!(!dir.join("app.log.3").exists());
+    }
+
+    #[test]
+    fn lines_within_the_size_share_a_file() {
+        let dir = log_dir("rotate-within");
+        let path = dir.join("app.log");
+        let mut file = RotatingFile::open(&path, Rotation::Never, Some(100), 2).unwrap();
+        file.write_all(b"first\n").unwrap();
+        file.write_all(b"second\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+        assert!(!dir.join("app.log.1").exists());
+    }
+
+    #[test]
+    fn nothing_is_kept_without_keep() {
+        let dir = log_dir("rotate-keep-none");
+        let path = dir.join("app.log");
+        let mut file = RotatingFile::open(&path, Rotation::Never, Some(10), 0).unwrap();
+        file.write_all(b"first line\n").unwrap();
+        file.write_all(b"second line\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second line\n");
+        assert!(!dir.join("app.log.1").exists());
+    }
+
+    #[test]
+    fn a_file_of_a_past_period_is_rotated_on_open() {
+        let dir = log_dir("rotate-period");
+        let path = dir.join("app.log");
+        fs::write(&path, "yesterday\n").unwrap();
+        let yesterday = SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60);
+        File::options()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(yesterday)
+            .unwrap();
+        let mut file = RotatingFile::open(&path, Rotation::Daily, None, 1).unwrap();
+        file.write_all(b"today\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "today\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("app.log.1")).unwrap(),
+            "yesterday\n"
+        );
+    }
+}
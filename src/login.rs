@@ -0,0 +1,279 @@
+//! `login github`: the OAuth device flow, storing the token it grants where
+//! `GitConfig::token` finds it when no token is configured. The token of the
+//! gh CLI is found there as well, if enabled.
+
+use super::env;
+use super::error::AppError;
+use super::git::{Provider, USER_AGENT_NAME};
+use super::http::HttpClient;
+use reqwest::header::{ACCEPT, USER_AGENT};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// The variable holding the client ID of the OAuth app, if not given with
+/// `--client-id`. There is no default: a client ID shipped in the binary would
+/// be one app that every installation's tokens are granted to, and revoked with.
+pub const GITHUB_CLIENT_ID_ENV: &str = "VERSION_UPDATER_GITHUB_CLIENT_ID";
+const DEVICE_CODE_URL: &str = "https://github.com/login/device/code";
+const ACCESS_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+/// Added to the polling interval each time GitHub asks to slow down
+const SLOW_DOWN: Duration = Duration::from_secs(5);
+/// How long `gh auth token` may take before the gh CLI counts as having no token
+const GH_TIMEOUT: Duration = Duration::from_secs(5);
+const GH_POLL: Duration = Duration::from_millis(20);
+
+/// The code the user enters at `verification_uri` to authorize the login.
+#[derive(Debug, Deserialize)]
+pub struct DeviceCode {
+    device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    /// Seconds until the code expires
+    pub expires_in: u64,
+    /// Seconds to wait between polls
+    interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+    interval: Option<u64>,
+}
+
+/// Where the token granted by `login` is stored for `provider`: under the user's
+/// config directory, e.g. `~/.config/version-updater/github-token`.
+pub fn token_path(provider: &Provider) -> Option<PathBuf> {
+    let dir = dirs::config_dir()?.join("version-updater");
+    Some(dir.join(format!("{}-token", provider.name())))
+}
+
+/// The token stored by `login` for `provider`, if there is one.
+pub fn stored_token(provider: &Provider) -> Result<Option<String>, AppError> {
+    match token_path(provider) {
+        Some(path) if path.exists() => env::read_secret(&path).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// The token of the gh CLI for github.com, from its `hosts.yml` or else, as
+/// newer versions keep it in the system keyring, from `gh auth token`. Looked
+/// up once per process, when the config enabling it is loaded, so checks
+/// don't wait for gh.
+pub fn gh_cli_token() -> Option<String> {
+    static TOKEN: OnceLock<Option<String>> = OnceLock::new();
+    TOKEN
+        .get_or_init(|| gh_hosts_token().or_else(gh_auth_token))
+        .clone()
+}
+
+#[derive(Deserialize)]
+struct GhHost {
+    oauth_token: Option<String>,
+}
+
+fn gh_hosts_token() -> Option<String> {
+    let dir = match std::env::var_os("GH_CONFIG_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir).join("gh"),
+            None => dirs::home_dir()?.join(".config").join("gh"),
+        },
+    };
+    let path = dir.join("hosts.yml");
+    let content = fs::read_to_string(&path).ok()?;
+    let hosts: HashMap<String, GhHost> = match serde_yaml::from_str(&content) {
+        Ok(hosts) => hosts,
+        Err(e) => {
+            debug!("Failed to parse {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    hosts.get("github.com")?.oauth_token.clone()
+}
+
+/// Runs `gh auth token`, or gives up after `GH_TIMEOUT`, e.g. if the keyring
+/// waits for the user to unlock it.
+fn gh_auth_token() -> Option<String> {
+    let mut child = match Command::new("gh")
+        .args(["auth", "token", "--hostname", "github.com"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            debug!("Failed to run gh auth token: {}", e);
+            return None;
+        }
+    };
+    let deadline = Instant::now() + GH_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(GH_POLL),
+            Ok(None) => {
+                debug!("gh auth token timed out after {:?}", GH_TIMEOUT);
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Err(e) => {
+                debug!("Failed to wait for gh auth token: {}", e);
+                return None;
+            }
+        }
+    };
+    if !status.success() {
+        debug!("gh auth token exited with {}", status);
+        return None;
+    }
+    let mut token = String::new();
+    child.stdout.take()?.read_to_string(&mut token).ok()?;
+    let token = token.trim().to_string();
+    (!token.is_empty()).then_some(token)
+}
+
+/// Starts a device flow for the OAuth app `client_id`, asking for `scope`
+/// (space separated, empty for public repositories only).
+pub async fn request_code(
+    http: &HttpClient,
+    client_id: &str,
+    scope: &str,
+) -> Result<DeviceCode, AppError> {
+    let request = http
+        .post(DEVICE_CODE_URL)
+        .header(USER_AGENT, USER_AGENT_NAME)
+        .header(ACCEPT, "application/json")
+        .form(&[("client_id", client_id), ("scope", scope)]);
+    let response = http
+        .send(request)
+        .await
+        .map_err(|e| login_error(DEVICE_CODE_URL, e))?;
+    if !response.status().is_success() {
+        return Err(login_error(DEVICE_CODE_URL, response.status()));
+    }
+    response
+        .json()
+        .await
+        .map_err(|e| login_error(DEVICE_CODE_URL, e))
+}
+
+/// Polls until the user authorized `code` and returns the granted token.
+pub async fn poll_token(
+    http: &HttpClient,
+    client_id: &str,
+    code: &DeviceCode,
+) -> Result<String, AppError> {
+    let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+    let mut interval = Duration::from_secs(code.interval);
+    loop {
+        tokio::time::sleep(interval).await;
+        if Instant::now() >= deadline {
+            return Err(expired());
+        }
+        let request = http
+            .post(ACCESS_TOKEN_URL)
+            .header(USER_AGENT, USER_AGENT_NAME)
+            .header(ACCEPT, "application/json")
+            .form(&[
+                ("client_id", client_id),
+                ("device_code", code.device_code.as_str()),
+                ("grant_type", DEVICE_CODE_GRANT),
+            ]);
+        let response = http
+            .send(request)
+            .await
+            .map_err(|e| login_error(ACCESS_TOKEN_URL, e))?;
+        let response: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| login_error(ACCESS_TOKEN_URL, e))?;
+        if let Some(token) = response.access_token {
+            return Ok(token);
+        }
+        match response.error.as_deref() {
+            Some("authorization_pending") => {}
+            Some("slow_down") => {
+                interval = response
+                    .interval
+                    .map_or(interval + SLOW_DOWN, Duration::from_secs);
+            }
+            Some("expired_token") => return Err(expired()),
+            Some("access_denied") => {
+                return Err(AppError::LoginError("the login was denied".to_string()))
+            }
+            error => {
+                let reason = response
+                    .error_description
+                    .as_deref()
+                    .or(error)
+                    .unwrap_or("no token in the response");
+                return Err(login_error(ACCESS_TOKEN_URL, reason));
+            }
+        }
+    }
+}
+
+/// Writes `token` to `path`, readable only by the user on Unix.
+pub fn store_token(path: &Path, token: &str) -> Result<(), AppError> {
+    let error = |e: std::io::Error| AppError::LoginError(format!("{}: {}", path.display(), e));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(error)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut file| {
+            // The mode only applies to new files, an existing one keeps its own
+            #[cfg(unix)]
+            file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+            writeln!(file, "{}", token)
+        })
+        .map_err(error)
+}
+
+fn expired() -> AppError {
+    AppError::LoginError("the code expired, run login again".to_string())
+}
+
+fn login_error(url: &str, e: impl std::fmt::Display) -> AppError {
+    AppError::LoginError(format!("{}: {}", url, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn stored_token_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!(
+            "version-updater-login-{}-token",
+            std::process::id()
+        ));
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        store_token(&path, "gho_token").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "gho_token\n");
+        fs::remove_file(&path).unwrap();
+    }
+}
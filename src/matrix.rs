@@ -0,0 +1,145 @@
+//! Service templates and matrix expansion.
+//!
+//! A service entry can start from a `template` and expand into one service per
+//! combination of its `matrix` values:
+//!
+//! ```yaml
+//! templates:
+//!   bitnami:
+//!     git: { type: github, repo: bitnami/containers, version_filter: "{chart}-(.*)" }
+//!     image: { name: "bitnami/{chart}", tag: "${RELEASE_VERSION}" }
+//! services:
+//!   bitnami:
+//!     template: bitnami
+//!     matrix:
+//!       chart: [redis, postgresql]
+//!     service_name: "{chart}"
+//! ```
+//!
+//! `{name}` (the entry name), `vars` and matrix values are replaced in all string
+//! values. Without `service_name`, expanded services are named after the entry
+//! and their matrix values, e.g. `app-alpine`.
+
+use super::error::AppError;
+use super::template;
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Keys of a service entry that control the expansion.
+const TEMPLATE: &str = "template";
+const VARS: &str = "vars";
+const MATRIX: &str = "matrix";
+const SERVICE_NAME: &str = "service_name";
+
+/// Expands a service entry into its services, still as raw values.
+pub fn expand(
+    name: &str,
+    entry: &Value,
+    templates: &HashMap<String, Value>,
+) -> Result<Vec<(String, Value)>, AppError> {
+    let error = |message: String| AppError::ConfigError(format!("Service '{}': {}", name, message));
+    let Value::Mapping(entry) = entry else {
+        return Ok(vec![(name.to_string(), entry.clone())]);
+    };
+    let mut entry = entry.clone();
+
+    let base = match entry.remove(TEMPLATE) {
+        Some(Value::String(template)) => templates
+            .get(&template)
+            .cloned()
+            .ok_or_else(|| error(format!("Unknown template '{}'", template)))?,
+        Some(_) => return Err(error("template must be a name".to_string())),
+        None => Value::Mapping(Mapping::new()),
+    };
+    let vars: BTreeMap<String, String> = match entry.remove(VARS) {
+        Some(vars) => serde_yaml::from_value(vars)
+            .map_err(|e| error(format!("vars must map names to strings: {}", e)))?,
+        None => BTreeMap::new(),
+    };
+    let matrix: BTreeMap<String, Vec<String>> = match entry.remove(MATRIX) {
+        Some(matrix) => serde_yaml::from_value(matrix)
+            .map_err(|e| error(format!("matrix must map names to lists of strings: {}", e)))?,
+        None => BTreeMap::new(),
+    };
+    let service_name = match entry.remove(SERVICE_NAME) {
+        Some(Value::String(pattern)) => Some(pattern),
+        Some(_) => return Err(error("service_name must be a string".to_string())),
+        None => None,
+    };
+
+    let mut merged = base;
+    merge(&mut merged, Value::Mapping(entry));
+
+    let mut services = Vec::new();
+    for combination in combinations(&matrix) {
+        let mut values: Vec<(&str, String)> = vec![("name", name.to_string())];
+        values.extend(
+            vars.iter()
+                .map(|(key, value)| (key.as_str(), value.clone())),
+        );
+        values.extend(
+            combination
+                .iter()
+                .map(|(key, value)| (*key, value.to_string())),
+        );
+
+        let service = match &service_name {
+            Some(pattern) => template::render(pattern, &values),
+            None => std::iter::once(name)
+                .chain(combination.iter().map(|(_, value)| *value))
+                .collect::<Vec<_>>()
+                .join("-"),
+        };
+        let mut value = merged.clone();
+        render(&mut value, &values);
+        services.push((service, value));
+    }
+    Ok(services)
+}
+
+/// Merges `overlay` into `base`. Mappings are merged recursively, anything
+/// else is replaced.
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// All combinations of the matrix values, one empty combination without a matrix.
+fn combinations(matrix: &BTreeMap<String, Vec<String>>) -> Vec<Vec<(&str, &str)>> {
+    let mut combinations = vec![Vec::new()];
+    for (key, values) in matrix {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((key.as_str(), value.as_str()));
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+/// Replaces the placeholders in all string values.
+fn render(value: &mut Value, vars: &[(&str, String)]) {
+    match value {
+        Value::String(string) => *string = template::render(string, vars),
+        Value::Sequence(sequence) => sequence.iter_mut().for_each(|item| render(item, vars)),
+        Value::Mapping(mapping) => mapping.values_mut().for_each(|item| render(item, vars)),
+        Value::Tagged(tagged) => render(&mut tagged.value, vars),
+        _ => {}
+    }
+}
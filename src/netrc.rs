@@ -0,0 +1,90 @@
+//! Credentials from `~/.netrc` (or the file in `NETRC`), used when no token or
+//! registry credentials are configured otherwise.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// Returns the login and password of the `machine` entry for `host`, or of the
+/// `default` entry.
+pub fn lookup(host: &str) -> Option<(String, String)> {
+    let path = env::var_os("NETRC")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".netrc")))?;
+    let content = fs::read_to_string(&path).ok()?;
+    let credentials = parse(&content, host);
+    if credentials.is_some() {
+        debug!("Using credentials for {} from {}", host, path.display());
+    }
+    credentials
+}
+
+fn parse(content: &str, host: &str) -> Option<(String, String)> {
+    let mut matched: Option<(String, String)> = None;
+    let mut default: Option<(String, String)> = None;
+    // The entry being read: whether it is for `host` or the default entry
+    let mut entry: Option<(bool, Option<String>, Option<String>)> = None;
+    let mut finish = |entry: Option<(bool, Option<String>, Option<String>)>| {
+        if let Some((is_default, login, Some(password))) = entry {
+            let target = if is_default {
+                &mut default
+            } else {
+                &mut matched
+            };
+            target.get_or_insert((login.unwrap_or_default(), password));
+        }
+    };
+
+    let mut tokens = tokens(content).into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => {
+                finish(entry.take());
+                if tokens.next() == Some(host) {
+                    entry = Some((false, None, None));
+                }
+            }
+            "default" => {
+                finish(entry.take());
+                entry = Some((true, None, None));
+            }
+            "login" | "password" => {
+                let value = tokens.next().map(str::to_string);
+                if let Some((_, login, password)) = &mut entry {
+                    if token == "login" {
+                        *login = value;
+                    } else {
+                        *password = value;
+                    }
+                }
+            }
+            "account" | "macdef" => {
+                tokens.next();
+            }
+            _ => {}
+        }
+    }
+    finish(entry);
+    matched.or(default)
+}
+
+/// Splits the file into tokens, skipping comments and the bodies of macro
+/// definitions, which end at the next empty line.
+fn tokens(content: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut in_macro = false;
+    for line in content.lines() {
+        if in_macro {
+            in_macro = !line.trim().is_empty();
+            continue;
+        }
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        in_macro = words.contains(&"macdef");
+        tokens.extend(words);
+    }
+    tokens
+}
@@ -0,0 +1,148 @@
+use super::Event;
+use crate::env;
+use crate::template;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+const DEFAULT_SUBJECT: &str = "version-updater: {count} services changed";
+const DEFAULT_TEXT: &str = "{events}";
+const DEFAULT_HTML: &str = "<p>{count} services changed:</p>\n{events_html}";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmailConfig {
+    pub host: String,
+    /// Defaults to the port of the TLS mode
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: TlsMode,
+    /// The password is read from `SMTP_PASSWORD`
+    #[serde(default)]
+    pub username: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Templates, see `template_vars` for the available variables
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub html: Option<String>,
+    /// Only report these events (`changed`, `error`), defaults to all
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsMode {
+    /// Upgrade the connection with STARTTLS, port 587
+    #[default]
+    Starttls,
+    /// Implicit TLS, port 465
+    Tls,
+    /// Unencrypted, port 25
+    None,
+}
+
+/// Sends a single report containing all events.
+pub async fn send(config: &EmailConfig, events: &[Event]) {
+    let events: Vec<&Event> = events
+        .iter()
+        .filter(|event| {
+            config.events.is_empty() || config.events.iter().any(|e| e == event.event.as_str())
+        })
+        .collect();
+    if events.is_empty() {
+        return;
+    }
+    match send_report(config, &events).await {
+        Ok(()) => info!("Sent email report to {}", config.to.join(", ")),
+        Err(e) => error!("Email notification failed: {}", e),
+    }
+}
+
+async fn send_report(config: &EmailConfig, events: &[&Event]) -> Result<(), String> {
+    let vars = template_vars(events);
+    let subject = template::render(config.subject.as_deref().unwrap_or(DEFAULT_SUBJECT), &vars);
+    let text = template::render(config.text.as_deref().unwrap_or(DEFAULT_TEXT), &vars);
+    let html = template::render(config.html.as_deref().unwrap_or(DEFAULT_HTML), &vars);
+
+    let mut builder = Message::builder()
+        .from(parse_mailbox(&config.from)?)
+        .subject(subject);
+    for to in &config.to {
+        builder = builder.to(parse_mailbox(to)?);
+    }
+    let message = builder
+        .multipart(MultiPart::alternative_plain_html(text, html))
+        .map_err(|e| e.to_string())?;
+
+    let transport = match config.tls {
+        TlsMode::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host),
+        TlsMode::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host),
+        TlsMode::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+            &config.host,
+        )),
+    }
+    .map_err(|e| e.to_string())?;
+    let mut transport = match config.port {
+        Some(port) => transport.port(port),
+        None => transport,
+    };
+    if let Some(username) = &config.username {
+        let password = env::secret("SMTP_PASSWORD")
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "SMTP_PASSWORD is required with a username".to_string())?;
+        transport = transport.credentials(Credentials::new(username.clone(), password));
+    }
+
+    transport
+        .build()
+        .send(message)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, String> {
+    address
+        .parse()
+        .map_err(|e| format!("Invalid address '{}': {}", address, e))
+}
+
+/// Variables of a report: `{count}`, `{services}`, `{events}` (one line per event)
+/// and `{events_html}` (an HTML list).
+fn template_vars(events: &[&Event]) -> Vec<(&'static str, String)> {
+    let lines: Vec<String> = events.iter().map(|event| describe(event)).collect();
+    let items: String = lines
+        .iter()
+        .map(|line| format!("<li>{}</li>\n", escape_html(line)))
+        .collect();
+    let services: Vec<&str> = events.iter().map(|event| event.service.as_str()).collect();
+    vec![
+        ("count", events.len().to_string()),
+        ("services", services.join(", ")),
+        ("events", lines.join("\n")),
+        ("events_html", format!("<ul>\n{}</ul>", items)),
+    ]
+}
+
+fn describe(event: &Event) -> String {
+    match (&event.error, &event.old_tag, &event.new_tag) {
+        (Some(error), _, _) => format!("{} failed: {}", event.service, error),
+        (None, Some(old), Some(new)) => format!("{}: {} -> {}", event.service, old, new),
+        (None, _, new) => format!("{}: {}", event.service, new.as_deref().unwrap_or("unknown")),
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
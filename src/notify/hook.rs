@@ -0,0 +1,83 @@
+use super::{Event, EventKind};
+use crate::template;
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{error, info, warn};
+
+/// Commands run once per event. Arguments are split like a shell would and
+/// rendered individually, so values never need quoting and can't inject
+/// further arguments.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HookConfig {
+    /// Run when the resolved tag of a service changed, e.g. `./deploy.sh {service} {new_tag}`
+    #[serde(default)]
+    pub on_update: Option<String>,
+    /// Run when a service failed with a new error
+    #[serde(default)]
+    pub on_error: Option<String>,
+    /// Commands still running after this long are killed
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+pub async fn run(config: &HookConfig, events: &[Event]) {
+    for event in events {
+        let command = match event.event {
+            EventKind::Changed => &config.on_update,
+            EventKind::Error => &config.on_error,
+        };
+        if let Some(command) = command {
+            if let Err(e) = run_command(command, event, config.timeout).await {
+                error!("Hook for {} failed: {}", event.service, e);
+            }
+        }
+    }
+}
+
+async fn run_command(command: &str, event: &Event, timeout: Duration) -> Result<(), String> {
+    let words = shlex::split(command).ok_or_else(|| format!("Invalid command '{}'", command))?;
+    let vars = event.vars();
+    let mut args = words.iter().map(|word| template::render(word, &vars));
+    let program = args.next().ok_or_else(|| "Empty command".to_string())?;
+    let args: Vec<String> = args.collect();
+
+    info!(
+        "Running hook for {}: {} {}",
+        event.service,
+        program,
+        args.join(" ")
+    );
+    let child = Command::new(&program)
+        .args(&args)
+        .envs(
+            vars.iter()
+                .map(|(name, value)| (format!("VERSION_UPDATER_{}", name.to_uppercase()), value)),
+        )
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("{}: {}", program, e))?;
+    let output = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| format!("Timed out after {}", humantime::format_duration(timeout)))?
+        .map_err(|e| e.to_string())?;
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        info!("[hook {}] {}", event.service, line);
+    }
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        warn!("[hook {}] {}", event.service, line);
+    }
+    if !output.status.success() {
+        return Err(format!("{} exited with {}", program, output.status));
+    }
+    Ok(())
+}
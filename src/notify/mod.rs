@@ -0,0 +1,157 @@
+//! Notifications about services whose resolved tag changed or that failed.
+
+#[cfg(feature = "email")]
+mod email;
+mod hook;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod webhook;
+
+use super::config::OutputData;
+use super::http::HttpConfig;
+use super::plugin::{self, PluginConfig};
+use super::template;
+#[cfg(feature = "email")]
+use email::EmailConfig;
+use hook::HookConfig;
+#[cfg(feature = "mqtt")]
+use mqtt::MqttConfig;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use webhook::WebhookConfig;
+
+#[cfg(not(feature = "email"))]
+super::features::disabled_config!(EmailConfig, "Email notifications", "email");
+#[cfg(not(feature = "mqtt"))]
+super::features::disabled_config!(MqttConfig, "MQTT notifications", "mqtt");
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NotificationConfig {
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub email: Option<EmailConfig>,
+    #[serde(default)]
+    pub mqtt: Option<MqttConfig>,
+    #[serde(default)]
+    pub hooks: HookConfig,
+    /// Plugins receiving the events as JSON, see the `plugin` module
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    /// The resolved tag changed, or a new service resolved for the first time
+    Changed,
+    /// The service failed with an error it did not fail with before
+    Error,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Changed => "changed",
+            EventKind::Error => "error",
+        }
+    }
+}
+
+/// A change of a single service between two runs.
+#[derive(Debug, Serialize)]
+pub struct Event {
+    pub event: EventKind,
+    pub service: String,
+    pub image: String,
+    pub old_tag: Option<String>,
+    pub new_tag: Option<String>,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Event {
+    /// Template variables: `{event}`, `{service}`, `{image}`, `{old_tag}`, `{new_tag}`,
+    /// `{old_version}`, `{new_version}`, `{update_type}` and `{error}`.
+    pub fn vars(&self) -> Vec<(&'static str, String)> {
+        let value = |v: &Option<String>| v.clone().unwrap_or_else(|| "unknown".to_string());
+        let update_type = match &self.new_version {
+            Some(new) => template::update_type(self.old_version.as_deref(), new),
+            None => "unknown",
+        };
+        vec![
+            ("event", self.event.as_str().to_string()),
+            ("service", self.service.clone()),
+            ("image", self.image.clone()),
+            ("old_tag", value(&self.old_tag)),
+            ("new_tag", value(&self.new_tag)),
+            ("old_version", value(&self.old_version)),
+            ("new_version", value(&self.new_version)),
+            ("update_type", update_type.to_string()),
+            ("error", self.error.clone().unwrap_or_default()),
+        ]
+    }
+}
+
+/// Compares two outputs and returns an event for every service whose tag
+/// changed or that started failing with a new error.
+pub fn events(previous: &OutputData, output: &OutputData) -> Vec<Event> {
+    let mut events = Vec::new();
+    for (name, result) in output {
+        let old = previous.get(name);
+        let old_tag = old.and_then(|old| old.tag()).map(str::to_string);
+        let old_version = old.and_then(|old| old.version()).map(str::to_string);
+        if let Some(error) = result.error() {
+            if old.and_then(|old| old.error()).as_ref() == Some(&error) {
+                continue;
+            }
+            events.push(Event {
+                event: EventKind::Error,
+                service: name.clone(),
+                image: result.image.clone(),
+                old_tag,
+                new_tag: None,
+                old_version,
+                new_version: None,
+                error: Some(error),
+            });
+        } else if old_tag.as_deref() != result.tag() {
+            events.push(Event {
+                event: EventKind::Changed,
+                service: name.clone(),
+                image: result.image.clone(),
+                old_tag,
+                new_tag: result.tag().map(str::to_string),
+                old_version,
+                new_version: result.version().map(str::to_string),
+                error: None,
+            });
+        }
+    }
+    events
+}
+
+/// Sends the events to all configured notifiers. Failures are logged and
+/// never fail the run.
+pub async fn send(config: &NotificationConfig, http: &HttpConfig, events: &[Event]) {
+    if events.is_empty() {
+        return;
+    }
+    info!("Sending notifications for {} events", events.len());
+    for webhook in &config.webhooks {
+        webhook::send(webhook, http, events).await;
+    }
+    #[cfg(feature = "email")]
+    if let Some(email) = &config.email {
+        email::send(email, events).await;
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = &config.mqtt {
+        mqtt::send(mqtt, events).await;
+    }
+    hook::run(&config.hooks, events).await;
+    for config in &config.plugins {
+        plugin::notify(config, events).await;
+    }
+}
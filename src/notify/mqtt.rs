@@ -0,0 +1,106 @@
+use super::Event;
+use crate::env;
+use crate::template;
+use rumqttc::{AsyncClient, Event as MqttEvent, MqttOptions, Outgoing, QoS};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// The password is read from `MQTT_PASSWORD`
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    /// Topic template, `{service}` is replaced with the service name
+    #[serde(default = "default_topic")]
+    pub topic: String,
+    /// 0, 1 or 2
+    #[serde(default = "default_qos")]
+    pub qos: u8,
+    #[serde(default = "default_retain")]
+    pub retain: bool,
+    /// Only publish these events (`changed`, `error`), defaults to all
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_client_id() -> String {
+    "version-updater".to_string()
+}
+
+fn default_topic() -> String {
+    "version-updater/{service}".to_string()
+}
+
+fn default_qos() -> u8 {
+    1
+}
+
+fn default_retain() -> bool {
+    true
+}
+
+/// Publishes one message per event to the topic of its service.
+pub async fn send(config: &MqttConfig, events: &[Event]) {
+    let events: Vec<&Event> = events
+        .iter()
+        .filter(|event| {
+            config.events.is_empty() || config.events.iter().any(|e| e == event.event.as_str())
+        })
+        .collect();
+    if events.is_empty() {
+        return;
+    }
+    let publish = tokio::time::timeout(Duration::from_secs(30), publish(config, &events));
+    match publish.await {
+        Ok(Ok(())) => info!("Published {} MQTT messages", events.len()),
+        Ok(Err(e)) => error!("MQTT notification failed: {}", e),
+        Err(_) => error!("MQTT notification timed out"),
+    }
+}
+
+async fn publish(config: &MqttConfig, events: &[&Event]) -> Result<(), String> {
+    let qos = match config.qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        2 => QoS::ExactlyOnce,
+        qos => return Err(format!("Invalid QoS {}", qos)),
+    };
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    if let Some(username) = &config.username {
+        let password = env::secret("MQTT_PASSWORD")
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "MQTT_PASSWORD is required with a username".to_string())?;
+        options.set_credentials(username, password);
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, events.len() + 1);
+    for event in events {
+        let topic = template::render(&config.topic, &[("service", event.service.clone())]);
+        let payload = serde_json::to_vec(event).map_err(|e| e.to_string())?;
+        debug!("Publishing to {}", topic);
+        client
+            .publish(topic, qos, config.retain, payload)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    client.disconnect().await.map_err(|e| e.to_string())?;
+
+    // The event loop sends the queued publishes before the disconnect
+    loop {
+        match eventloop.poll().await {
+            Ok(MqttEvent::Outgoing(Outgoing::Disconnect)) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use super::Event;
+use crate::git::USER_AGENT_NAME;
+use crate::http::{self, HttpConfig};
+use crate::template;
+use reqwest::header::{CONTENT_TYPE, USER_AGENT};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{debug, error, warn};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Body template rendered per event, defaults to the event as JSON
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default = "default_content_type")]
+    pub content_type: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Only send these events (`changed`, `error`), defaults to all
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Attempts after the first one on network errors, 429 and 5xx responses
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_content_type() -> String {
+    "application/json".to_string()
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+pub async fn send(config: &WebhookConfig, http: &HttpConfig, events: &[Event]) {
+    let client = match http::client(http) {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Webhook notifications failed: {}", e);
+            return;
+        }
+    };
+    for event in events {
+        let kind = event.event.as_str();
+        if !config.events.is_empty() && !config.events.iter().any(|e| e == kind) {
+            continue;
+        }
+        let body = match &config.body {
+            Some(body) => {
+                let mut vars = event.vars();
+                // Keep templated JSON bodies valid when values contain quotes
+                if config.content_type.contains("json") {
+                    for (_, value) in vars.iter_mut() {
+                        let quoted = serde_json::Value::String(value.clone()).to_string();
+                        *value = quoted[1..quoted.len() - 1].to_string();
+                    }
+                }
+                template::render(body, &vars)
+            }
+            None => serde_json::to_string(event).unwrap_or_default(),
+        };
+        if let Err(e) = post(&client, config, body).await {
+            error!("Webhook notification for {} failed: {}", event.service, e);
+        }
+    }
+}
+
+/// Posts the body, retrying with exponential backoff.
+async fn post(client: &Client, config: &WebhookConfig, body: String) -> Result<(), String> {
+    let mut attempt = 0;
+    loop {
+        let mut request = client
+            .post(&config.url)
+            .header(USER_AGENT, USER_AGENT_NAME)
+            .header(CONTENT_TYPE, &config.content_type)
+            .body(body.clone());
+        for (name, value) in &config.headers {
+            request = request.header(name, value);
+        }
+
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                debug!("Webhook {} answered {}", config.url, response.status());
+                return Ok(());
+            }
+            Ok(response)
+                if !response.status().is_server_error() && response.status().as_u16() != 429 =>
+            {
+                return Err(format!("{} returned {}", config.url, response.status()));
+            }
+            Ok(response) => format!("{} returned {}", config.url, response.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt >= config.retries {
+            return Err(error);
+        }
+        let delay = Duration::from_secs(1 << attempt.min(6));
+        warn!("{}, retrying in {}s", error, delay.as_secs());
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
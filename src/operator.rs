@@ -0,0 +1,316 @@
+//! The Kubernetes operator, built with the `kubernetes` feature. Each
+//! `VersionCheck` custom resource holds a service in its spec, which is
+//! resolved every interval and whenever the spec changes, with the result
+//! written to the status of the resource.
+
+use super::config::{self, GlobalConfig, ServiceConfig, ServiceVersion};
+use super::error::AppError;
+use super::http::HttpClient;
+use super::service::ServiceProcessor;
+use super::shutdown;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::StreamExt;
+use kube::api::{Api, ApiResource, DynamicObject, GroupVersionKind, Patch, PatchParams};
+use kube::runtime::controller::{Action, Controller};
+use kube::runtime::watcher;
+use kube::{Client, ResourceExt};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+const GROUP: &str = "version-updater.io";
+const VERSION: &str = "v1alpha1";
+const KIND: &str = "VersionCheck";
+const PLURAL: &str = "versionchecks";
+/// The field manager of the status patches
+const MANAGER: &str = "version-updater";
+/// How long to wait before reconciling a resource again after a failure
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// The CustomResourceDefinition of `VersionCheck`, printed with `operator --crd`.
+/// The spec is a service as in the `services` section of the config file.
+pub const CRD: &str = r#"apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: versionchecks.version-updater.io
+spec:
+  group: version-updater.io
+  names:
+    kind: VersionCheck
+    listKind: VersionCheckList
+    plural: versionchecks
+    singular: versioncheck
+    shortNames: [vc]
+  scope: Namespaced
+  versions:
+    - name: v1alpha1
+      served: true
+      storage: true
+      subresources:
+        status: {}
+      additionalPrinterColumns:
+        - { name: Version, type: string, jsonPath: .status.version }
+        - { name: Tag, type: string, jsonPath: .status.tag }
+        - { name: Status, type: string, jsonPath: .status.status }
+        - { name: Checked, type: date, jsonPath: .status.lastChecked }
+      schema:
+        openAPIV3Schema:
+          type: object
+          properties:
+            spec:
+              description: A service as in the services section of the config file
+              type: object
+              x-kubernetes-preserve-unknown-fields: true
+            status:
+              type: object
+              properties:
+                status: { type: string }
+                version: { type: string }
+                tag: { type: string }
+                digest: { type: string }
+                error: { type: string }
+                lastChecked: { type: string, format: date-time }
+                observedGeneration: { type: integer, format: int64 }
+                conditions:
+                  type: array
+                  items:
+                    type: object
+                    required: [type, status]
+                    properties:
+                      type: { type: string }
+                      status: { type: string }
+                      reason: { type: string }
+                      message: { type: string }
+                      lastTransitionTime: { type: string, format: date-time }
+"#;
+
+struct Context {
+    client: Client,
+    global: GlobalConfig,
+    http: HttpClient,
+    interval: Duration,
+}
+
+/// Reconciles the `VersionCheck` resources in `namespaces` (all if empty)
+/// until SIGINT or SIGTERM, resolving each every `interval` with the global
+/// settings of `global`.
+pub async fn run(
+    global: GlobalConfig,
+    http: HttpClient,
+    namespaces: &[String],
+    interval: Duration,
+) -> Result<(), AppError> {
+    let client = Client::try_default().await.map_err(operator_error)?;
+    let resource = api_resource();
+    let apis: Vec<Api<DynamicObject>> = match namespaces {
+        [] => vec![Api::all_with(client.clone(), &resource)],
+        namespaces => namespaces
+            .iter()
+            .map(|namespace| Api::namespaced_with(client.clone(), namespace, &resource))
+            .collect(),
+    };
+    let context = Arc::new(Context {
+        client,
+        global,
+        http,
+        interval,
+    });
+    info!(
+        "Reconciling {} resources, checking every {}",
+        KIND,
+        humantime::format_duration(interval)
+    );
+    let controllers = apis.into_iter().map(|api| {
+        Controller::new_with(api, watcher::Config::default(), resource.clone())
+            .graceful_shutdown_on(async {
+                shutdown::requested().await;
+            })
+            .run(reconcile, error_policy, context.clone())
+            .boxed()
+    });
+    futures::stream::select_all(controllers)
+        .for_each(|result| async move {
+            if let Err(e) = result {
+                warn!("Reconciliation failed: {}", e);
+            }
+        })
+        .await;
+    info!("Shutting down");
+    Ok(())
+}
+
+fn api_resource() -> ApiResource {
+    ApiResource::from_gvk_with_plural(&GroupVersionKind::gvk(GROUP, VERSION, KIND), PLURAL)
+}
+
+async fn reconcile(object: Arc<DynamicObject>, context: Arc<Context>) -> Result<Action, AppError> {
+    let name = object.name_any();
+    let namespace = object.namespace().unwrap_or_default();
+    let status = &object.data["status"];
+    let generation = object.metadata.generation;
+    // Status patches trigger a reconciliation as well, which must not resolve again
+    let last_checked = status["lastChecked"]
+        .as_str()
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc));
+    if let (Some(last_checked), true) = (
+        last_checked,
+        status["observedGeneration"].as_i64() == generation,
+    ) {
+        let elapsed = (Utc::now() - last_checked).to_std().unwrap_or_default();
+        if elapsed < context.interval {
+            return Ok(Action::requeue(context.interval - elapsed));
+        }
+    }
+
+    let id = format!("{}/{}", namespace, name);
+    let result = match service(&object.data["spec"], &context.global) {
+        Ok(service) => ServiceProcessor::new(service, context.http.new_run())
+            .with_name(&id)
+            .process()
+            .await
+            .map_err(|e| format!("{:#}", e)),
+        Err(e) => Err(e.to_string()),
+    };
+    let new_status = status_of(&result, status, generation);
+    let api: Api<DynamicObject> =
+        Api::namespaced_with(context.client.clone(), &namespace, &api_resource());
+    api.patch_status(
+        &name,
+        &PatchParams::apply(MANAGER),
+        &Patch::Merge(json!({ "status": new_status })),
+    )
+    .await
+    .map_err(operator_error)?;
+    match &result {
+        Ok(version) if version.error().is_none() => {
+            info!("{}: {}", id, version.tag().unwrap_or_default())
+        }
+        Ok(version) => warn!("{}: {}", id, version.error().unwrap_or_default()),
+        Err(e) => error!("{}: {}", id, e),
+    }
+    Ok(Action::requeue(context.interval))
+}
+
+fn error_policy(object: Arc<DynamicObject>, e: &AppError, _: Arc<Context>) -> Action {
+    warn!("Failed to reconcile {}: {}", object.name_any(), e);
+    Action::requeue(RETRY_DELAY)
+}
+
+/// The service in `spec`, with the settings it takes from the global config.
+fn service(spec: &Value, global: &GlobalConfig) -> Result<ServiceConfig, AppError> {
+    let mut service: ServiceConfig = serde_json::from_value(spec.clone())
+        .map_err(|e| AppError::ConfigError(format!("spec: {}", e)))?;
+    let forbidden = forbidden_fields(&service);
+    if !forbidden.is_empty() {
+        return Err(AppError::ConfigError(format!(
+            "spec: {} can't be set in a {}",
+            forbidden.join(", "),
+            KIND
+        )));
+    }
+    config::apply_global(global, &mut service);
+    service.validate()?;
+    Ok(service)
+}
+
+/// The fields of `service` that anyone allowed to create a resource could
+/// use to read the operator's files and variables, or to send its tokens to
+/// a host of their own, as the global tokens are added for the git host.
+fn forbidden_fields(service: &ServiceConfig) -> Vec<&'static str> {
+    let git = &service.git;
+    let auth = service.image.credentials.as_ref();
+    [
+        ("git.host", git.host.is_some()),
+        ("git.token_file", git.token_file.is_some()),
+        ("git.token_env", git.token_env.is_some()),
+        (
+            "image.auth.password_file",
+            auth.is_some_and(|auth| auth.password_file.is_some()),
+        ),
+        (
+            "image.auth.password_env",
+            auth.is_some_and(|auth| auth.password_env.is_some()),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(field, set)| set.then_some(field))
+    .collect()
+}
+
+/// The status reporting `result`, with a `Ready` condition whose transition
+/// time is kept from `previous` while it doesn't change.
+fn status_of(
+    result: &Result<ServiceVersion, String>,
+    previous: &Value,
+    generation: Option<i64>,
+) -> Value {
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let (ready, reason, message) = match result {
+        Ok(version) => match version.error() {
+            None => ("True", "Resolved", String::new()),
+            Some(error) => ("False", "ResolutionFailed", error),
+        },
+        Err(e) => ("False", "InvalidSpec", e.clone()),
+    };
+    let previous_ready = &previous["conditions"][0];
+    let transition = match previous_ready["status"].as_str() == Some(ready) {
+        true => previous_ready["lastTransitionTime"].clone(),
+        false => json!(now),
+    };
+    let version = result.as_ref().ok();
+    json!({
+        "status": version.map_or("error", |version| version.outcome.status()),
+        "version": version.and_then(ServiceVersion::version),
+        "tag": version.and_then(ServiceVersion::tag),
+        "digest": version.and_then(ServiceVersion::digest),
+        "error": (!message.is_empty()).then_some(&message),
+        "lastChecked": now,
+        "observedGeneration": generation,
+        "conditions": [{
+            "type": "Ready",
+            "status": ready,
+            "reason": reason,
+            "message": message,
+            "lastTransitionTime": transition,
+        }],
+    })
+}
+
+fn operator_error(e: kube::Error) -> AppError {
+    AppError::OperatorError(format!("Kubernetes: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credential_sources_and_hosts_are_rejected() {
+        let global = GlobalConfig::default();
+        let spec = |git: Value, image: Value| json!({ "git": git, "image": image });
+        let nginx = json!({ "name": "nginx", "tag": "${RELEASE_VERSION}" });
+        let github = json!({ "type": "github", "repo": "nginx/nginx" });
+        assert!(service(&spec(github.clone(), nginx.clone()), &global).is_ok());
+
+        let gitlab = json!({
+            "type": "gitlab",
+            "project_id": 1,
+            "host": "gitlab.example.com",
+            "token_env": "AWS_SECRET_ACCESS_KEY",
+        });
+        let error = service(&spec(gitlab, nginx), &global).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid configuration: spec: git.host, git.token_env can't be set in a VersionCheck"
+        );
+        let image = json!({
+            "name": "registry.example.com/app",
+            "tag": "${RELEASE_VERSION}",
+            "auth": { "username": "robot", "password_file": "/var/run/secrets/token" },
+        });
+        let error = service(&spec(github, image), &global).unwrap_err();
+        assert!(error.to_string().contains("image.auth.password_file"));
+    }
+}
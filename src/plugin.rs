@@ -0,0 +1,155 @@
+//! External plugins speaking JSON over stdin and stdout.
+//!
+//! A plugin is a command that is started for every request. It reads a single
+//! JSON object from stdin and writes a single JSON object to stdout before
+//! exiting. Anything written to stderr is logged.
+//!
+//! Plugins in `global.plugins` are version sources, selected by services with
+//! `git.type: <plugin name>`. They receive
+//! `{"action": "latest_tag", "git": {...}, "token": ...}` and answer with
+//! `{"tag": "v1.2.3"}`. Plugins in `notifications.plugins` receive
+//! `{"action": "notify", "events": [...]}` and their answer is ignored.
+//!
+//! Either kind may answer `{"error": "..."}` or exit with a non-zero status to
+//! report a failure.
+
+use super::error::AppError;
+use super::git::{GitConfig, VersionProvider};
+use super::http::HttpClient;
+use super::notify::Event;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, error, info, warn};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PluginConfig {
+    /// Split like a shell would, e.g. `python3 plugins/gitea.py --verbose`
+    pub command: String,
+    /// Plugins still running after this long are killed
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+/// A version source backed by a plugin.
+pub struct ExecProvider {
+    name: String,
+    config: PluginConfig,
+}
+
+impl ExecProvider {
+    pub fn new(name: &str, config: PluginConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            config,
+        }
+    }
+}
+
+impl VersionProvider for ExecProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn latest_tag<'a>(
+        &'a self,
+        config: &'a GitConfig,
+        _http: &'a HttpClient,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            info!(
+                "Getting latest version from plugin {} for {}",
+                self.name, config
+            );
+            let request = json!({
+                "action": "latest_tag",
+                "git": config,
+                "token": config.token()?,
+            });
+            let response = call(&self.name, &self.config, &request).await?;
+            match response["tag"].as_str() {
+                Some(tag) => Ok(tag.to_string()),
+                None => Err(AppError::PluginError(format!(
+                    "{}: Response has no tag: {}",
+                    self.name, response
+                ))
+                .into()),
+            }
+        })
+    }
+}
+
+/// Passes the events to a notifier plugin. Failures are logged.
+pub async fn notify(config: &PluginConfig, events: &[Event]) {
+    let request = json!({ "action": "notify", "events": events });
+    if let Err(e) = call(&config.command, config, &request).await {
+        error!("{}", e);
+    }
+}
+
+/// Runs the plugin with `request` on stdin and returns the JSON object it
+/// printed.
+async fn call(name: &str, config: &PluginConfig, request: &Value) -> Result<Value, AppError> {
+    let error = |message: String| AppError::PluginError(format!("{}: {}", name, message));
+    let words = shlex::split(&config.command)
+        .ok_or_else(|| error(format!("Invalid command '{}'", config.command)))?;
+    let (program, args) = words
+        .split_first()
+        .ok_or_else(|| error("Empty command".to_string()))?;
+
+    debug!("Running plugin {}: {}", name, config.command);
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| error(format!("{}: {}", program, e)))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let write = async move {
+        // A plugin may exit without reading the whole request
+        match stdin.write_all(request.to_string().as_bytes()).await {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+            _ => Ok(()),
+        }
+    };
+    // The request is written while the output is read, so that neither side
+    // blocks on a full pipe, and both are bounded by the timeout
+    let run = async {
+        let (written, output) = tokio::join!(write, child.wait_with_output());
+        written.map_err(|e| error(format!("Failed to write request: {}", e)))?;
+        output.map_err(|e| error(e.to_string()))
+    };
+    let output = tokio::time::timeout(config.timeout, run)
+        .await
+        .map_err(|_| {
+            error(format!(
+                "Timed out after {}",
+                humantime::format_duration(config.timeout)
+            ))
+        })??;
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        warn!("[plugin {}] {}", name, line);
+    }
+    if !output.status.success() {
+        return Err(error(format!("{} exited with {}", program, output.status)));
+    }
+
+    let response: Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| error(format!("Invalid response: {}", e)))?;
+    match response["error"].as_str() {
+        Some(message) => Err(error(message.to_string())),
+        None => Ok(response),
+    }
+}
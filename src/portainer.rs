@@ -0,0 +1,183 @@
+use super::config::{OutputData, ServiceConfig};
+use super::env;
+use super::error::AppError;
+use super::git::USER_AGENT_NAME;
+use super::http::{self, HttpConfig};
+use super::updater;
+use reqwest::header::USER_AGENT;
+use reqwest::{Client, Method, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{debug, info, trace};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PortainerConfig {
+    /// Base URL of the Portainer instance; the access token is read from `PORTAINER_TOKEN`
+    pub url: String,
+}
+
+/// The Portainer stack a service is deployed with.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PortainerTarget {
+    pub stack: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Stack {
+    id: u64,
+    name: String,
+    endpoint_id: u64,
+    #[serde(default)]
+    git_config: Option<Value>,
+    #[serde(default)]
+    env: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StackFile {
+    stack_file_content: String,
+}
+
+/// Redeploys the Portainer stacks of services that resolved to a new tag and
+/// returns the names of the redeployed stacks.
+///
+/// Stacks created from a compose file get their `image:` lines rewritten. Git
+/// based stacks are redeployed from their repository when the tag differs from
+/// the previous output, as the new tag is expected to be committed there.
+pub async fn redeploy(
+    config: &PortainerConfig,
+    http: &HttpConfig,
+    services: &HashMap<String, ServiceConfig>,
+    output: &OutputData,
+    previous: Option<&OutputData>,
+    dry_run: bool,
+) -> Result<Vec<String>, AppError> {
+    // Group the resolved services by the stack they are deployed with
+    let mut by_stack: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (name, result) in output {
+        let Some(target) = services.get(name).and_then(|s| s.portainer.as_ref()) else {
+            continue;
+        };
+        if result.tag().is_some() {
+            by_stack.entry(&target.stack).or_default().push(name);
+        }
+    }
+    if by_stack.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let token = env::secret("PORTAINER_TOKEN")?.ok_or_else(|| {
+        AppError::PortainerError("PORTAINER_TOKEN is required for Portainer".to_string())
+    })?;
+    let api = PortainerApi {
+        client: http::client(http)?,
+        url: config.url.trim_end_matches('/'),
+        token: &token,
+    };
+    let stacks: Vec<Stack> = api.send(api.request(Method::GET, "stacks")).await?;
+
+    let mut redeployed = Vec::new();
+    for (stack_name, names) in by_stack {
+        let stack = stacks
+            .iter()
+            .find(|stack| stack.name == stack_name)
+            .ok_or_else(|| AppError::PortainerError(format!("Stack '{}' not found", stack_name)))?;
+
+        let changed = names.iter().any(|name| {
+            let tag = output[*name].tag();
+            previous
+                .and_then(|previous| previous.get(*name))
+                .is_none_or(|previous| previous.tag() != tag)
+        });
+
+        let payload = if stack.git_config.is_some() {
+            if !changed {
+                debug!("Git stack {} has no new tags", stack.name);
+                continue;
+            }
+            json!({ "env": stack.env, "prune": false, "pullImage": true })
+        } else {
+            let file: StackFile = api
+                .send(api.request(Method::GET, &format!("stacks/{}/file", stack.id)))
+                .await?;
+            let mut content = file.stack_file_content.clone();
+            for name in &names {
+                let result = &output[*name];
+                let tag = result.tag().unwrap_or_default();
+                content = updater::update_compose_images(&content, &result.image, tag);
+            }
+            if content == file.stack_file_content {
+                debug!("Stack {} is up to date", stack.name);
+                continue;
+            }
+            json!({
+                "stackFileContent": content,
+                "env": stack.env,
+                "prune": false,
+                "pullImage": true,
+            })
+        };
+
+        if dry_run {
+            info!("Would redeploy Portainer stack {}", stack.name);
+            redeployed.push(stack.name.clone());
+            continue;
+        }
+
+        info!("Redeploying Portainer stack {}", stack.name);
+        let path = match stack.git_config {
+            Some(_) => format!("stacks/{}/git/redeploy", stack.id),
+            None => format!("stacks/{}", stack.id),
+        };
+        let request = api
+            .request(Method::PUT, &path)
+            .query(&[("endpointId", stack.endpoint_id)])
+            .json(&payload);
+        api.send::<Value>(request).await?;
+        redeployed.push(stack.name.clone());
+    }
+    Ok(redeployed)
+}
+
+struct PortainerApi<'a> {
+    client: Client,
+    url: &'a str,
+    token: &'a str,
+}
+
+impl PortainerApi<'_> {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}/api/{}", self.url, path);
+        debug!("Portainer API request {} {}", method, url);
+        self.client
+            .request(method, url)
+            .header(USER_AGENT, USER_AGENT_NAME)
+            .header("X-API-Key", self.token)
+    }
+
+    async fn send<T: for<'de> Deserialize<'de>>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, AppError> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| AppError::PortainerError(e.to_string()))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| AppError::PortainerError(e.to_string()))?;
+        trace!("Portainer API response {}: {}", status, body);
+        if !status.is_success() {
+            return Err(AppError::PortainerError(format!(
+                "Portainer API returned {}: {}",
+                status, body
+            )));
+        }
+        serde_json::from_str(&body).map_err(|e| AppError::InvalidResponse(e.to_string()))
+    }
+}
@@ -0,0 +1,94 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, IsTerminal, Write};
+use std::sync::RwLock;
+use std::time::Duration;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// The bar of the running batch, which log lines are printed above.
+static ACTIVE: RwLock<Option<ProgressBar>> = RwLock::new(None);
+
+/// An overall progress bar for a batch of services, shown on stderr while it
+/// is a terminal and hidden otherwise.
+pub struct Progress {
+    bar: ProgressBar,
+    failed: u64,
+}
+
+impl Progress {
+    pub fn start(total: usize, enabled: bool) -> Self {
+        let bar = match enabled && io::stderr().is_terminal() {
+            true => ProgressBar::new(total as u64),
+            false => ProgressBar::hidden(),
+        };
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{spinner} [{elapsed_precise}] {bar:30} {pos}/{len} services {msg}",
+            )
+            .expect("valid progress template"),
+        );
+        bar.enable_steady_tick(Duration::from_millis(120));
+        if !bar.is_hidden() {
+            *ACTIVE.write().unwrap_or_else(|e| e.into_inner()) = Some(bar.clone());
+        }
+        Self { bar, failed: 0 }
+    }
+
+    /// Counts a finished service, `failed` if it ended with an error.
+    pub fn finish_service(&mut self, name: &str, failed: bool) {
+        if failed {
+            self.failed += 1;
+        }
+        let message = match self.failed {
+            0 => format!("(last: {})", name),
+            n => format!("({} failed, last: {})", n, name),
+        };
+        self.bar.set_message(message);
+        self.bar.inc(1);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+        if !self.bar.is_hidden() {
+            *ACTIVE.write().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+    }
+}
+
+/// Writes log lines to stderr, above the progress bar while one is shown.
+pub struct LogWriter;
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = LogLine;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogLine(Vec::new())
+    }
+}
+
+/// A buffered log line, printed when dropped.
+pub struct LogLine(Vec<u8>);
+
+impl Write for LogLine {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogLine {
+    fn drop(&mut self) {
+        let print = || {
+            let _ = io::stderr().lock().write_all(&self.0);
+        };
+        match &*ACTIVE.read().unwrap_or_else(|e| e.into_inner()) {
+            Some(bar) => bar.suspend(print),
+            None => print(),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use super::error::AppError;
+use reqwest::{ClientBuilder, Proxy, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use tracing::debug;
+
+/// Value of a host override that bypasses any proxy.
+const DIRECT: &str = "direct";
+
+/// Proxy settings for the git provider and registry APIs. Without any settings,
+/// `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` are honored.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProxyConfig {
+    /// Proxy for all hosts, e.g. `http://proxy:3128` or `socks5h://proxy:1080`.
+    /// Defaults to the proxy from the environment.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Comma separated hosts and domains that are reached directly, defaults to `NO_PROXY`
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+    /// Proxy or `direct` per host, with `*.example.com` matching subdomains
+    #[serde(default)]
+    pub hosts: BTreeMap<String, String>,
+}
+
+/// Configures the proxies of a client. Leaves the builder alone without any
+/// proxy settings, so reqwest applies the environment.
+pub fn apply(builder: ClientBuilder, config: &ProxyConfig) -> Result<ClientBuilder, AppError> {
+    if config.url.is_none() && config.no_proxy.is_none() && config.hosts.is_empty() {
+        return Ok(builder);
+    }
+
+    let default = match &config.url {
+        Some(url) => Some(parse(url)?),
+        None => None,
+    };
+    let hosts = config
+        .hosts
+        .iter()
+        .map(|(host, proxy)| match proxy.as_str() {
+            DIRECT => Ok((host.to_lowercase(), None)),
+            proxy => Ok((host.to_lowercase(), Some(parse(proxy)?))),
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+    let no_proxy = config
+        .no_proxy
+        .clone()
+        .or_else(|| env_var(&["NO_PROXY", "no_proxy"]))
+        .unwrap_or_default();
+
+    let proxy = Proxy::custom(move |url| {
+        let host = url.host_str()?.to_lowercase();
+        let proxy = match hosts
+            .iter()
+            .find(|(pattern, _)| matches_host(pattern, &host))
+        {
+            Some((_, proxy)) => proxy.clone(),
+            None if bypassed(&no_proxy, &host) => None,
+            None => default.clone().or_else(|| env_proxy(url.scheme())),
+        };
+        debug!(
+            "Proxy for {}: {}",
+            host,
+            proxy.as_ref().map_or(DIRECT, Url::as_str)
+        );
+        proxy
+    });
+    Ok(builder.proxy(proxy))
+}
+
+fn parse(url: &str) -> Result<Url, AppError> {
+    Url::parse(url).map_err(|e| AppError::RequestError(format!("Invalid proxy {}: {}", url, e)))
+}
+
+/// Matches `host` against `example.com`, or `*.example.com` for its subdomains.
+pub fn matches_host(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|rest| rest.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+/// Checks a `NO_PROXY` style list: `*` for all hosts, otherwise hosts that are
+/// equal to or subdomains of an entry.
+fn bypassed(no_proxy: &str, host: &str) -> bool {
+    no_proxy
+        .split(',')
+        .map(|entry| {
+            entry
+                .trim()
+                .trim_start_matches("*.")
+                .trim_start_matches('.')
+        })
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            entry == "*"
+                || host == entry
+                || host
+                    .strip_suffix(entry)
+                    .is_some_and(|rest| rest.ends_with('.'))
+        })
+}
+
+fn env_proxy(scheme: &str) -> Option<Url> {
+    let names: &[&str] = match scheme {
+        "https" => &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"],
+        _ => &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"],
+    };
+    env_var(names).and_then(|url| Url::parse(&url).ok())
+}
+
+fn env_var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+}
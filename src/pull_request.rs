@@ -0,0 +1,559 @@
+use super::commit::{self, CommitConfig, CommitRef};
+use super::env;
+use super::error::AppError;
+use super::git::USER_AGENT_NAME;
+use super::http::{self, HttpConfig};
+use super::template;
+use super::updater::UpdatePlan;
+use anyhow::Result;
+use reqwest::header::{ACCEPT, AUTHORIZATION, USER_AGENT};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use std::path::PathBuf;
+use tracing::{debug, info, trace, warn};
+
+const DEFAULT_GITHUB_API: &str = "https://api.github.com";
+
+fn default_base() -> String {
+    "main".to_string()
+}
+
+fn default_branch() -> String {
+    "version-updater/updates".to_string()
+}
+
+fn default_branch_prefix() -> String {
+    "version-updater/".to_string()
+}
+
+fn default_remote() -> String {
+    "origin".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Forge {
+    #[default]
+    Github,
+    /// Gitea and Forgejo share the same API
+    #[serde(alias = "forgejo")]
+    Gitea,
+}
+
+impl Forge {
+    fn token_env(&self) -> &'static str {
+        match self {
+            Forge::Github => "GITHUB_TOKEN",
+            Forge::Gitea => "GITEA_TOKEN",
+        }
+    }
+}
+
+impl fmt::Display for Forge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Forge::Github => write!(f, "GitHub"),
+            Forge::Gitea => write!(f, "Gitea"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PullRequestMode {
+    /// All updates in one pull request from `branch`
+    #[default]
+    Combined,
+    /// One pull request per service and version from `<branch_prefix><service>/<version>`
+    PerUpdate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PullRequestConfig {
+    #[serde(default)]
+    pub provider: Forge,
+    #[serde(default)]
+    pub mode: PullRequestMode,
+    /// Repository the pull request is opened in, e.g. `owner/name`
+    pub repo: String,
+    #[serde(default = "default_base")]
+    pub base: String,
+    #[serde(default = "default_branch")]
+    pub branch: String,
+    #[serde(default = "default_branch_prefix")]
+    pub branch_prefix: String,
+    #[serde(default = "default_remote")]
+    pub remote: String,
+    /// Title and body templates, see the `template` module for the available variables
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub assignees: Vec<String>,
+    /// API base URL for GitHub Enterprise, or the instance URL for Gitea/Forgejo
+    #[serde(default)]
+    pub api_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequest {
+    number: u64,
+    title: String,
+    #[serde(default)]
+    body: Option<String>,
+    html_url: String,
+    state: String,
+    head: PullRequestHead,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequestHead {
+    #[serde(rename = "ref")]
+    branch: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    id: u64,
+    name: String,
+}
+
+/// Commits the planned updates, pushes them and opens (or refreshes) pull requests
+/// according to the configured mode.
+pub async fn publish(
+    plan: &UpdatePlan,
+    config: &PullRequestConfig,
+    commit_config: &CommitConfig,
+    http: &HttpConfig,
+) -> Result<()> {
+    if plan.services.is_empty() {
+        info!("No updates, not opening a pull request");
+        return Ok(());
+    }
+
+    let token_env = config.provider.token_env();
+    let token = env::secret(token_env)?.ok_or_else(|| {
+        AppError::PullRequestError(format!("{} is required to open pull requests", token_env))
+    })?;
+    let forge = ForgeApi::new(config, &token, http)?;
+
+    match config.mode {
+        PullRequestMode::Combined => publish_combined(&forge, plan, commit_config).await,
+        PullRequestMode::PerUpdate => publish_per_update(&forge, plan, commit_config).await,
+    }
+}
+
+/// Proposes all updates in a single pull request from the configured branch.
+async fn publish_combined(
+    forge: &ForgeApi<'_>,
+    plan: &UpdatePlan,
+    commit_config: &CommitConfig,
+) -> Result<()> {
+    let config = forge.config;
+    let vars = template::plan_vars(plan);
+    let (default_title, default_body) = match plan.services.len() {
+        1 => (
+            template::DEFAULT_SERVICE_TITLE,
+            template::DEFAULT_SERVICE_BODY,
+        ),
+        _ => (
+            template::DEFAULT_COMBINED_TITLE,
+            template::DEFAULT_COMBINED_BODY,
+        ),
+    };
+    let title = template::render(config.title.as_deref().unwrap_or(default_title), &vars);
+    let body = template::render(config.body.as_deref().unwrap_or(default_body), &vars);
+
+    let existing = forge.find(&config.branch, "open").await?;
+    if let Some(pr) = &existing {
+        if pr.title == title && pr.body.as_deref().unwrap_or_default() == body {
+            info!("Identical pull request already open: {}", pr.html_url);
+            return Ok(());
+        }
+    }
+
+    let repos = commit::commit_updates(plan, commit_config, CommitRef::Branch(&config.branch))?;
+    push(forge, &repos, &config.branch)?;
+
+    let pr = match existing {
+        Some(pr) => forge.update(pr.number, &title, &body).await?,
+        None => forge.create(&config.branch, &title, &body).await?,
+    };
+    finish(forge, &pr).await
+}
+
+/// Proposes every service update in its own pull request from a branch named
+/// after the service and version. Bumps that were already proposed are skipped
+/// and pull requests for older versions of the same service are closed.
+async fn publish_per_update(
+    forge: &ForgeApi<'_>,
+    plan: &UpdatePlan,
+    commit_config: &CommitConfig,
+) -> Result<()> {
+    let config = forge.config;
+    let open = forge.list_open().await?;
+
+    for update in &plan.services {
+        let branch = update_branch(&config.branch_prefix, &update.service, &update.version);
+        let service_prefix = update_branch(&config.branch_prefix, &update.service, "");
+
+        let pr = match forge.find(&branch, "all").await? {
+            Some(pr) if pr.state == "open" => {
+                info!(
+                    "Update of '{}' to {} is already proposed: {}",
+                    update.service, update.version, pr.html_url
+                );
+                pr
+            }
+            Some(pr) => {
+                info!(
+                    "Update of '{}' to {} was already proposed and closed, not reopening: {}",
+                    update.service, update.version, pr.html_url
+                );
+                continue;
+            }
+            None => {
+                let vars = template::service_vars(update);
+                let title = template::render(
+                    config
+                        .title
+                        .as_deref()
+                        .unwrap_or(template::DEFAULT_SERVICE_TITLE),
+                    &vars,
+                );
+                let body = template::render(
+                    config
+                        .body
+                        .as_deref()
+                        .unwrap_or(template::DEFAULT_SERVICE_BODY),
+                    &vars,
+                );
+
+                let repos =
+                    commit::commit_service(update, commit_config, CommitRef::Branch(&branch))?;
+                push(forge, &repos, &branch)?;
+                let pr = forge.create(&branch, &title, &body).await?;
+                finish(forge, &pr).await?;
+                pr
+            }
+        };
+
+        for superseded in open
+            .iter()
+            .filter(|old| old.head.branch.starts_with(&service_prefix) && old.head.branch != branch)
+        {
+            info!(
+                "Closing pull request #{} superseded by #{}",
+                superseded.number, pr.number
+            );
+            forge
+                .close(superseded.number, &format!("Superseded by #{}.", pr.number))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Builds the deterministic branch name of a single service update.
+fn update_branch(prefix: &str, service: &str, version: &str) -> String {
+    let sanitize = |value: &str| -> String {
+        value
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect()
+    };
+    format!("{}{}/{}", prefix, sanitize(service), sanitize(version))
+}
+
+fn push(forge: &ForgeApi, repos: &[PathBuf], branch: &str) -> Result<(), AppError> {
+    let [workdir] = repos else {
+        return Err(AppError::PullRequestError(format!(
+            "Updated files must live in a single repository, found {}",
+            repos.len()
+        )));
+    };
+    commit::push_branch(workdir, &forge.config.remote, branch, Some(forge.token))
+}
+
+async fn finish(forge: &ForgeApi<'_>, pr: &PullRequest) -> Result<()> {
+    if !forge.config.labels.is_empty() {
+        forge.add_labels(pr.number, &forge.config.labels).await?;
+    }
+    if !forge.config.assignees.is_empty() {
+        forge
+            .add_assignees(pr.number, &forge.config.assignees)
+            .await?;
+    }
+    info!("Pull request #{} is open: {}", pr.number, pr.html_url);
+    Ok(())
+}
+
+/// A minimal client for the pull request APIs of GitHub and Gitea/Forgejo, which
+/// only differ in a few endpoints and in how they authenticate.
+struct ForgeApi<'a> {
+    client: Client,
+    config: &'a PullRequestConfig,
+    token: &'a str,
+    repo_url: String,
+}
+
+impl<'a> ForgeApi<'a> {
+    fn new(
+        config: &'a PullRequestConfig,
+        token: &'a str,
+        http: &HttpConfig,
+    ) -> Result<Self, AppError> {
+        let repo_url = match (config.provider, config.api_url.as_deref()) {
+            (Forge::Github, api_url) => format!(
+                "{}/repos/{}",
+                api_url.unwrap_or(DEFAULT_GITHUB_API).trim_end_matches('/'),
+                config.repo
+            ),
+            (Forge::Gitea, Some(url)) => {
+                format!("{}/api/v1/repos/{}", url.trim_end_matches('/'), config.repo)
+            }
+            (Forge::Gitea, None) => {
+                return Err(AppError::PullRequestError(
+                    "api_url is required for Gitea/Forgejo".to_string(),
+                ))
+            }
+        };
+        Ok(Self {
+            client: http::client(http)?,
+            config,
+            token,
+            repo_url,
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+        let url = format!("{}/{}", self.repo_url, path);
+        debug!("{} API request {} {}", self.config.provider, method, url);
+        let request = self
+            .client
+            .request(method, url)
+            .header(USER_AGENT, USER_AGENT_NAME);
+        match self.config.provider {
+            Forge::Github => request
+                .header(ACCEPT, "application/vnd.github+json")
+                .bearer_auth(self.token),
+            Forge::Gitea => request
+                .header(ACCEPT, "application/json")
+                .header(AUTHORIZATION, format!("token {}", self.token)),
+        }
+    }
+
+    async fn send<T: for<'de> Deserialize<'de>>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, AppError> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| AppError::PullRequestError(e.to_string()))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| AppError::PullRequestError(e.to_string()))?;
+        trace!("{} API response {}: {}", self.config.provider, status, body);
+        if !status.is_success() {
+            return Err(AppError::PullRequestError(format!(
+                "{} API returned {}: {}",
+                self.config.provider, status, body
+            )));
+        }
+        serde_json::from_str(&body).map_err(|e| AppError::InvalidResponse(e.to_string()))
+    }
+
+    /// Returns the most recent pull request from `branch` in the given state (`open` or `all`).
+    async fn find(&self, branch: &str, state: &str) -> Result<Option<PullRequest>, AppError> {
+        let pulls = self.list(state, Some(branch)).await?;
+        Ok(pulls.into_iter().find(|pr| pr.head.branch == branch))
+    }
+
+    async fn list_open(&self) -> Result<Vec<PullRequest>, AppError> {
+        self.list("open", None).await
+    }
+
+    /// Lists the pull requests in the given state, following the pages until a
+    /// short one comes back. With a `head` branch, GitHub filters on the server
+    /// and Gitea, which cannot, stops at the first page containing the branch.
+    async fn list(&self, state: &str, head: Option<&str>) -> Result<Vec<PullRequest>, AppError> {
+        let (size_param, page_size) = match self.config.provider {
+            Forge::Github => ("per_page", 100),
+            Forge::Gitea => ("limit", 50),
+        };
+        let mut query = vec![
+            ("state", state.to_string()),
+            (size_param, page_size.to_string()),
+        ];
+        if let (Forge::Github, Some(branch)) = (self.config.provider, head) {
+            let owner = self.config.repo.split('/').next().unwrap_or_default();
+            query.push(("head", format!("{}:{}", owner, branch)));
+        }
+
+        let mut pulls = Vec::new();
+        for page in 1.. {
+            let request = self
+                .request(reqwest::Method::GET, "pulls")
+                .query(&query)
+                .query(&[("page", page)]);
+            let batch: Vec<PullRequest> = self.send(request).await?;
+            let last = batch.len() < page_size
+                || head.is_some_and(|branch| batch.iter().any(|pr| pr.head.branch == branch));
+            pulls.extend(batch);
+            if last {
+                break;
+            }
+        }
+        Ok(pulls)
+    }
+
+    async fn create(&self, branch: &str, title: &str, body: &str) -> Result<PullRequest, AppError> {
+        let request = self.request(reqwest::Method::POST, "pulls").json(&json!({
+            "title": title,
+            "body": body,
+            "head": branch,
+            "base": self.config.base,
+        }));
+        self.send(request).await
+    }
+
+    async fn update(&self, number: u64, title: &str, body: &str) -> Result<PullRequest, AppError> {
+        let request = self
+            .request(reqwest::Method::PATCH, &format!("pulls/{}", number))
+            .json(&json!({ "title": title, "body": body }));
+        self.send(request).await
+    }
+
+    async fn close(&self, number: u64, comment: &str) -> Result<(), AppError> {
+        let request = self
+            .request(
+                reqwest::Method::POST,
+                &format!("issues/{}/comments", number),
+            )
+            .json(&json!({ "body": comment }));
+        self.send::<serde_json::Value>(request).await?;
+        let request = self
+            .request(reqwest::Method::PATCH, &format!("pulls/{}", number))
+            .json(&json!({ "state": "closed" }));
+        self.send::<serde_json::Value>(request).await.map(|_| ())
+    }
+
+    async fn add_labels(&self, number: u64, labels: &[String]) -> Result<(), AppError> {
+        let payload = match self.config.provider {
+            Forge::Github => json!({ "labels": labels }),
+            Forge::Gitea => {
+                // Gitea expects label IDs rather than names
+                let existing: Vec<Label> = self
+                    .send(self.request(reqwest::Method::GET, "labels"))
+                    .await?;
+                let ids: Vec<u64> = labels
+                    .iter()
+                    .filter_map(|name| {
+                        let id = existing.iter().find(|l| &l.name == name).map(|l| l.id);
+                        if id.is_none() {
+                            warn!("Label '{}' does not exist in {}", name, self.config.repo);
+                        }
+                        id
+                    })
+                    .collect();
+                json!({ "labels": ids })
+            }
+        };
+        let request = self
+            .request(reqwest::Method::POST, &format!("issues/{}/labels", number))
+            .json(&payload);
+        self.send::<serde_json::Value>(request).await.map(|_| ())
+    }
+
+    async fn add_assignees(&self, number: u64, assignees: &[String]) -> Result<(), AppError> {
+        let payload = json!({ "assignees": assignees });
+        let request = match self.config.provider {
+            Forge::Github => self.request(
+                reqwest::Method::POST,
+                &format!("issues/{}/assignees", number),
+            ),
+            Forge::Gitea => self.request(reqwest::Method::PATCH, &format!("issues/{}", number)),
+        };
+        self.send::<serde_json::Value>(request.json(&payload))
+            .await
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn pulls(numbers: std::ops::Range<u64>) -> serde_json::Value {
+        numbers
+            .map(|number| {
+                json!({
+                    "number": number,
+                    "title": format!("Update {}", number),
+                    "html_url": format!("https://example.org/pulls/{}", number),
+                    "state": "open",
+                    "head": { "ref": format!("version-updater/app/{}", number) },
+                })
+            })
+            .collect()
+    }
+
+    async fn mount_page(server: &MockServer, api_path: &str, page: &str, body: serde_json::Value) {
+        Mock::given(method("GET"))
+            .and(path(api_path))
+            .and(query_param("page", page))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    fn config(provider: &str, server: &MockServer) -> PullRequestConfig {
+        serde_json::from_value(json!({
+            "provider": provider,
+            "repo": "owner/app",
+            "api_url": server.uri(),
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn open_pull_requests_are_listed_from_every_page() {
+        let server = MockServer::start().await;
+        mount_page(&server, "/repos/owner/app/pulls", "1", pulls(0..100)).await;
+        mount_page(&server, "/repos/owner/app/pulls", "2", pulls(100..101)).await;
+        let config = config("github", &server);
+        let forge = ForgeApi::new(&config, "token", &HttpConfig::default()).unwrap();
+
+        assert_eq!(forge.list_open().await.unwrap().len(), 101);
+    }
+
+    #[tokio::test]
+    async fn gitea_pages_are_read_until_the_branch_is_found() {
+        let server = MockServer::start().await;
+        let api_path = "/api/v1/repos/owner/app/pulls";
+        mount_page(&server, api_path, "1", pulls(0..50)).await;
+        mount_page(&server, api_path, "2", pulls(50..100)).await;
+        let config = config("gitea", &server);
+        let forge = ForgeApi::new(&config, "token", &HttpConfig::default()).unwrap();
+
+        let pr = forge.find("version-updater/app/60", "all").await.unwrap();
+        assert_eq!(pr.map(|pr| pr.number), Some(60));
+    }
+}
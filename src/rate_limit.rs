@@ -0,0 +1,161 @@
+use super::proxy::matches_host;
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Default limits in requests per second, applied unless `http.rate_limits` is set.
+pub fn default_rate_limits() -> BTreeMap<String, f64> {
+    BTreeMap::from([
+        ("api.github.com".to_string(), 5.0),
+        ("registry.hub.docker.com".to_string(), 3.0),
+    ])
+}
+
+/// Token buckets per host, shared by all clones so concurrent service tasks
+/// draw from the same budget. A bucket holds up to one second of requests.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    limits: Arc<Vec<(String, f64)>>,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Limits are in requests per second, keyed by host or `*.domain`.
+    pub fn new(limits: &BTreeMap<String, f64>) -> Self {
+        Self {
+            limits: Arc::new(
+                limits
+                    .iter()
+                    .filter(|(_, rate)| **rate > 0.0)
+                    .map(|(host, rate)| (host.to_lowercase(), *rate))
+                    .collect(),
+            ),
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Waits until a request to the host of `url` is allowed.
+    pub async fn acquire(&self, url: &Url) {
+        let Some(host) = url.host_str().map(str::to_lowercase) else {
+            return;
+        };
+        let Some(rate) = self
+            .limits
+            .iter()
+            .find(|(pattern, _)| matches_host(pattern, &host))
+            .map(|(_, rate)| *rate)
+        else {
+            return;
+        };
+
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let now = Instant::now();
+                let bucket = buckets.entry(host.clone()).or_insert(Bucket {
+                    tokens: rate.max(1.0),
+                    updated: now,
+                });
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * rate).min(rate.max(1.0));
+                bucket.updated = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / rate)
+            };
+            debug!("Rate limiting {}, waiting {}ms", host, wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// The request quota a host reported in its `x-ratelimit-*` (GitHub) or
+/// `ratelimit-*` (Docker Hub, GitLab) response headers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+    pub remaining: u64,
+    pub limit: Option<u64>,
+    /// When the quota is replenished, if the host reports it
+    pub reset: Option<SystemTime>,
+    /// The window the limit applies to, e.g. 6 hours on Docker Hub
+    pub window: Option<Duration>,
+}
+
+impl Budget {
+    fn parse(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| {
+            let value = headers
+                .get(format!("x-ratelimit-{}", name))
+                .or_else(|| headers.get(format!("ratelimit-{}", name)))?;
+            value.to_str().ok().map(str::trim)
+        };
+        // Docker Hub appends the window, e.g. `76;w=21600`
+        let (remaining, window) = match header("remaining")?.split_once(';') {
+            Some((remaining, params)) => {
+                let window = params
+                    .split(';')
+                    .find_map(|param| param.trim().strip_prefix("w="))
+                    .and_then(|seconds| seconds.parse().ok())
+                    .map(Duration::from_secs);
+                (remaining, window)
+            }
+            None => (header("remaining")?, None),
+        };
+        let limit = header("limit")
+            .and_then(|limit| limit.split(';').next())
+            .and_then(|limit| limit.trim().parse().ok());
+        let reset = header("reset")
+            .and_then(|reset| reset.parse().ok())
+            .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds));
+        Some(Self {
+            remaining: remaining.trim().parse().ok()?,
+            limit,
+            reset,
+            window,
+        })
+    }
+
+    /// Whether less than a tenth of the limit is left.
+    pub fn is_low(&self) -> bool {
+        match self.limit {
+            Some(limit) => self.remaining * 10 < limit,
+            None => self.remaining == 0,
+        }
+    }
+}
+
+/// The lowest `Budget` each host reported during a run, shared by all clones.
+#[derive(Clone, Default)]
+pub struct BudgetTracker {
+    budgets: Arc<Mutex<BTreeMap<String, Budget>>>,
+}
+
+impl BudgetTracker {
+    /// Records the budget reported in the response `headers` of `url`, if any.
+    pub fn observe(&self, url: &Url, headers: &HeaderMap) {
+        let (Some(host), Some(budget)) = (url.host_str(), Budget::parse(headers)) else {
+            return;
+        };
+        debug!("{} has {} requests left", host, budget.remaining);
+        let mut budgets = self.budgets.lock().unwrap();
+        let lowest = budgets.entry(host.to_lowercase()).or_insert(budget);
+        if budget.remaining < lowest.remaining {
+            *lowest = budget;
+        }
+    }
+
+    pub fn budgets(&self) -> BTreeMap<String, Budget> {
+        self.budgets.lock().unwrap().clone()
+    }
+}
@@ -1,14 +1,25 @@
-use crate::git::USER_AGENT_NAME;
+use crate::git::{GitConfig, Provider, USER_AGENT_NAME};
 
+use super::env;
 use super::error::AppError;
+use super::http::HttpClient;
+use super::netrc;
+use super::tag_template;
 use anyhow::Result;
 use base64::{engine::general_purpose::STANDARD, Engine as _};
-use log::{debug, info, trace, warn};
 use regex::Regex;
 use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE, USER_AGENT};
-use reqwest::{Client, StatusCode};
-use serde::{Deserialize, Serialize};
+use reqwest::StatusCode;
+use serde::de::{self, DeserializeOwned, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::PathBuf;
+use tracing::{debug, info, trace, warn};
 
 #[derive(Debug)]
 pub struct ImageParts {
@@ -64,60 +75,932 @@ struct TokenResponse {
 pub struct ImageConfig {
     pub name: String,
     pub tag: String,
+    /// Credentials for this image, defaults to `global.registries` for the
+    /// registry of the image. Both take precedence over ~/.docker/config.json
+    #[serde(default, rename = "auth")]
+    pub credentials: Option<RegistryCredentials>,
+    /// Where the tags of the image are looked up
+    #[serde(default)]
+    pub tag_source: TagSource,
+}
+
+/// The API listing the tags of an image and their digests.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TagSource {
+    /// The registry API of the image's registry
+    #[default]
+    Registry,
+    /// The GitHub Packages API, for ghcr.io images, authenticated with the
+    /// GitHub token instead of a registry token
+    GithubPackages,
+    /// The GitLab container registry API, for registry.gitlab.com images,
+    /// authenticated with the GitLab token or CI job token
+    GitlabRegistry,
+}
+
+impl ImageConfig {
+    pub fn new(name: &str, tag: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            tag: tag.to_string(),
+            credentials: None,
+            tag_source: TagSource::Registry,
+        }
+    }
+
+    /// The image of a service that only reports the upstream version
+    /// (`image: none`). The tag is the version itself.
+    pub fn none() -> Self {
+        Self {
+            name: String::new(),
+            tag: "${RELEASE_VERSION}".to_string(),
+            credentials: None,
+            tag_source: TagSource::Registry,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    /// The registry host of the image, `registry.hub.docker.com` for Docker Hub.
+    pub fn registry(&self) -> String {
+        extract_registry(&self.name).registry
+    }
+
+    /// Checks the filters of the tag template and that the `tag_source` can
+    /// serve the image.
+    pub fn validate(&self) -> Result<(), AppError> {
+        tag_template::check(&self.tag)?;
+        let (source, registry) = match self.tag_source {
+            TagSource::Registry => return Ok(()),
+            TagSource::GithubPackages => ("github_packages", "ghcr.io"),
+            TagSource::GitlabRegistry => ("gitlab_registry", "registry.gitlab.com"),
+        };
+        if self.registry() != registry {
+            return Err(AppError::ConfigError(format!(
+                "tag_source {} needs a {} image, got {}",
+                source, registry, self.name
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Deserializes an image section, or `none` for services without image.
+pub fn deserialize_image<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ImageConfig, D::Error> {
+    struct ImageVisitor;
+
+    impl<'de> Visitor<'de> for ImageVisitor {
+        type Value = ImageConfig;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an image with name and tag, or \"none\"")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<ImageConfig, E> {
+            match value {
+                "none" => Ok(ImageConfig::none()),
+                _ => Err(E::invalid_value(de::Unexpected::Str(value), &self)),
+            }
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ImageConfig, A::Error> {
+            ImageConfig::deserialize(de::value::MapAccessDeserializer::new(map))
+        }
+    }
+
+    deserializer.deserialize_any(ImageVisitor)
+}
+
+/// Credentials for a registry. The password can be given inline, read from an
+/// environment variable or from a file such as a mounted Docker or Kubernetes
+/// secret.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegistryCredentials {
+    pub username: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Name of the environment variable holding the password
+    #[serde(default)]
+    pub password_env: Option<String>,
+    #[serde(default)]
+    pub password_file: Option<PathBuf>,
+}
+
+impl RegistryCredentials {
+    /// Returns the username and password.
+    pub fn resolve(&self) -> Result<(String, String), AppError> {
+        let password = if let Some(password) = &self.password {
+            password.clone()
+        } else if let Some(name) = &self.password_env {
+            env::secret(name)?.ok_or_else(|| {
+                AppError::SecretError(format!(
+                    "{} is not set for registry user {}",
+                    name, self.username
+                ))
+            })?
+        } else if let Some(path) = &self.password_file {
+            env::read_secret(path)?
+        } else {
+            return Err(AppError::SecretError(format!(
+                "No password, password_env or password_file for registry user {}",
+                self.username
+            )));
+        };
+        Ok((self.username.clone(), password))
+    }
+}
+
+/// Looks up the credentials for the registry of `image` in `registries`, which
+/// is keyed by registry host. `docker.io` stands for Docker Hub.
+pub fn credentials_for(
+    registries: &BTreeMap<String, RegistryCredentials>,
+    image: &str,
+) -> Option<RegistryCredentials> {
+    let registry = extract_registry(image).registry;
+    registries
+        .iter()
+        .find(|(host, _)| canonical_registry(host) == registry)
+        .map(|(_, credentials)| credentials.clone())
+}
+
+/// The registry host images are resolved against, with the Docker Hub
+/// aliases mapped to `registry.hub.docker.com`.
+pub fn canonical_registry(host: &str) -> &str {
+    match host {
+        "docker.io" | "index.docker.io" | "registry-1.docker.io" => "registry.hub.docker.com",
+        host => host,
+    }
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct DockerConfig {
+    #[serde(default)]
     auths: std::collections::HashMap<String, DockerAuth>,
+    /// Credential helper for all registries
+    #[serde(default)]
+    creds_store: Option<String>,
+    /// Credential helpers by registry
+    #[serde(default)]
+    cred_helpers: BTreeMap<String, String>,
 }
 pub struct RegistryClient {
-    client: Client,
+    http: HttpClient,
     registry: String,
     image_path: String,
+    credentials: Option<RegistryCredentials>,
+    tag_source: TagSource,
 }
 
 impl RegistryClient {
-    pub fn new(full_image_name: &str) -> Self {
+    pub fn new(full_image_name: &str, http: HttpClient) -> Self {
         let image_parts = extract_registry(full_image_name);
         Self {
-            client: Client::new(),
+            http,
             registry: image_parts.registry,
             image_path: image_parts.image_path,
+            credentials: None,
+            tag_source: TagSource::Registry,
         }
     }
 
-    pub async fn validate_tag(&self, tag: &str) -> Result<bool, AppError> {
-        info!("Validating tag '{}' for image '{}'", tag, self.image_path);
+    /// The registry host, `registry.hub.docker.com` for Docker Hub images.
+    pub fn registry(&self) -> &str {
+        &self.registry
+    }
 
-        let creds = get_docker_credentials(&self.registry)
-            .map_err(|e| AppError::CredentialsError(e.to_string()))?;
+    /// Uses `credentials` instead of those in ~/.docker/config.json.
+    pub fn with_credentials(mut self, credentials: Option<RegistryCredentials>) -> Self {
+        self.credentials = credentials;
+        self
+    }
 
-        let token = get_registry_token(&self.client, &self.registry, &self.image_path, creds)
-            .await
-            .map_err(|e| AppError::AuthenticationError(e.to_string()))?;
+    /// Looks up tags and digests through `tag_source` instead of the registry API.
+    pub fn with_tag_source(mut self, tag_source: TagSource) -> Self {
+        self.tag_source = tag_source;
+        self
+    }
+
+    /// Checks that `tag` exists and returns the digest of its manifest, which
+    /// is empty if the registry doesn't report it, or `None` if the tag is missing.
+    pub async fn tag_digest(&self, tag: &str) -> Result<Option<String>, AppError> {
+        info!("Validating tag '{}' for image '{}'", tag, self.image_path);
+        match self.tag_source {
+            TagSource::Registry => {}
+            TagSource::GithubPackages => {
+                let versions = self.package_versions().await?;
+                return Ok(versions
+                    .into_iter()
+                    .find(|version| version.tags.iter().any(|t| t == tag))
+                    .map(|version| version.digest));
+            }
+            TagSource::GitlabRegistry => return self.gitlab_tag_digest(tag).await,
+        }
 
         let manifest_url = format!(
             "https://{}/v2/{}/manifests/{}",
             self.registry, self.image_path, tag
         );
+        let cache_key = self.lookup_key(&format!("manifest-digest:{}", manifest_url));
+        if let Some(digest) = self.http.cached(&cache_key) {
+            if let Ok(digest) = serde_json::from_str(&digest) {
+                return Ok(digest);
+            }
+        }
+
+        self.http.ensure_online(&manifest_url)?;
+        let lookup = async {
+            let token = self.token().await?;
+            let digest = check_manifest(&self.http, &manifest_url, token.as_deref()).await?;
+            let value = serde_json::to_string(&digest)
+                .map_err(|e| AppError::InvalidResponse(e.to_string()))?;
+            self.http.store(&cache_key, &value);
+            Ok::<_, AppError>(value)
+        };
+        let value = self.http.coalesce(&cache_key, lookup).await?;
+        serde_json::from_str(&value).map_err(|e| AppError::InvalidResponse(e.to_string()))
+    }
+
+    /// The blobs and child manifests of the manifest `reference`, a tag or
+    /// digest, that the registry doesn't have. A manifest whose blobs are gone
+    /// still passes `tag_digest`, but the image can't be pulled.
+    pub async fn missing_blobs(&self, reference: &str) -> Result<Vec<String>, AppError> {
+        let manifest_url = format!(
+            "https://{}/v2/{}/manifests/{}",
+            self.registry, self.image_path, reference
+        );
+        let cache_key = self.lookup_key(&format!("missing-blobs:{}", manifest_url));
+        if let Some(missing) = self.http.cached(&cache_key) {
+            if let Ok(missing) = serde_json::from_str(&missing) {
+                return Ok(missing);
+            }
+        }
+
+        self.http.ensure_online(&manifest_url)?;
+        let lookup = async {
+            let token = self.token().await?;
+            let missing = self.check_blobs(reference, token.as_deref()).await?;
+            let value = serde_json::to_string(&missing)
+                .map_err(|e| AppError::InvalidResponse(e.to_string()))?;
+            // A missing blob may still be pushed, only complete images are cached
+            if missing.is_empty() {
+                self.http.store(&cache_key, &value);
+            }
+            Ok::<_, AppError>(value)
+        };
+        let value = self.http.coalesce(&cache_key, lookup).await?;
+        serde_json::from_str(&value).map_err(|e| AppError::InvalidResponse(e.to_string()))
+    }
+
+    /// Checks the config and layer blobs of the manifest `reference`, and
+    /// those of each platform of an image index.
+    async fn check_blobs(
+        &self,
+        reference: &str,
+        token: Option<&str>,
+    ) -> Result<Vec<String>, AppError> {
+        let Some(manifest) = self.manifest(reference, token).await? else {
+            return Ok(vec![reference.to_string()]);
+        };
+        let mut missing = Vec::new();
+        for child in &manifest.manifests {
+            let Some(child) = self.manifest(&child.digest, token).await? else {
+                missing.push(child.digest.clone());
+                continue;
+            };
+            missing.extend(self.missing_of(&child, token).await?);
+        }
+        missing.extend(self.missing_of(&manifest, token).await?);
+        Ok(missing)
+    }
+
+    /// The config and layer blobs of an image manifest that are missing.
+    async fn missing_of(
+        &self,
+        manifest: &Manifest,
+        token: Option<&str>,
+    ) -> Result<Vec<String>, AppError> {
+        let mut missing = Vec::new();
+        for blob in manifest.config.iter().chain(&manifest.layers) {
+            let url = format!(
+                "https://{}/v2/{}/blobs/{}",
+                self.registry, self.image_path, blob.digest
+            );
+            debug!("Checking blob {}", url);
+            let mut request = self.http.head(&url).header(USER_AGENT, USER_AGENT_NAME);
+            if let Some(token) = token {
+                request = request.header("Authorization", format!("Bearer {}", token));
+            }
+            let response = self.http.send(request).await.map_err(|e| {
+                AppError::RequestError(format!("Failed to send blob request: {}", e))
+            })?;
+            match response.status() {
+                status if status.is_success() => {}
+                StatusCode::NOT_FOUND => {
+                    warn!("Blob {} of {} is missing", blob.digest, self.image_path);
+                    missing.push(blob.digest.clone());
+                }
+                StatusCode::TOO_MANY_REQUESTS => {
+                    return Err(AppError::RateLimited(format!("{} blobs", self.registry)))
+                }
+                status => {
+                    return Err(AppError::RequestError(format!(
+                        "{} returned {}",
+                        url, status
+                    )))
+                }
+            }
+        }
+        Ok(missing)
+    }
+
+    /// The manifest or image index `reference`, `None` if it is missing.
+    async fn manifest(
+        &self,
+        reference: &str,
+        token: Option<&str>,
+    ) -> Result<Option<Manifest>, AppError> {
+        let url = format!(
+            "https://{}/v2/{}/manifests/{}",
+            self.registry, self.image_path, reference
+        );
+        let mut request = self
+            .http
+            .get(&url)
+            .header(ACCEPT, MANIFEST_TYPES.join(", "))
+            .header(USER_AGENT, USER_AGENT_NAME);
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        let response = self.http.send(request).await.map_err(|e| {
+            AppError::RequestError(format!("Failed to send manifest request: {}", e))
+        })?;
+        match response.status() {
+            StatusCode::OK => response
+                .json()
+                .await
+                .map(Some)
+                .map_err(|e| AppError::InvalidResponse(format!("Manifest {}: {}", url, e))),
+            StatusCode::NOT_FOUND => Ok(None),
+            StatusCode::TOO_MANY_REQUESTS => Err(AppError::RateLimited(format!(
+                "{} manifests",
+                self.registry
+            ))),
+            status => Err(AppError::RequestError(format!(
+                "{} returned {}",
+                url, status
+            ))),
+        }
+    }
 
-        check_manifest(&self.client, &manifest_url, token.as_deref()).await
+    /// Finds the highest version among the tags matching `template`, e.g.
+    /// `1.27.2` for the tag `1.27.2-alpine` with the template
+    /// `${RELEASE_VERSION}-alpine`. Versions are dot-separated numbers.
+    pub async fn latest_version(&self, template: &str) -> Result<String, AppError> {
+        let pattern = tag_template::pattern(template, "RELEASE_VERSION", r"(\d+(?:\.\d+)*)");
+        let pattern = Regex::new(&pattern).map_err(|e| AppError::ConfigError(e.to_string()))?;
+
+        let tags = self.list_tags().await?;
+        let version = tags
+            .iter()
+            .filter_map(|tag| pattern.captures(tag))
+            .filter_map(|captures| captures.get(1))
+            .map(|version| version.as_str())
+            .max_by_key(|version| version_key(version))
+            .ok_or_else(|| {
+                AppError::NotFound(format!(
+                    "No tag of {} matches {}",
+                    self.image_path, template
+                ))
+            })?;
+        info!("Latest version of {} is {}", self.image_path, version);
+        Ok(version.to_string())
+    }
+
+    /// Lists all tags of the image, following the pagination links. Services
+    /// with the same credentials share the lookup.
+    pub async fn list_tags(&self) -> Result<Vec<String>, AppError> {
+        match self.tag_source {
+            TagSource::Registry => {}
+            TagSource::GithubPackages => {
+                let versions = self.package_versions().await?;
+                return Ok(versions.into_iter().flat_map(|v| v.tags).collect());
+            }
+            TagSource::GitlabRegistry => return self.gitlab_tags().await,
+        }
+
+        let url = format!(
+            "https://{}/v2/{}/tags/list?n=1000",
+            self.registry, self.image_path
+        );
+        let cache_key = self.lookup_key(&url);
+        if let Some(body) = self.http.cached(&cache_key) {
+            return serde_json::from_str(&body)
+                .map_err(|e| AppError::InvalidResponse(e.to_string()));
+        }
+        self.http.ensure_online(&url)?;
+        let lookup = async {
+            let tags = self.registry_tags(url).await?;
+            let value = serde_json::to_string(&tags)
+                .map_err(|e| AppError::InvalidResponse(e.to_string()))?;
+            self.http.store(&cache_key, &value);
+            Ok::<_, AppError>(value)
+        };
+        let value = self.http.coalesce(&cache_key, lookup).await?;
+        serde_json::from_str(&value).map_err(|e| AppError::InvalidResponse(e.to_string()))
+    }
+
+    /// Follows the pages of the tag list at `url` of the registry API.
+    async fn registry_tags(&self, mut url: String) -> Result<Vec<String>, AppError> {
+        #[derive(Deserialize)]
+        struct TagList {
+            #[serde(default)]
+            tags: Option<Vec<String>>,
+        }
+
+        let token = self.token().await?;
+        let mut tags = Vec::new();
+        for _ in 0..MAX_TAG_PAGES {
+            info!("Listing tags at URL: {}", url);
+            let mut request = self.http.get(&url).header(USER_AGENT, USER_AGENT_NAME);
+            if let Some(token) = &token {
+                request = request.header("Authorization", format!("Bearer {}", token));
+            }
+            let response = self
+                .http
+                .send(request)
+                .await
+                .map_err(|e| AppError::RequestError(format!("Failed to list tags: {}", e)))?;
+            match response.status() {
+                StatusCode::OK => {}
+                StatusCode::NOT_FOUND => {
+                    return Err(AppError::ImageNotFound(self.image_path.clone()));
+                }
+                StatusCode::TOO_MANY_REQUESTS => {
+                    return Err(AppError::RateLimited(self.registry.clone()));
+                }
+                status => {
+                    return Err(AppError::RequestError(format!(
+                        "Listing tags returned {}",
+                        status
+                    )))
+                }
+            }
+            let next = response
+                .headers()
+                .get("Link")
+                .and_then(|link| link.to_str().ok())
+                .and_then(next_link);
+            let page: TagList = response
+                .json()
+                .await
+                .map_err(|e| AppError::InvalidResponse(e.to_string()))?;
+            tags.extend(page.tags.unwrap_or_default());
+            match next {
+                Some(next) if next.starts_with('/') => {
+                    url = format!("https://{}{}", self.registry, next)
+                }
+                Some(next) => url = next,
+                None => break,
+            }
+        }
+        debug!("Found {} tags for {}", tags.len(), self.image_path);
+        Ok(tags)
+    }
+
+    /// The versions of the image in the GitHub Packages API, newest first,
+    /// shared by the tag and digest lookups of a run. The package is looked up
+    /// in the organization of its owner, or else in the user's packages.
+    async fn package_versions(&self) -> Result<Vec<PackageVersion>, AppError> {
+        let (owner, package) = self
+            .image_path
+            .split_once('/')
+            .ok_or_else(|| AppError::ImageNotFound(self.image_path.clone()))?;
+        let package = package.replace('/', "%2F");
+        let cache_key = format!("github-packages:{}/{}", owner, package);
+        let versions = match self.http.cached(&cache_key) {
+            Some(versions) => versions,
+            None => {
+                let lookup = async {
+                    let mut versions = None;
+                    for scope in ["orgs", "users"] {
+                        let url = format!(
+                            "https://api.github.com/{}/{}/packages/container/{}/versions?per_page=100",
+                            scope, owner, package
+                        );
+                        versions = self.list_package_versions(url).await?;
+                        if versions.is_some() {
+                            break;
+                        }
+                    }
+                    let versions =
+                        versions.ok_or_else(|| AppError::ImageNotFound(self.image_path.clone()))?;
+                    let value = serde_json::to_string(&versions)
+                        .map_err(|e| AppError::InvalidResponse(e.to_string()))?;
+                    self.http.store(&cache_key, &value);
+                    Ok::<_, AppError>(value)
+                };
+                self.http.coalesce(&cache_key, lookup).await?
+            }
+        };
+        serde_json::from_str(&versions).map_err(|e| AppError::InvalidResponse(e.to_string()))
+    }
+
+    /// Follows the pages of the package versions at `url`, or returns `None`
+    /// if the package doesn't exist there.
+    async fn list_package_versions(
+        &self,
+        url: String,
+    ) -> Result<Option<Vec<PackageVersion>>, AppError> {
+        #[derive(Deserialize)]
+        struct Version {
+            name: String,
+            metadata: Metadata,
+        }
+        #[derive(Deserialize)]
+        struct Metadata {
+            container: Container,
+        }
+        #[derive(Deserialize)]
+        struct Container {
+            #[serde(default)]
+            tags: Vec<String>,
+        }
+
+        let token = GitConfig::new(Provider::Github, "")
+            .token()?
+            .ok_or_else(|| {
+                AppError::AuthenticationError(
+                    "The GitHub Packages API needs GITHUB_TOKEN with read:packages".to_string(),
+                )
+            })?;
+        let auth = ("Authorization", format!("Bearer {}", token));
+        let Some(page) = self
+            .api_pages::<Version>(url, Some(auth), "GitHub Packages API")
+            .await?
+        else {
+            return Ok(None);
+        };
+        let versions: Vec<PackageVersion> = page
+            .into_iter()
+            .map(|version| PackageVersion {
+                digest: version.name,
+                tags: version.metadata.container.tags,
+            })
+            .collect();
+        let untagged = versions.iter().filter(|v| v.tags.is_empty()).count();
+        if untagged > 0 {
+            info!(
+                "{} of {} versions of {} are untagged and could be cleaned up",
+                untagged,
+                versions.len(),
+                self.image_path
+            );
+        }
+        Ok(Some(versions))
+    }
+
+    /// The API URL of the image's repository in the GitLab container registry.
+    /// Its project is the longest prefix of the image path that exists, as
+    /// images may be nested below the project path.
+    async fn gitlab_repository(&self) -> Result<String, AppError> {
+        #[derive(Deserialize)]
+        struct Repository {
+            id: u64,
+            path: String,
+        }
+
+        let cache_key = format!("gitlab-registry:{}", self.image_path);
+        if let Some(url) = self.http.cached(&cache_key) {
+            return Ok(url);
+        }
+        let lookup = async {
+            let auth = gitlab_auth()?;
+            let segments: Vec<&str> = self.image_path.split('/').collect();
+            for end in (2..=segments.len()).rev() {
+                let project = format!(
+                    "https://gitlab.com/api/v4/projects/{}",
+                    segments[..end].join("%2F")
+                );
+                let url = format!("{}/registry/repositories?per_page=100", project);
+                let Some(repositories) = self
+                    .api_pages::<Repository>(url, auth.clone(), "GitLab registry API")
+                    .await?
+                else {
+                    continue;
+                };
+                let repository = repositories
+                    .into_iter()
+                    .find(|repository| repository.path == self.image_path)
+                    .ok_or_else(|| AppError::ImageNotFound(self.image_path.clone()))?;
+                let url = format!("{}/registry/repositories/{}", project, repository.id);
+                self.http.store(&cache_key, &url);
+                return Ok(url);
+            }
+            Err(AppError::ImageNotFound(self.image_path.clone()))
+        };
+        self.http.coalesce(&cache_key, lookup).await
+    }
+
+    /// The tags of the image in the GitLab registry API.
+    async fn gitlab_tags(&self) -> Result<Vec<String>, AppError> {
+        #[derive(Deserialize)]
+        struct Tag {
+            name: String,
+        }
+
+        let url = format!("{}/tags?per_page=100", self.gitlab_repository().await?);
+        if let Some(body) = self.http.cached(&url) {
+            return serde_json::from_str(&body)
+                .map_err(|e| AppError::InvalidResponse(e.to_string()));
+        }
+        let tags: Vec<String> = self
+            .api_pages::<Tag>(url.clone(), gitlab_auth()?, "GitLab registry API")
+            .await?
+            .ok_or_else(|| AppError::ImageNotFound(self.image_path.clone()))?
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        debug!("Found {} tags for {}", tags.len(), self.image_path);
+        if let Ok(body) = serde_json::to_string(&tags) {
+            self.http.store(&url, &body);
+        }
+        Ok(tags)
+    }
+
+    /// The digest of `tag` in the GitLab registry API, or `None` if it's missing.
+    async fn gitlab_tag_digest(&self, tag: &str) -> Result<Option<String>, AppError> {
+        #[derive(Deserialize)]
+        struct TagDetails {
+            #[serde(default)]
+            digest: Option<String>,
+        }
+
+        let url = format!("{}/tags/{}", self.gitlab_repository().await?, tag);
+        let cache_key = format!("manifest-digest:{}", url);
+        if let Some(digest) = self.http.cached(&cache_key) {
+            if let Ok(digest) = serde_json::from_str(&digest) {
+                return Ok(digest);
+            }
+        }
+        self.http.ensure_online(&url)?;
+        let lookup = async {
+            let mut request = self.http.get(&url).header(USER_AGENT, USER_AGENT_NAME);
+            if let Some((header, value)) = gitlab_auth()? {
+                request = request.header(header, value);
+            }
+            let response =
+                self.http.send(request).await.map_err(|e| {
+                    AppError::RequestError(format!("Failed to get tag {}: {}", tag, e))
+                })?;
+            let digest = match response.status() {
+                StatusCode::OK => {
+                    let details: TagDetails = response
+                        .json()
+                        .await
+                        .map_err(|e| AppError::InvalidResponse(e.to_string()))?;
+                    Some(details.digest.unwrap_or_default())
+                }
+                StatusCode::NOT_FOUND => None,
+                status => return Err(api_error("GitLab registry API", status)),
+            };
+            let value = serde_json::to_string(&digest)
+                .map_err(|e| AppError::InvalidResponse(e.to_string()))?;
+            self.http.store(&cache_key, &value);
+            Ok::<_, AppError>(value)
+        };
+        let value = self.http.coalesce(&cache_key, lookup).await?;
+        serde_json::from_str(&value).map_err(|e| AppError::InvalidResponse(e.to_string()))
+    }
+
+    /// Follows the pages of the JSON list at `url` of the REST API `api`,
+    /// sending the `auth` header, or returns `None` if there's nothing at `url`.
+    async fn api_pages<T: DeserializeOwned>(
+        &self,
+        mut url: String,
+        auth: Option<(&str, String)>,
+        api: &str,
+    ) -> Result<Option<Vec<T>>, AppError> {
+        let mut items = Vec::new();
+        for _ in 0..MAX_TAG_PAGES {
+            self.http.ensure_online(&url)?;
+            info!("Listing {} at URL: {}", api, url);
+            let mut request = self
+                .http
+                .get(&url)
+                .header(USER_AGENT, USER_AGENT_NAME)
+                .header(ACCEPT, "application/json");
+            if let Some((header, value)) = &auth {
+                request = request.header(*header, value);
+            }
+            let response = self
+                .http
+                .send(request)
+                .await
+                .map_err(|e| AppError::RequestError(format!("{}: {}", api, e)))?;
+            match response.status() {
+                StatusCode::OK => {}
+                StatusCode::NOT_FOUND => return Ok(None),
+                status => return Err(api_error(api, status)),
+            }
+            let next = response
+                .headers()
+                .get("Link")
+                .and_then(|link| link.to_str().ok())
+                .and_then(next_link);
+            let page: Vec<T> = response
+                .json()
+                .await
+                .map_err(|e| AppError::InvalidResponse(e.to_string()))?;
+            items.extend(page);
+            match next {
+                Some(next) => url = next,
+                None => break,
+            }
+        }
+        Ok(Some(items))
+    }
+
+    /// The cache and coalescing key of the lookup `key`, which is only shared
+    /// by clients with the same credentials, so a service isn't handed the
+    /// result of another's login.
+    fn lookup_key(&self, key: &str) -> String {
+        match &self.credentials {
+            Some(credentials) => {
+                // An inline password only as its hash
+                let mut hasher = DefaultHasher::new();
+                credentials.password.hash(&mut hasher);
+                format!(
+                    "{}:{}:{:?}:{:?}:{:x}",
+                    key,
+                    credentials.username,
+                    credentials.password_env,
+                    credentials.password_file,
+                    hasher.finish()
+                )
+            }
+            None => format!("{}:docker-config", key),
+        }
+    }
+
+    /// A bearer token for pulling the image, using the configured credentials,
+    /// `GITHUB_TOKEN` for ghcr.io, ~/.docker/config.json or ~/.netrc in that order.
+    async fn token(&self) -> Result<Option<String>, AppError> {
+        let creds = match &self.credentials {
+            Some(credentials) => Some(credentials.resolve()?),
+            // Without credentials, `get_registry_token` sends the GitHub token
+            None if self.registry.contains("ghcr.io") && env::secret("GITHUB_TOKEN")?.is_some() => {
+                None
+            }
+            None => get_docker_credentials(&self.registry)
+                .map_err(|e| AppError::CredentialsError(e.to_string()))?
+                .or_else(|| netrc::lookup(&self.registry)),
+        };
+
+        get_registry_token(&self.http, &self.registry, &self.image_path, creds)
+            .await
+            .map_err(|e| AppError::AuthenticationError(e.to_string()))
     }
 }
 
-pub fn get_docker_credentials(registry: &str) -> Result<Option<(String, String)>, AppError> {
-    info!("Getting docker credentials for {}", registry);
+/// The header authenticating GitLab API requests: the GitLab token, or else
+/// the CI job token.
+fn gitlab_auth() -> Result<Option<(&'static str, String)>, AppError> {
+    let git = GitConfig::new(Provider::Gitlab, "");
+    Ok(match git.token()? {
+        Some(token) => Some(("PRIVATE-TOKEN", token)),
+        None => git.job_token().map(|token| ("JOB-TOKEN", token)),
+    })
+}
+
+fn api_error(api: &str, status: StatusCode) -> AppError {
+    match status {
+        StatusCode::UNAUTHORIZED => AppError::Unauthorized(api.to_string()),
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => {
+            AppError::RateLimited(api.to_string())
+        }
+        status => AppError::RequestError(format!("{} returned {}", api, status)),
+    }
+}
+
+/// A version of a container package: an image manifest and its tags.
+#[derive(Debug, Serialize, Deserialize)]
+struct PackageVersion {
+    digest: String,
+    tags: Vec<String>,
+}
+
+/// The manifest media types accepted when reading manifests, image indexes
+/// included.
+const MANIFEST_TYPES: &[&str] = &[
+    "application/vnd.oci.image.manifest.v1+json",
+    "application/vnd.docker.distribution.manifest.v2+json",
+    "application/vnd.oci.image.index.v1+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+];
+
+/// The references of an image manifest, or of an image index to the
+/// manifests of its platforms.
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    config: Option<Descriptor>,
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+    #[serde(default)]
+    manifests: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct Descriptor {
+    digest: String,
+}
+
+/// Upper bound for tag list pages, 1000 tags each.
+const MAX_TAG_PAGES: usize = 50;
+
+/// Extracts the target of the `rel="next"` link of a `Link` header.
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (target, params) = link.split_once(';')?;
+        params.contains(r#"rel="next""#).then(|| {
+            target
+                .trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+    })
+}
+
+/// Sort key of a dot-separated numeric version.
+fn version_key(version: &str) -> Vec<u64> {
+    version
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+/// Sorts `tags` by the numbers in them, highest first, so that `v1.10.0`
+/// comes before `v1.9.2`. Tags without numbers, like `latest`, come last.
+pub fn sort_tags(tags: &mut [String]) {
+    let numbers = |tag: &str| -> Vec<u64> {
+        tag.split(|c: char| !c.is_ascii_digit())
+            .filter(|part| !part.is_empty())
+            .map(|part| part.parse().unwrap_or(u64::MAX))
+            .collect()
+    };
+    tags.sort_by(|a, b| numbers(b).cmp(&numbers(a)).then_with(|| a.cmp(b)));
+}
+
+/// Reads ~/.docker/config.json, if it exists.
+fn read_docker_config() -> Result<Option<DockerConfig>, AppError> {
     let config_path = dirs::home_dir().ok_or_else(|| {
         AppError::CredentialsError("Could not determine home directory".to_string())
     })?;
     let config_path = config_path.join(".docker/config.json");
 
     trace!("Trying to read docker credentials from ~/.docker/config.json");
-    let config_contents = fs::read_to_string(config_path)
-        .map_err(|e| AppError::CredentialsError(format!("Failed to read docker config: {}", e)))?;
+    let config_contents = match fs::read_to_string(config_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(AppError::CredentialsError(format!(
+                "Failed to read docker config: {}",
+                e
+            )))
+        }
+    };
 
-    let config: DockerConfig = serde_json::from_str(&config_contents)
-        .map_err(|e| AppError::CredentialsError(format!("Failed to parse docker config: {}", e)))?;
+    serde_json::from_str(&config_contents)
+        .map(Some)
+        .map_err(|e| AppError::CredentialsError(format!("Failed to parse docker config: {}", e)))
+}
+
+/// The credential helpers of ~/.docker/config.json by registry, with the
+/// `credsStore` helper for all registries under `*`. version-updater reads
+/// the `auths` only.
+pub fn docker_credential_helpers() -> Result<BTreeMap<String, String>, AppError> {
+    let Some(config) = read_docker_config()? else {
+        return Ok(BTreeMap::new());
+    };
+    let mut helpers = config.cred_helpers;
+    if let Some(store) = config.creds_store {
+        helpers.insert("*".to_string(), store);
+    }
+    Ok(helpers)
+}
+
+pub fn get_docker_credentials(registry: &str) -> Result<Option<(String, String)>, AppError> {
+    info!("Getting docker credentials for {}", registry);
+    let Some(config) = read_docker_config()? else {
+        return Ok(None);
+    };
 
     if let Some(auth) = config.auths.get(registry) {
         // Try to get credentials from base64-encoded auth string
@@ -142,11 +1025,13 @@ pub fn get_docker_credentials(registry: &str) -> Result<Option<(String, String)>
     Ok(None)
 }
 
+/// Returns the digest of the manifest at `manifest_url` (empty if the registry
+/// doesn't send one), or `None` if there is no such manifest.
 pub async fn check_manifest(
-    client: &Client,
+    http: &HttpClient,
     manifest_url: &str,
     token: Option<&str>,
-) -> Result<bool, AppError> {
+) -> Result<Option<String>, AppError> {
     info!("Getting image manifest at URL: {}", manifest_url);
     let accept_headers = [
         "application/vnd.docker.distribution.manifest.v2+json",
@@ -157,7 +1042,7 @@ pub async fn check_manifest(
     for accept in accept_headers {
         debug!("Trying manifest format: {}", accept);
 
-        let mut request = client
+        let mut request = http
             .get(manifest_url)
             .header("Accept", accept)
             .header(USER_AGENT, USER_AGENT_NAME);
@@ -167,7 +1052,7 @@ pub async fn check_manifest(
             request = request.header("Authorization", format!("Bearer {}", token));
         }
 
-        let response = request.send().await.map_err(|e| {
+        let response = http.send(request).await.map_err(|e| {
             AppError::RequestError(format!("Failed to send manifest request: {}", e))
         })?;
 
@@ -177,7 +1062,12 @@ pub async fn check_manifest(
                     "Successfully found manifest at {} with accept header: {}",
                     manifest_url, accept
                 );
-                return Ok(true);
+                let digest = response
+                    .headers()
+                    .get("Docker-Content-Digest")
+                    .and_then(|digest| digest.to_str().ok())
+                    .unwrap_or_default();
+                return Ok(Some(digest.to_string()));
             }
             StatusCode::NOT_FOUND => {
                 if let Ok(error_body) = response.text().await {
@@ -203,7 +1093,7 @@ pub async fn check_manifest(
                     }
                 }
                 if accept == accept_headers[accept_headers.len() - 1] {
-                    return Ok(false);
+                    return Ok(None);
                 }
             }
             StatusCode::TOO_MANY_REQUESTS => {
@@ -222,14 +1112,12 @@ pub async fn check_manifest(
         }
     }
 
-    Err(AppError::ImageNotFound(format!(
-        "No manifest found for {}",
-        manifest_url
-    )))
+    debug!("No manifest found for {}", manifest_url);
+    Ok(None)
 }
 
 pub async fn get_registry_token(
-    client: &Client,
+    http: &HttpClient,
     registry: &str,
     image_name: &str,
     creds: Option<(String, String)>,
@@ -241,13 +1129,13 @@ pub async fn get_registry_token(
     info!("Getting registry token for {}", registry);
 
     let auth = RegistryAuth::from_registry(registry);
-    let token = get_token(client, &auth, image_name, creds).await?;
+    let token = get_token(http, &auth, image_name, creds).await?;
 
     Ok(Some(token))
 }
 
 async fn get_token(
-    client: &Client,
+    http: &HttpClient,
     auth: &RegistryAuth,
     image_name: &str,
     creds: Option<(String, String)>,
@@ -274,28 +1162,23 @@ async fn get_token(
     headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
     headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
 
-    let mut token_request = client.get(&token_url).headers(headers);
+    let mut token_request = http.get(&token_url).headers(headers);
 
-    // Handle authentication
-    if service == "ghcr.io" {
-        // Try GITHUB_TOKEN first
-        if let Ok(github_token) = std::env::var("GITHUB_TOKEN") {
+    // Handle authentication, ghcr.io takes the GitHub token without credentials
+    if let Some((username, password)) = creds {
+        token_request = token_request.basic_auth(username, Some(password));
+    } else if service == "ghcr.io" {
+        if let Some(github_token) = env::secret("GITHUB_TOKEN")? {
             token_request = token_request.header(
                 "Authorization",
                 HeaderValue::from_str(&format!("Bearer {}", github_token))
                     .map_err(|e| AppError::AuthenticationError(e.to_string()))?,
             );
-        } else if let Some((username, password)) = creds {
-            // Fall back to basic auth only if GITHUB_TOKEN is not present
-            token_request = token_request.basic_auth(username, Some(password));
         }
-    } else if let Some((username, password)) = creds {
-        // For non-ghcr.io services, use basic auth if credentials are available
-        token_request = token_request.basic_auth(username, Some(password));
     }
     trace!("token request client is: {:?}", token_request);
 
-    let response = token_request.send().await.map_err(|e| {
+    let response = http.send(token_request).await.map_err(|e| {
         AppError::AuthenticationError(format!("Failed to send token request: {}", e))
     })?;
 
@@ -317,7 +1200,7 @@ async fn get_token(
 
 // Helper function
 fn extract_registry(full_image_name: &str) -> ImageParts {
-    info!("Extracting image registry for image {}", full_image_name);
+    debug!("Extracting image registry for image {}", full_image_name);
     // Matches FQDN pattern: contains dots, optional port number
     let re = Regex::new(r"^([a-zA-Z0-9][-a-zA-Z0-9.]*\.[a-zA-Z]{2,})(?::\d+)?/(.+)").unwrap();
 
@@ -329,7 +1212,7 @@ fn extract_registry(full_image_name: &str) -> ImageParts {
         if !image_path.contains('/') {
             image_path = format!("library/{}", image_path);
         }
-        info!("Found image {} with registry {}", image_path, &registry);
+        debug!("Found image {} with registry {}", image_path, &registry);
         ImageParts {
             registry,
             image_path,
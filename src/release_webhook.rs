@@ -0,0 +1,278 @@
+//! Release webhooks of GitHub and GitLab received with `--serve`, which
+//! trigger an immediate check of the services of the released repository.
+
+use super::config::AppConfig;
+use super::env;
+use super::error::AppError;
+use super::git::Provider;
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::PathBuf;
+
+/// The secrets of the webhook endpoints, `global.release_webhooks`. Webhooks
+/// of a provider without secret are rejected.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReleaseWebhookConfig {
+    #[serde(default)]
+    pub github: Option<WebhookSecret>,
+    #[serde(default)]
+    pub gitlab: Option<WebhookSecret>,
+}
+
+/// The secret given when creating the webhook, read from the config, an
+/// environment variable or a file.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookSecret {
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Name of the environment variable holding the secret
+    #[serde(default)]
+    pub secret_env: Option<String>,
+    #[serde(default)]
+    pub secret_file: Option<PathBuf>,
+}
+
+impl WebhookSecret {
+    pub fn resolve(&self) -> Result<String, AppError> {
+        if let Some(secret) = &self.secret {
+            return Ok(secret.clone());
+        }
+        if let Some(name) = &self.secret_env {
+            return env::secret(name)?.ok_or_else(|| {
+                AppError::SecretError(format!("{} is not set for the release webhook", name))
+            });
+        }
+        if let Some(path) = &self.secret_file {
+            return env::read_secret(path);
+        }
+        Err(AppError::SecretError(
+            "No secret, secret_env or secret_file for the release webhook".to_string(),
+        ))
+    }
+}
+
+/// A release of a repository announced by a webhook.
+#[derive(Debug, PartialEq)]
+pub enum Release {
+    /// A GitHub repository by `owner/name`
+    Github(String),
+    /// A GitLab project by host and ID
+    Gitlab(String, u64),
+}
+
+/// The secrets and the services to check per repository, from the config
+/// the server runs with.
+#[derive(Debug, Default)]
+pub struct ReleaseWebhooks {
+    github_secret: Option<String>,
+    gitlab_secret: Option<String>,
+    services: Vec<(Release, String)>,
+}
+
+impl ReleaseWebhooks {
+    pub fn new(config: &AppConfig) -> Result<Self, AppError> {
+        let webhooks = &config.global.release_webhooks;
+        let mut services: Vec<(Release, String)> = config
+            .services
+            .iter()
+            .filter_map(|(name, service)| {
+                let git = &service.git;
+                let release = match git.git_type {
+                    Provider::Github => Release::Github(git.repo.to_lowercase()),
+                    Provider::Gitlab => {
+                        Release::Gitlab(git.gitlab_host().to_string(), git.project_id?)
+                    }
+                    _ => return None,
+                };
+                Some((release, name.clone()))
+            })
+            .collect();
+        services.sort_by(|a, b| a.1.cmp(&b.1));
+        Ok(Self {
+            github_secret: webhooks.github.as_ref().map(|s| s.resolve()).transpose()?,
+            gitlab_secret: webhooks.gitlab.as_ref().map(|s| s.resolve()).transpose()?,
+            services,
+        })
+    }
+
+    /// Whether webhooks of `provider` are accepted.
+    pub fn enabled(&self, provider: &Provider) -> bool {
+        match provider {
+            Provider::Github => self.github_secret.is_some(),
+            Provider::Gitlab => self.gitlab_secret.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Checks the `X-Hub-Signature-256` header, the HMAC of `body` with the secret.
+    pub fn verify_github(&self, signature: &str, body: &[u8]) -> bool {
+        let Some(secret) = &self.github_secret else {
+            return false;
+        };
+        let Some(signature) = signature.strip_prefix("sha256=").and_then(decode_hex) else {
+            return false;
+        };
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+        mac.update(body);
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    /// Checks the `X-Gitlab-Token` header, which carries the secret itself.
+    pub fn verify_gitlab(&self, token: &str) -> bool {
+        self.gitlab_secret
+            .as_deref()
+            .is_some_and(|secret| constant_time_eq(secret.as_bytes(), token.as_bytes()))
+    }
+
+    /// The names of the services resolved from the repository of `release`.
+    pub fn services(&self, release: &Release) -> Vec<String> {
+        self.services
+            .iter()
+            .filter(|(candidate, _)| candidate == release)
+            .map(|(_, name)| name.clone())
+            .collect()
+    }
+}
+
+/// The release announced by a GitHub webhook of type `event`, or `None` for
+/// other events and release actions that don't publish a release.
+pub fn parse_github(event: &str, body: &[u8]) -> Result<Option<Release>, String> {
+    if event != "release" {
+        return Ok(None);
+    }
+    let payload: serde_json::Value = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    if !matches!(payload["action"].as_str(), Some("published" | "released")) {
+        return Ok(None);
+    }
+    let repo = payload["repository"]["full_name"]
+        .as_str()
+        .ok_or("repository.full_name missing")?;
+    Ok(Some(Release::Github(repo.to_lowercase())))
+}
+
+/// The release announced by a GitLab webhook, or `None` for other events and
+/// deleted releases.
+pub fn parse_gitlab(body: &[u8]) -> Result<Option<Release>, String> {
+    let payload: serde_json::Value = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    if payload["object_kind"] != "release" || payload["action"] == "delete" {
+        return Ok(None);
+    }
+    let project = &payload["project"];
+    let id = project["id"].as_u64().ok_or("project.id missing")?;
+    let host = project["web_url"]
+        .as_str()
+        .and_then(|url| Url::parse(url).ok())
+        .and_then(|url| url.host_str().map(str::to_string))
+        .ok_or("project.web_url missing")?;
+    Ok(Some(Release::Gitlab(host, id)))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example of GitHub's webhook documentation
+    const SECRET: &str = "It's a Secret to Everybody";
+    const BODY: &[u8] = b"Hello, World!";
+    const SIGNATURE: &str =
+        "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+
+    fn webhooks() -> ReleaseWebhooks {
+        ReleaseWebhooks {
+            github_secret: Some(SECRET.to_string()),
+            gitlab_secret: Some("gitlab-token".to_string()),
+            services: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn github_signatures_are_verified() {
+        let webhooks = webhooks();
+        assert!(webhooks.verify_github(SIGNATURE, BODY));
+        assert!(!webhooks.verify_github(SIGNATURE, b"Hello, World?"));
+        assert!(!webhooks.verify_github(&SIGNATURE.replace("757", "758"), BODY));
+        // Without prefix, with an odd length, not hex or missing
+        assert!(!webhooks.verify_github(&SIGNATURE[7..], BODY));
+        assert!(!webhooks.verify_github(&SIGNATURE[..SIGNATURE.len() - 1], BODY));
+        assert!(!webhooks.verify_github(&SIGNATURE.replace('e', "g"), BODY));
+        assert!(!webhooks.verify_github("", BODY));
+        assert!(!ReleaseWebhooks::default().verify_github(SIGNATURE, BODY));
+    }
+
+    #[test]
+    fn gitlab_tokens_are_verified() {
+        let webhooks = webhooks();
+        assert!(webhooks.verify_gitlab("gitlab-token"));
+        assert!(!webhooks.verify_gitlab("gitlab-tokem"));
+        assert!(!webhooks.verify_gitlab("gitlab-token2"));
+        assert!(!webhooks.verify_gitlab(""));
+        assert!(!ReleaseWebhooks::default().verify_gitlab(""));
+    }
+
+    #[test]
+    fn hex_is_decoded() {
+        assert_eq!(decode_hex("00ff7a"), Some(vec![0x00, 0xff, 0x7a]));
+        assert_eq!(decode_hex("00FF"), Some(vec![0x00, 0xff]));
+        assert_eq!(decode_hex(""), Some(Vec::new()));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("é0"), None);
+    }
+
+    #[test]
+    fn github_releases_are_parsed() {
+        let body = br#"{
+            "action": "published",
+            "repository": { "full_name": "Traefik/Traefik" }
+        }"#;
+        assert_eq!(
+            parse_github("release", body),
+            Ok(Some(Release::Github("traefik/traefik".to_string())))
+        );
+        assert_eq!(parse_github("push", body), Ok(None));
+        let draft = br#"{ "action": "created", "repository": { "full_name": "a/b" } }"#;
+        assert_eq!(parse_github("release", draft), Ok(None));
+        let incomplete = br#"{ "action": "released", "repository": {} }"#;
+        assert!(parse_github("release", incomplete).is_err());
+        assert!(parse_github("release", b"not json").is_err());
+    }
+
+    #[test]
+    fn gitlab_releases_are_parsed() {
+        let body = br#"{
+            "object_kind": "release",
+            "action": "create",
+            "project": { "id": 278964, "web_url": "https://gitlab.example.com/group/app" }
+        }"#;
+        assert_eq!(
+            parse_gitlab(body),
+            Ok(Some(Release::Gitlab(
+                "gitlab.example.com".to_string(),
+                278964
+            )))
+        );
+        let deleted = br#"{ "object_kind": "release", "action": "delete", "project": {} }"#;
+        assert_eq!(parse_gitlab(deleted), Ok(None));
+        assert_eq!(parse_gitlab(br#"{ "object_kind": "push" }"#), Ok(None));
+        let incomplete = br#"{ "object_kind": "release", "project": { "id": 1 } }"#;
+        assert!(parse_gitlab(incomplete).is_err());
+        assert!(parse_gitlab(b"").is_err());
+    }
+}
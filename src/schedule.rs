@@ -0,0 +1,86 @@
+use super::config::ServiceConfig;
+use super::error::AppError;
+use chrono::{DateTime, Local};
+use cron::Schedule;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::debug;
+
+/// Parses a cron expression in local time. The classic five-field form
+/// (`0 3 * * *`) is accepted in addition to the seconds-first form of the cron crate.
+pub fn parse(expression: &str) -> Result<Schedule, AppError> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {}", expression),
+        _ => expression.to_string(),
+    };
+    Schedule::from_str(&expression)
+        .map_err(|e| AppError::InvalidSchedule(format!("'{}': {}", expression, e)))
+}
+
+/// Tracks when each service is due in daemon mode. Services with a `schedule`
+/// follow their cron expression, all others are checked every `interval`.
+/// Only the `selected` services are scheduled, all if `None`.
+pub struct Scheduler {
+    interval: Duration,
+    schedules: HashMap<String, Schedule>,
+    next_runs: HashMap<String, DateTime<Local>>,
+}
+
+impl Scheduler {
+    pub fn new(
+        services: &HashMap<String, ServiceConfig>,
+        selected: Option<&HashSet<String>>,
+        interval: Duration,
+    ) -> Result<Self, AppError> {
+        let services: HashMap<&String, &ServiceConfig> = services
+            .iter()
+            .filter(|(name, _)| selected.is_none_or(|selected| selected.contains(*name)))
+            .collect();
+        let mut schedules = HashMap::new();
+        for (name, service) in &services {
+            if let Some(expression) = &service.schedule {
+                schedules.insert(name.to_string(), parse(expression)?);
+            }
+        }
+        // Everything is checked once on startup
+        let now = Local::now();
+        let next_runs = services
+            .keys()
+            .map(|name| (name.to_string(), now))
+            .collect();
+        Ok(Self {
+            interval,
+            schedules,
+            next_runs,
+        })
+    }
+
+    /// Returns the services that are due now and schedules their next run.
+    pub fn take_due(&mut self) -> HashSet<String> {
+        let now = Local::now();
+        let mut due = HashSet::new();
+        for (name, next_run) in self.next_runs.iter_mut() {
+            if *next_run > now {
+                continue;
+            }
+            *next_run = match self.schedules.get(name) {
+                Some(schedule) => schedule.after(&now).next().unwrap_or(now + self.interval),
+                None => now + self.interval,
+            };
+            debug!("Next check of {} at {}", name, next_run);
+            due.insert(name.clone());
+        }
+        due
+    }
+
+    /// Time until the next service is due.
+    pub fn until_next(&self) -> Duration {
+        let now = Local::now();
+        self.next_runs
+            .values()
+            .min()
+            .and_then(|next_run| (*next_run - now).to_std().ok())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,306 @@
+use super::config::OutputData;
+use super::error::AppError;
+use super::git::Provider;
+use super::release_webhook::{self, ReleaseWebhooks};
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use serde_json::json;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::{Notify, RwLock};
+use tracing::{error, info, warn};
+
+/// State shared between the check loop and the HTTP server.
+#[derive(Default)]
+pub struct ServerState {
+    output: RwLock<OutputData>,
+    refresh: Notify,
+    release_webhooks: RwLock<ReleaseWebhooks>,
+    /// Services to check because a release webhook announced a new release
+    triggered: Mutex<HashSet<String>>,
+    trigger: Notify,
+    checks: AtomicU64,
+    failures: AtomicU64,
+    last_check: AtomicI64,
+    last_heartbeat: AtomicI64,
+}
+
+/// Time without a heartbeat after which `/healthz` reports the loop as wedged.
+const HEALTH_TIMEOUT: i64 = 10 * 60;
+
+impl ServerState {
+    /// Publishes the output of a check.
+    pub async fn publish(&self, output: &OutputData) {
+        *self.output.write().await = output.clone();
+        self.last_check
+            .store(Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    /// Counts a finished check.
+    pub fn record_check(&self, success: bool) {
+        self.checks.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records that the check loop is alive.
+    pub fn heartbeat(&self) {
+        self.last_heartbeat
+            .store(Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    /// Waits until a refresh is requested through the API.
+    pub async fn refresh_requested(&self) {
+        self.refresh.notified().await;
+    }
+
+    /// Sets the secrets and services the release webhooks are checked against.
+    pub async fn set_release_webhooks(&self, webhooks: ReleaseWebhooks) {
+        *self.release_webhooks.write().await = webhooks;
+    }
+
+    /// Waits until a release webhook triggers a check, see `take_triggered`.
+    pub async fn check_triggered(&self) {
+        self.trigger.notified().await;
+    }
+
+    /// The services release webhooks triggered a check of since the last call.
+    pub fn take_triggered(&self) -> HashSet<String> {
+        std::mem::take(&mut *self.triggered.lock().unwrap())
+    }
+}
+
+/// Binds `addr` and serves the API in the background.
+pub async fn start(addr: SocketAddr, state: Arc<ServerState>) -> Result<(), AppError> {
+    let router = Router::new()
+        .route("/api/v1/services", get(list_services))
+        .route("/api/v1/services/{name}", get(get_service))
+        .route("/api/v1/refresh", post(refresh))
+        .route("/api/v1/webhooks/github", post(github_webhook))
+        .route("/api/v1/webhooks/gitlab", post(gitlab_webhook))
+        .route("/metrics", get(metrics))
+        .route("/healthz", get(healthz))
+        .with_state(state);
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| AppError::ServerError(format!("Failed to bind {}: {}", addr, e)))?;
+    info!("Serving API on http://{}", addr);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            error!("API server failed: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn list_services(State(state): State<Arc<ServerState>>) -> Response {
+    Json(state.output.read().await.clone()).into_response()
+}
+
+async fn get_service(State(state): State<Arc<ServerState>>, Path(name): Path<String>) -> Response {
+    match state.output.read().await.get(&name) {
+        Some(service) => Json(service.clone()).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Unknown service '{}'", name) })),
+        )
+            .into_response(),
+    }
+}
+
+async fn refresh(State(state): State<Arc<ServerState>>) -> Response {
+    info!("Refresh requested through the API");
+    state.refresh.notify_one();
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({ "status": "refresh scheduled" })),
+    )
+        .into_response()
+}
+
+async fn github_webhook(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let webhooks = state.release_webhooks.read().await;
+    if !webhooks.enabled(&Provider::Github) {
+        return webhook_error(StatusCode::NOT_FOUND, "GitHub webhooks are not configured");
+    }
+    let signature = header_value(&headers, "x-hub-signature-256");
+    if !webhooks.verify_github(signature, &body) {
+        warn!("Rejected a GitHub webhook with an invalid signature");
+        return webhook_error(StatusCode::UNAUTHORIZED, "Invalid signature");
+    }
+    let event = header_value(&headers, "x-github-event");
+    let release = release_webhook::parse_github(event, &body);
+    trigger_check(&state, &webhooks, release)
+}
+
+async fn gitlab_webhook(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let webhooks = state.release_webhooks.read().await;
+    if !webhooks.enabled(&Provider::Gitlab) {
+        return webhook_error(StatusCode::NOT_FOUND, "GitLab webhooks are not configured");
+    }
+    if !webhooks.verify_gitlab(header_value(&headers, "x-gitlab-token")) {
+        warn!("Rejected a GitLab webhook with an invalid token");
+        return webhook_error(StatusCode::UNAUTHORIZED, "Invalid token");
+    }
+    trigger_check(&state, &webhooks, release_webhook::parse_gitlab(&body))
+}
+
+/// Schedules a check of the services of the released repository, if any.
+fn trigger_check(
+    state: &ServerState,
+    webhooks: &ReleaseWebhooks,
+    release: Result<Option<release_webhook::Release>, String>,
+) -> Response {
+    let release = match release {
+        Ok(Some(release)) => release,
+        Ok(None) => return (StatusCode::OK, Json(json!({ "status": "ignored" }))).into_response(),
+        Err(e) => {
+            return webhook_error(StatusCode::BAD_REQUEST, &format!("Invalid payload: {}", e))
+        }
+    };
+    let services = webhooks.services(&release);
+    if services.is_empty() {
+        info!("No services to check for the release of {:?}", release);
+        return (
+            StatusCode::OK,
+            Json(json!({ "status": "no matching services" })),
+        )
+            .into_response();
+    }
+    info!(
+        "Release webhook triggered a check of {}",
+        services.join(", ")
+    );
+    state
+        .triggered
+        .lock()
+        .unwrap()
+        .extend(services.iter().cloned());
+    state.trigger.notify_one();
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({ "status": "check scheduled", "services": services })),
+    )
+        .into_response()
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+fn webhook_error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+async fn healthz(State(state): State<Arc<ServerState>>) -> Response {
+    let last_heartbeat = state.last_heartbeat.load(Ordering::Relaxed);
+    let status = if Utc::now().timestamp() - last_heartbeat <= HEALTH_TIMEOUT {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(json!({
+            "status": if status.is_success() { "ok" } else { "unhealthy" },
+            "last_heartbeat": last_heartbeat,
+        })),
+    )
+        .into_response()
+}
+
+/// Renders the Prometheus text exposition format.
+async fn metrics(State(state): State<Arc<ServerState>>) -> Response {
+    let output = state.output.read().await;
+    let mut body = String::new();
+
+    let _ = writeln!(
+        body,
+        "# HELP version_updater_service_info Resolved tag of a service"
+    );
+    let _ = writeln!(body, "# TYPE version_updater_service_info gauge");
+    for (name, service) in output.iter() {
+        let (Some(tag), Some(version)) = (service.tag(), service.version()) else {
+            continue;
+        };
+        let _ = writeln!(
+            body,
+            "version_updater_service_info{{service=\"{}\",image=\"{}\",tag=\"{}\",version=\"{}\"}} 1",
+            escape(name),
+            escape(&service.image),
+            escape(tag),
+            escape(version)
+        );
+    }
+
+    let _ = writeln!(
+        body,
+        "# HELP version_updater_service_error Whether the last check of a service failed"
+    );
+    let _ = writeln!(body, "# TYPE version_updater_service_error gauge");
+    for (name, service) in output.iter() {
+        let _ = writeln!(
+            body,
+            "version_updater_service_error{{service=\"{}\"}} {}",
+            escape(name),
+            u8::from(service.tag().is_none())
+        );
+    }
+
+    for (metric, kind, help, value) in [
+        (
+            "checks_total",
+            "counter",
+            "Number of checks run",
+            state.checks.load(Ordering::Relaxed) as i64,
+        ),
+        (
+            "check_failures_total",
+            "counter",
+            "Number of checks that failed as a whole",
+            state.failures.load(Ordering::Relaxed) as i64,
+        ),
+        (
+            "last_check_timestamp_seconds",
+            "gauge",
+            "Time the results were last updated",
+            state.last_check.load(Ordering::Relaxed),
+        ),
+    ] {
+        let _ = writeln!(body, "# HELP version_updater_{} {}", metric, help);
+        let _ = writeln!(body, "# TYPE version_updater_{} {}", metric, kind);
+        let _ = writeln!(body, "version_updater_{} {}", metric, value);
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+/// Escapes a Prometheus label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
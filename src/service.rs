@@ -1,69 +1,479 @@
-use super::config::{ServiceConfig, ServiceVersion};
-use super::git::GitClient;
+use super::cache::Cache;
+use super::config::{AppConfig, OutputData, ServiceConfig, ServiceVersion};
+use super::error::AppError;
+use super::git::{GitClient, Provider};
+use super::http::{self, HttpClient};
+use super::progress::Progress;
+use super::rate_limit::Budget;
 use super::registry::RegistryClient;
-use log::error;
+use super::tag_template;
+use super::telemetry;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, error_span, warn, Instrument, Span};
 
 use anyhow::Result;
 
+/// Variables available in image tag templates.
+pub const TAG_VARIABLES: &[&str] = &["RELEASE_VERSION", "MAJOR", "MINOR", "PATCH", "MAJOR_MINOR"];
+
+/// Most tags listed as candidates when the expected tag is missing.
+const MAX_CANDIDATES: usize = 10;
+
+/// How resolving a service ended. Serialized with a `status` field next to the
+/// fields of the variant.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ResolutionOutcome {
+    /// The image has a tag for the latest version
+    Resolved {
+        version: String,
+        tag: String,
+        /// Digest of the tag's manifest, if the registry reports one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        digest: Option<String>,
+    },
+    /// The latest version is known, but the image has no tag for it
+    TagMissing {
+        version: String,
+        /// The expected tag
+        tag: String,
+        /// Existing tags containing the version
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        candidates: Vec<String>,
+    },
+    /// The git provider or registry rejected the requests as rate limited
+    RateLimited {
+        source: String,
+    },
+    Error {
+        error: String,
+    },
+}
+
+impl ResolutionOutcome {
+    /// The `status` the outcome is serialized with.
+    pub fn status(&self) -> &'static str {
+        match self {
+            ResolutionOutcome::Resolved { .. } => "resolved",
+            ResolutionOutcome::TagMissing { .. } => "tag_missing",
+            ResolutionOutcome::RateLimited { .. } => "rate_limited",
+            ResolutionOutcome::Error { .. } => "error",
+        }
+    }
+}
+
+/// How long resolving a service took, reported with --timings.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Timing {
+    pub duration_ms: u64,
+    /// Requests sent to git providers and registries, including retries
+    pub http_requests: u32,
+}
+
+/// How thoroughly the image of a resolved service was checked.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verification {
+    /// The manifest of the tag exists
+    Shallow,
+    /// The manifest and all blobs it references exist, see --deep-verify
+    Deep,
+}
+
+/// Marks an output entry answered offline from expired cache entries or from
+/// the previous output.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Stale {
+    /// When the oldest answer used was fetched, in seconds since the epoch
+    pub fetched_at: i64,
+    pub age_seconds: u64,
+}
+
+impl Stale {
+    pub fn since(fetched: SystemTime) -> Self {
+        let fetched_at = fetched
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        Self::at(fetched_at)
+    }
+
+    /// Staleness of an answer fetched at `fetched_at` seconds since the epoch.
+    pub fn at(fetched_at: i64) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        Self {
+            fetched_at,
+            age_seconds: now.saturating_sub(fetched_at).max(0) as u64,
+        }
+    }
+}
+
 pub struct ServiceProcessor {
     config: ServiceConfig,
+    http: HttpClient,
+    timing: bool,
+    deep_verify: bool,
+    span: Span,
 }
 
 impl ServiceProcessor {
-    pub fn new(config: ServiceConfig) -> Self {
-        Self { config }
+    pub fn new(config: ServiceConfig, http: HttpClient) -> Self {
+        Self {
+            config,
+            http,
+            timing: false,
+            deep_verify: false,
+            span: Span::none(),
+        }
     }
 
+    /// Logs within the `span` of the service `name`, so that the log lines of
+    /// the git provider and registry clients name the service.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.span = span(name, &self.config);
+        self
+    }
+
+    /// Adds the `Timing` of `process` to the output entry.
+    pub fn with_timing(mut self, timing: bool) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Also checks that the blobs of the resolved image exist, so that tags
+    /// of images that can't be pulled fail.
+    pub fn with_deep_verify(mut self, deep_verify: bool) -> Self {
+        self.deep_verify = deep_verify;
+        self
+    }
+
+    /// Resolves the service into an output entry, see `resolve`.
     pub async fn process(&self) -> Result<ServiceVersion> {
+        self.process_service().instrument(self.span.clone()).await
+    }
+
+    async fn process_service(&self) -> Result<ServiceVersion> {
+        let start = Instant::now();
+        let (outcome, stats) = http::track_requests(self.resolve_service()).await;
+        let timing = Timing {
+            duration_ms: start.elapsed().as_millis() as u64,
+            http_requests: stats.requests,
+        };
+        debug!(
+            "Resolved in {}ms with {} HTTP requests",
+            timing.duration_ms, timing.http_requests
+        );
+        let outcome = outcome?;
+        let verified = match (&outcome, self.deep_verify) {
+            _ if self.config.image.is_none() => None,
+            (ResolutionOutcome::Resolved { .. }, false) => Some(Verification::Shallow),
+            (ResolutionOutcome::Resolved { .. }, true) => Some(Verification::Deep),
+            _ => None,
+        };
+        Ok(ServiceVersion::new(self.config.image.name.clone(), outcome)
+            .with_verified(verified)
+            .with_helm(self.config.helm.clone())
+            .with_timing(self.timing.then_some(timing))
+            .with_stale(stats.oldest_cached.map(Stale::since)))
+    }
+
+    /// Resolves the version and tag of the service. Transient failures are
+    /// reported in the returned outcome, fatal ones (see `AppError::is_fatal`)
+    /// as error.
+    pub async fn resolve(&self) -> Result<ResolutionOutcome> {
+        self.resolve_service().instrument(self.span.clone()).await
+    }
+
+    async fn resolve_service(&self) -> Result<ResolutionOutcome> {
         let version = match self.get_version().await {
             Ok(v) => v,
-            Err(e) => {
-                return Ok(ServiceVersion::error(
-                    self.config.image.name.clone(),
-                    &format!("Failed to get version: {}", e),
-                ));
-            }
+            Err(e) if is_fatal(&e) => return Err(e.context("Failed to get version")),
+            Err(e) => return Ok(failure(e, "Failed to get version")),
         };
-        let tag = match self.validate_image_tag(&version).await {
+        let variables = tag_template::version_variables(&version);
+        let tag = match tag_template::render(&self.config.image.tag, &variables) {
             Ok(tag) => tag,
-            Err(e) => {
-                return Ok(ServiceVersion::error(
-                    self.config.image.name.clone(),
-                    &format!("Failed to validate image tag: {}", e),
-                ));
+            Err(e) => return Ok(failure(e.into(), "Failed to render image tag")),
+        };
+        // The version components are missing if the version isn't numeric
+        if let Some(variable) = tag_template::variables(&tag)
+            .into_iter()
+            .find(|variable| TAG_VARIABLES.contains(variable))
+        {
+            let e = AppError::ConfigError(format!(
+                "${{{}}} needs a numeric version, got {}",
+                variable, version
+            ));
+            return Ok(failure(e.into(), "Failed to render image tag"));
+        }
+        if self.config.image.is_none() {
+            return Ok(ResolutionOutcome::Resolved {
+                version,
+                tag,
+                digest: None,
+            });
+        }
+
+        debug!("Tag template {:?} gives {:?}", self.config.image.tag, tag);
+        let registry_client = self.registry_client();
+        let digest = match registry_client.tag_digest(&tag).await {
+            Ok(digest) => digest,
+            Err(e) if e.is_fatal() => {
+                return Err(anyhow::Error::new(e).context("Failed to validate image tag"))
             }
+            Err(e) => return Ok(failure(e.into(), "Failed to validate image tag")),
+        };
+        let Some(digest) = digest else {
+            error!(
+                "Image {}:{} does not exist in the registry",
+                self.config.image.name, tag
+            );
+            let candidates = match registry_client.list_tags().await {
+                Ok(tags) => tags
+                    .into_iter()
+                    .filter(|candidate| candidate.contains(version.as_str()))
+                    .take(MAX_CANDIDATES)
+                    .collect(),
+                Err(e) => {
+                    debug!("Failed to list candidate tags: {}", e);
+                    Vec::new()
+                }
+            };
+            return Ok(ResolutionOutcome::TagMissing {
+                version,
+                tag,
+                candidates,
+            });
         };
 
-        Ok(ServiceVersion {
-            image: self.config.image.name.clone(),
+        if self.deep_verify {
+            // The digest pins the manifest that was just checked
+            let reference = if digest.is_empty() { &tag } else { &digest };
+            match registry_client.missing_blobs(reference).await {
+                Ok(missing) if missing.is_empty() => {}
+                Ok(missing) => {
+                    let e = AppError::NotFound(format!(
+                        "Image {}:{} references missing blobs {}",
+                        self.config.image.name,
+                        tag,
+                        missing.join(", ")
+                    ));
+                    return Ok(failure(e.into(), "Failed to deep verify image"));
+                }
+                Err(e) if e.is_fatal() => {
+                    return Err(anyhow::Error::new(e).context("Failed to deep verify image"))
+                }
+                Err(e) => return Ok(failure(e.into(), "Failed to deep verify image")),
+            }
+        }
+
+        Ok(ResolutionOutcome::Resolved {
+            version,
             tag,
-            error: None,
+            digest: (!digest.is_empty()).then_some(digest),
         })
     }
 
+    /// The latest upstream version from the git provider, or else from the
+    /// registry tags matching the tag template.
     async fn get_version(&self) -> Result<String> {
-        GitClient::get_version(&self.config.git).await
+        let tag = &self.config.image.tag;
+        if self.config.git.git_type == Provider::None
+            && !self.config.image.is_none()
+            && tag_template::uses(tag, "RELEASE_VERSION")
+        {
+            return Ok(self.registry_client().latest_version(tag).await?);
+        }
+        GitClient::get_version(&self.config.git, &self.http).await
     }
 
-    async fn validate_image_tag(&self, version: &str) -> Result<String> {
-        let image_tag = self.config.image.tag.replace("${RELEASE_VERSION}", version);
+    /// A client for the service image, recording its registry in the current span.
+    fn registry_client(&self) -> RegistryClient {
+        let client = RegistryClient::new(&self.config.image.name, self.http.clone())
+            .with_credentials(self.config.image.credentials.clone())
+            .with_tag_source(self.config.image.tag_source);
+        Span::current().record("registry", tracing::field::display(client.registry()));
+        client
+    }
+}
 
-        let registry_client = RegistryClient::new(&self.config.image.name);
+/// The span a service is resolved in, attributing the log lines of concurrently
+/// resolved services. The registry is recorded once the image is looked up.
+/// It has the error level to stay enabled when only warnings are logged.
+pub fn span(name: &str, config: &ServiceConfig) -> Span {
+    error_span!(
+        "service",
+        name = %name,
+        provider = %config.git.git_type.name(),
+        registry = tracing::field::Empty
+    )
+}
 
-        let exists = registry_client.validate_tag(&image_tag).await?;
+fn is_fatal(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<AppError>().is_some_and(AppError::is_fatal)
+}
 
-        if !exists {
-            error!(
-                "Image {}:{} does not exist in the registry",
-                self.config.image.name, image_tag
-            );
+/// The outcome of a transient failure in `step`, which prefixes the message.
+fn failure(e: anyhow::Error, step: &str) -> ResolutionOutcome {
+    match e.downcast_ref::<AppError>().map(AppError::root) {
+        Some(AppError::RateLimited(source)) => ResolutionOutcome::RateLimited {
+            source: source.clone(),
+        },
+        _ => ResolutionOutcome::Error {
+            error: format!("{}: {}", step, e),
+        },
+    }
+}
 
-            return Ok(match version {
-                "<RATE_LIMITED>" => "<RATE_LIMITED>".to_string(),
-                _ => "<NOT_FOUND>".to_string(),
-            });
-        }
+/// Resolves the versions of all services in `config`, or only of those in
+/// `due`, concurrently. Services failing fatally are reported in the output,
+/// unless `--fail-fast` is set.
+pub async fn process_services(
+    config: &AppConfig,
+    due: Option<&HashSet<String>>,
+) -> Result<OutputData> {
+    let run = process_services_until(config, due, false, std::future::pending()).await?;
+    Ok(run.output)
+}
+
+/// The results of `process_services_until`.
+pub struct Run {
+    /// The services completed, all of them unless `interrupted`
+    pub output: OutputData,
+    pub interrupted: bool,
+    /// The lowest remaining request quota each host reported
+    pub budgets: BTreeMap<String, Budget>,
+    /// How long each completed service took, whether or not its output entry
+    /// has a `Timing`
+    pub durations: BTreeMap<String, Duration>,
+}
+
+/// Like `process_services`, but stops once `interrupt` completes, cancelling
+/// the services still being resolved. With `refresh`, nothing is answered
+/// from the cache, e.g. for services a release webhook reported.
+pub async fn process_services_until(
+    config: &AppConfig,
+    due: Option<&HashSet<String>>,
+    refresh: bool,
+    interrupt: impl Future<Output = ()>,
+) -> Result<Run> {
+    let start = Instant::now();
+    let cache = match config.args.no_cache {
+        true => None,
+        false => Cache::new(&config.global.cache),
+    };
+    let http = HttpClient::new(&config.global.http, cache)?
+        .with_offline(config.args.offline)
+        .with_refresh(refresh);
+    let services = config
+        .services
+        .iter()
+        .filter(|(name, _)| due.is_none_or(|due| due.contains(*name)));
+    let options = RunOptions {
+        progress: !config.args.quiet,
+        fail_fast: config.args.fail_fast,
+        timing: config.args.timings,
+        deep_verify: config.args.deep_verify,
+    };
+    let run = run_services(services, &http, options, interrupt).await?;
+    if !run.interrupted {
+        telemetry::record_run(&run.output, start.elapsed());
+    }
+    Ok(run)
+}
+
+/// How `run_services` resolves the services.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct RunOptions {
+    /// Show the progress on stderr
+    pub progress: bool,
+    /// Stop at the first fatal failure instead of reporting it in the output
+    pub fail_fast: bool,
+    /// Add the `Timing` of each service to its output entry
+    pub timing: bool,
+    /// Check that the blobs of resolved images exist
+    pub deep_verify: bool,
+}
+
+/// Resolves `services` concurrently with `http`, until all are resolved or
+/// `interrupt` completes.
+pub(crate) async fn run_services<'a>(
+    services: impl IntoIterator<Item = (&'a String, &'a ServiceConfig)>,
+    http: &HttpClient,
+    options: RunOptions,
+    interrupt: impl Future<Output = ()>,
+) -> Result<Run> {
+    let mut output = OutputData::new();
+    let mut durations = BTreeMap::new();
+    let mut images = BTreeMap::new();
+
+    // Process all services concurrently
+    let mut processing_tasks: FuturesUnordered<_> = services
+        .into_iter()
+        .map(|(name, service_config)| {
+            let name = name.clone();
+            images.insert(name.clone(), service_config.image.name.clone());
+            let processor = ServiceProcessor::new(service_config.clone(), http.clone())
+                .with_name(&name)
+                .with_timing(options.timing)
+                .with_deep_verify(options.deep_verify);
+            async move {
+                let start = Instant::now();
+                let result = processor.process().await;
+                (name, result, start.elapsed())
+            }
+        })
+        .collect();
 
-        Ok(image_tag)
+    // Process results as they complete
+    let mut progress = Progress::start(processing_tasks.len(), options.progress);
+    tokio::pin!(interrupt);
+    loop {
+        let (name, result, duration) = tokio::select! {
+            next = processing_tasks.next() => match next {
+                Some(next) => next,
+                None => break,
+            },
+            () = &mut interrupt => {
+                warn!(
+                    "Interrupted, cancelling {} unfinished services",
+                    processing_tasks.len()
+                );
+                return Ok(Run {
+                    output,
+                    interrupted: true,
+                    budgets: http.budgets(),
+                    durations,
+                });
+            }
+        };
+        progress.finish_service(&name, !matches!(&result, Ok(v) if v.error().is_none()));
+        durations.insert(name.clone(), duration);
+        match result {
+            Ok(service_info) => {
+                output.insert(name, service_info);
+            }
+            // Dropping the remaining tasks cancels them
+            Err(e) if options.fail_fast => {
+                return Err(e.context(format!("Service '{}' failed", name)));
+            }
+            Err(e) => {
+                error!("Failed to process service '{}': {:#}", name, e);
+                let image = images.remove(&name).unwrap_or_default();
+                output.insert(name, ServiceVersion::failed(image, &format!("{:#}", e)));
+            }
+        }
     }
+
+    Ok(Run {
+        output,
+        interrupted: false,
+        budgets: http.budgets(),
+        durations,
+    })
 }
@@ -0,0 +1,99 @@
+//! Graceful shutdown on SIGINT and SIGTERM.
+//!
+//! After [`install`], the first signal is only recorded so that the running
+//! check can stop at a safe point and save what it has. A second signal exits
+//! right away.
+
+use std::fmt;
+use std::sync::OnceLock;
+use tokio::sync::Notify;
+use tracing::warn;
+
+static RECEIVED: OnceLock<Signal> = OnceLock::new();
+static NOTIFY: Notify = Notify::const_new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+}
+
+impl Signal {
+    /// The exit status of a process killed by the signal, 128 plus its number.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Signal::Interrupt => 130,
+            Signal::Terminate => 143,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Signal::Interrupt => write!(f, "SIGINT"),
+            Signal::Terminate => write!(f, "SIGTERM"),
+        }
+    }
+}
+
+/// Starts listening for the signals, which no longer terminate the process
+/// on their own.
+pub fn install() {
+    tokio::spawn(async {
+        loop {
+            let signal = next_signal().await;
+            if RECEIVED.set(signal).is_err() {
+                warn!("Received {} again, exiting immediately", signal);
+                std::process::exit(signal.exit_code());
+            }
+            warn!(
+                "Received {}, shutting down (repeat to exit immediately)",
+                signal
+            );
+            NOTIFY.notify_waiters();
+        }
+    });
+}
+
+/// The signal received so far, if any.
+pub fn received() -> Option<Signal> {
+    RECEIVED.get().copied()
+}
+
+/// Waits until a signal is received. Never completes without [`install`].
+pub async fn requested() -> Signal {
+    loop {
+        // Registered before checking, so a signal in between isn't missed
+        let notified = NOTIFY.notified();
+        if let Some(signal) = received() {
+            return signal;
+        }
+        notified.await;
+    }
+}
+
+#[cfg(unix)]
+async fn next_signal() -> Signal {
+    use tokio::signal::unix::{signal, SignalKind};
+    let (Ok(mut interrupt), Ok(mut terminate)) = (
+        signal(SignalKind::interrupt()),
+        signal(SignalKind::terminate()),
+    ) else {
+        warn!("Failed to listen for signals");
+        return std::future::pending().await;
+    };
+    tokio::select! {
+        _ = interrupt.recv() => Signal::Interrupt,
+        _ = terminate.recv() => Signal::Terminate,
+    }
+}
+
+#[cfg(not(unix))]
+async fn next_signal() -> Signal {
+    if tokio::signal::ctrl_c().await.is_err() {
+        warn!("Failed to listen for Ctrl-C");
+        return std::future::pending().await;
+    }
+    Signal::Interrupt
+}
@@ -0,0 +1,138 @@
+use super::config::OutputData;
+use super::rate_limit::Budget;
+use super::service::ResolutionOutcome;
+use nu_ansi_term::{Color, Style};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+/// Services listed as slowest in the summary.
+const SLOWEST: usize = 3;
+
+/// Counts of the outcomes of a run, printed at its end.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub ok: usize,
+    /// Resolved services whose tag differs from the previous output
+    pub updated: usize,
+    pub not_found: usize,
+    pub rate_limited: usize,
+    pub errors: usize,
+    pub duration: Duration,
+    pub slowest: Vec<(String, Duration)>,
+    /// The request quota left per host, see `with_budgets`
+    pub budgets: BTreeMap<String, Budget>,
+}
+
+impl Summary {
+    /// Summarizes the services resolved in a run taking `duration`.
+    pub fn new(output: &OutputData, previous: Option<&OutputData>, duration: Duration) -> Self {
+        let mut summary = Self {
+            duration,
+            ..Self::default()
+        };
+        for (name, version) in output {
+            match &version.outcome {
+                ResolutionOutcome::Resolved { tag, .. } => {
+                    summary.ok += 1;
+                    let previous_tag = previous.and_then(|p| p.get(name)?.tag());
+                    if previous_tag.is_some_and(|previous_tag| previous_tag != tag) {
+                        summary.updated += 1;
+                    }
+                }
+                ResolutionOutcome::TagMissing { .. } => summary.not_found += 1,
+                ResolutionOutcome::RateLimited { .. } => summary.rate_limited += 1,
+                ResolutionOutcome::Error { .. } => summary.errors += 1,
+            }
+        }
+        summary
+    }
+
+    /// Lists the slowest services by how long each of them took.
+    pub fn with_durations(mut self, durations: &BTreeMap<String, Duration>) -> Self {
+        let mut slowest: Vec<_> = durations
+            .iter()
+            .map(|(name, duration)| (name.clone(), *duration))
+            .collect();
+        slowest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        slowest.truncate(SLOWEST);
+        self.slowest = slowest;
+        self
+    }
+
+    /// Adds the request quota the hosts reported as left after the run.
+    pub fn with_budgets(mut self, budgets: BTreeMap<String, Budget>) -> Self {
+        self.budgets = budgets;
+        self
+    }
+
+    pub fn total(&self) -> usize {
+        self.ok + self.not_found + self.rate_limited + self.errors
+    }
+
+    /// The summary as printed at the end of a check, with the counts of
+    /// resolved services in green, held back ones in yellow and errors in red
+    /// if `color`, as are low request budgets.
+    pub fn render(&self, color: bool) -> String {
+        let mut lines = vec![format!(
+            "Checked {} services in {:.1}s",
+            self.total(),
+            self.duration.as_secs_f64()
+        )];
+        for (label, count, highlight) in [
+            ("ok", self.ok, Color::Green),
+            ("updated", self.updated, Color::Green),
+            ("not found", self.not_found, Color::Yellow),
+            ("rate limited", self.rate_limited, Color::Yellow),
+            ("errors", self.errors, Color::Red),
+        ] {
+            let style = match color && count > 0 {
+                true => highlight.bold(),
+                false => Style::new(),
+            };
+            lines.push(format!(
+                "  {:<14}{}",
+                label,
+                style.paint(format!("{:>5}", count))
+            ));
+        }
+        if !self.slowest.is_empty() {
+            let slowest: Vec<String> = self
+                .slowest
+                .iter()
+                .map(|(name, duration)| format!("{} ({:.1}s)", name, duration.as_secs_f64()))
+                .collect();
+            lines.push(format!("  slowest: {}", slowest.join(", ")));
+        }
+        if !self.budgets.is_empty() {
+            lines.push("  requests left:".to_string());
+        }
+        for (host, budget) in &self.budgets {
+            let style = match (color, budget.remaining) {
+                (false, _) => Style::new(),
+                (true, 0) => Color::Red.bold(),
+                (true, _) if budget.is_low() => Color::Yellow.bold(),
+                (true, _) => Style::new(),
+            };
+            let left = match budget.limit {
+                Some(limit) => format!("{}/{}", budget.remaining, limit),
+                None => budget.remaining.to_string(),
+            };
+            let mut line = format!("    {:<28}{}", host, style.paint(left));
+            if let Some(window) = budget.window {
+                line.push_str(&format!(" per {}", humantime::format_duration(window)));
+            }
+            let reset = budget
+                .reset
+                .and_then(|reset| reset.duration_since(SystemTime::now()).ok());
+            if let Some(reset) = reset {
+                let reset = Duration::from_secs(reset.as_secs());
+                line.push_str(&format!(
+                    ", resets in {}",
+                    humantime::format_duration(reset)
+                ));
+            }
+            lines.push(line);
+        }
+        lines.join("\n") + "\n"
+    }
+}
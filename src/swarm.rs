@@ -0,0 +1,132 @@
+use super::config::{OutputData, ServiceConfig};
+use super::error::AppError;
+use super::updater::{same_image, split_image_ref};
+use bollard::service::{ListServicesOptions, UpdateServiceOptions};
+use bollard::{Docker, API_DEFAULT_VERSION};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{debug, info};
+
+const DOCKER_TIMEOUT_SECS: u64 = 120;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SwarmConfig {
+    /// Docker host of a swarm manager (`unix:///var/run/docker.sock`, `tcp://manager:2375`).
+    /// Defaults to `DOCKER_HOST` or the local socket.
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+/// A swarm service whose image differs from the resolved one.
+pub struct SwarmChange {
+    pub swarm_service: String,
+    pub service: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Updates the image of every swarm service running an outdated tag of a
+/// resolved service. With `dry_run`, the changes are only reported.
+pub async fn apply(
+    config: &SwarmConfig,
+    services: &HashMap<String, ServiceConfig>,
+    output: &OutputData,
+    dry_run: bool,
+) -> Result<Vec<SwarmChange>, AppError> {
+    let docker = connect(config)?;
+    let swarm_services = docker
+        .list_services(None::<ListServicesOptions<String>>)
+        .await
+        .map_err(swarm_error)?;
+
+    let mut changes = Vec::new();
+    for swarm_service in swarm_services {
+        let (Some(id), Some(spec), Some(version)) = (
+            swarm_service.id,
+            swarm_service.spec,
+            swarm_service.version.and_then(|v| v.index),
+        ) else {
+            continue;
+        };
+        let swarm_name = spec.name.clone().unwrap_or(id.clone());
+        let Some(current) = spec
+            .task_template
+            .as_ref()
+            .and_then(|t| t.container_spec.as_ref())
+            .and_then(|c| c.image.clone())
+        else {
+            continue;
+        };
+        let (image_name, current_tag) = split_image_ref(&current);
+
+        let Some((name, result)) = output.iter().find(|(name, result)| {
+            result.tag().is_some()
+                && services.get(*name).is_some_and(|service| {
+                    if service.swarm.is_empty() {
+                        same_image(image_name, &result.image)
+                    } else {
+                        service.swarm.contains(&swarm_name)
+                    }
+                })
+        }) else {
+            continue;
+        };
+
+        // Swarm pins the digest of the tag it deployed, so only the tag is compared
+        let tag = result.tag().unwrap_or_default();
+        if current_tag == Some(tag) {
+            debug!("Swarm service {} is up to date", swarm_name);
+            continue;
+        }
+
+        let new_image = format!("{}:{}", image_name, tag);
+        changes.push(SwarmChange {
+            swarm_service: swarm_name.clone(),
+            service: name.clone(),
+            from: current.clone(),
+            to: new_image.clone(),
+        });
+
+        if dry_run {
+            continue;
+        }
+
+        info!(
+            "Updating swarm service {} from {} to {}",
+            swarm_name, current, new_image
+        );
+        let mut spec = spec;
+        if let Some(container) = spec
+            .task_template
+            .as_mut()
+            .and_then(|t| t.container_spec.as_mut())
+        {
+            container.image = Some(new_image);
+        }
+        let options = UpdateServiceOptions {
+            version,
+            registry_auth_from: true,
+            ..Default::default()
+        };
+        docker
+            .update_service(&id, spec, options, None)
+            .await
+            .map_err(swarm_error)?;
+    }
+    Ok(changes)
+}
+
+fn connect(config: &SwarmConfig) -> Result<Docker, AppError> {
+    match config.host.as_deref() {
+        Some(host) if host.starts_with("unix://") || host.starts_with('/') => {
+            Docker::connect_with_socket(host, DOCKER_TIMEOUT_SECS, API_DEFAULT_VERSION)
+        }
+        Some(host) => Docker::connect_with_http(host, DOCKER_TIMEOUT_SECS, API_DEFAULT_VERSION),
+        None => Docker::connect_with_defaults(),
+    }
+    .map_err(swarm_error)
+}
+
+fn swarm_error(e: bollard::errors::Error) -> AppError {
+    AppError::SwarmError(e.to_string())
+}
@@ -0,0 +1,311 @@
+//! Tag templates: `${VARIABLE}` placeholders, optionally transformed by a chain
+//! of filters like `${RELEASE_VERSION|strip_prefix:v|lower}`, so upstream tags
+//! like `V1.2.3+build` can map to image tags like `1.2.3-build`.
+//!
+//! Filters:
+//! - `lower`, `upper`: changes the case
+//! - `strip_prefix:<text>`, `strip_suffix:<text>`: removes the text if present
+//! - `replace:<from>,<to>`: replaces all occurrences of `from` by `to`
+
+use super::error::AppError;
+use std::ops::Range;
+
+/// A placeholder of a template.
+struct Placeholder<'a> {
+    /// The byte range of the whole `${...}`
+    range: Range<usize>,
+    variable: &'a str,
+    filters: Vec<&'a str>,
+}
+
+enum Filter<'a> {
+    Lower,
+    Upper,
+    StripPrefix(&'a str),
+    StripSuffix(&'a str),
+    Replace(&'a str, &'a str),
+}
+
+impl<'a> Filter<'a> {
+    fn parse(filter: &'a str) -> Result<Self, AppError> {
+        let (name, argument) = match filter.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (filter, None),
+        };
+        let filter = match (name, argument) {
+            ("lower", None) => Filter::Lower,
+            ("upper", None) => Filter::Upper,
+            ("strip_prefix", Some(prefix)) => Filter::StripPrefix(prefix),
+            ("strip_suffix", Some(suffix)) => Filter::StripSuffix(suffix),
+            ("replace", Some(argument)) => match argument.split_once(',') {
+                Some((from, to)) if !from.is_empty() => Filter::Replace(from, to),
+                _ => {
+                    return Err(AppError::ConfigError(format!(
+                        "Filter replace needs <from>,<to>, got '{}'",
+                        argument
+                    )))
+                }
+            },
+            _ => {
+                return Err(AppError::ConfigError(format!(
+                    "Unknown tag template filter '{}'",
+                    filter
+                )))
+            }
+        };
+        Ok(filter)
+    }
+
+    fn apply(&self, value: &str) -> String {
+        match self {
+            Filter::Lower => value.to_lowercase(),
+            Filter::Upper => value.to_uppercase(),
+            Filter::StripPrefix(prefix) => value.strip_prefix(prefix).unwrap_or(value).to_string(),
+            Filter::StripSuffix(suffix) => value.strip_suffix(suffix).unwrap_or(value).to_string(),
+            Filter::Replace(from, to) => value.replace(from, to),
+        }
+    }
+}
+
+fn placeholders(template: &str) -> Vec<Placeholder<'_>> {
+    let mut placeholders = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find("${").map(|i| offset + i) {
+        let Some(end) = template[start..].find('}').map(|i| start + i) else {
+            break;
+        };
+        let mut parts = template[start + 2..end].split('|');
+        placeholders.push(Placeholder {
+            range: start..end + 1,
+            variable: parts.next().unwrap_or_default().trim(),
+            filters: parts.map(str::trim).collect(),
+        });
+        offset = end + 1;
+    }
+    placeholders
+}
+
+/// The variables of `template`, like `RELEASE_VERSION` in `v${RELEASE_VERSION|lower}`.
+pub fn variables(template: &str) -> Vec<&str> {
+    placeholders(template)
+        .into_iter()
+        .map(|placeholder| placeholder.variable)
+        .collect()
+}
+
+/// Whether `template` has a placeholder of `variable`.
+pub fn uses(template: &str, variable: &str) -> bool {
+    variables(template).contains(&variable)
+}
+
+/// Checks that the filters of `template` exist and have valid arguments.
+pub fn check(template: &str) -> Result<(), AppError> {
+    for placeholder in placeholders(template) {
+        for filter in placeholder.filters {
+            Filter::parse(filter)?;
+        }
+    }
+    Ok(())
+}
+
+/// The values of the tag variables for `version`: `RELEASE_VERSION` and, if
+/// it starts with a number, `MAJOR`, `MINOR`, `PATCH` and `MAJOR_MINOR`, with
+/// missing components being 0. `v1.27-alpine` gives 1, 27, 0 and 1.27.
+pub fn version_variables(version: &str) -> Vec<(&'static str, String)> {
+    let mut variables = vec![("RELEASE_VERSION", version.to_string())];
+    let mut numbers = Vec::new();
+    for part in version.trim_start_matches(['v', 'V']).split('.') {
+        let end = part
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(part.len());
+        if end == 0 {
+            break;
+        }
+        numbers.push(&part[..end]);
+        if end < part.len() {
+            break;
+        }
+    }
+    if let Some(major) = numbers.first() {
+        let minor = numbers.get(1).unwrap_or(&"0");
+        let patch = numbers.get(2).unwrap_or(&"0");
+        variables.extend([
+            ("MAJOR", major.to_string()),
+            ("MINOR", minor.to_string()),
+            ("PATCH", patch.to_string()),
+            ("MAJOR_MINOR", format!("{}.{}", major, minor)),
+        ]);
+    }
+    variables
+}
+
+/// Fills in the placeholders of the variables in `values`, applying their
+/// filters. Placeholders of other variables are kept.
+pub fn render(template: &str, values: &[(&str, String)]) -> Result<String, AppError> {
+    let mut rendered = String::new();
+    let mut last = 0;
+    for placeholder in placeholders(template) {
+        let Some((_, value)) = values
+            .iter()
+            .find(|(name, _)| *name == placeholder.variable)
+        else {
+            continue;
+        };
+        let mut value = value.clone();
+        for filter in &placeholder.filters {
+            value = Filter::parse(filter)?.apply(&value);
+        }
+        rendered.push_str(&template[last..placeholder.range.start]);
+        rendered.push_str(&value);
+        last = placeholder.range.end;
+    }
+    rendered.push_str(&template[last..]);
+    Ok(rendered)
+}
+
+/// A regex matching the tags of `template`, with the placeholders of
+/// `variable` replaced by `capture`. Their filters are not applied.
+pub fn pattern(template: &str, variable: &str, capture: &str) -> String {
+    let mut pattern = String::from("^");
+    let mut last = 0;
+    for placeholder in placeholders(template) {
+        if placeholder.variable != variable {
+            continue;
+        }
+        pattern.push_str(&regex::escape(&template[last..placeholder.range.start]));
+        pattern.push_str(capture);
+        last = placeholder.range.end;
+    }
+    pattern.push_str(&regex::escape(&template[last..]));
+    pattern.push('$');
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(filter: &str, value: &str) -> String {
+        Filter::parse(filter).unwrap().apply(value)
+    }
+
+    #[test]
+    fn filters_are_applied() {
+        assert_eq!(apply("lower", "V1.2-RC1"), "v1.2-rc1");
+        assert_eq!(apply("upper", "v1.2-rc1"), "V1.2-RC1");
+        assert_eq!(apply("strip_prefix:v", "v1.2.3"), "1.2.3");
+        assert_eq!(apply("strip_prefix:v", "1.2.3"), "1.2.3");
+        assert_eq!(apply("strip_suffix:-alpine", "1.2-alpine"), "1.2");
+        assert_eq!(apply("replace:+,-", "1.2.3+build+1"), "1.2.3-build-1");
+        assert_eq!(apply("replace:.,", "1.2.3"), "123");
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        for filter in [
+            "title",
+            "lower:x",
+            "strip_prefix",
+            "replace",
+            "replace:+",
+            "replace:,-",
+        ] {
+            assert!(Filter::parse(filter).is_err(), "{}", filter);
+        }
+        assert_eq!(
+            check("${RELEASE_VERSION|lower|title}")
+                .unwrap_err()
+                .to_string(),
+            "Invalid configuration: Unknown tag template filter 'title'"
+        );
+    }
+
+    #[test]
+    fn placeholders_are_found() {
+        let template = "v${RELEASE_VERSION | strip_prefix:v | lower}-${MAJOR}";
+        let placeholders = placeholders(template);
+        assert_eq!(placeholders.len(), 2);
+        assert_eq!(placeholders[0].range, 1..44);
+        assert_eq!(placeholders[0].variable, "RELEASE_VERSION");
+        assert_eq!(placeholders[0].filters, ["strip_prefix:v", "lower"]);
+        assert_eq!(placeholders[1].variable, "MAJOR");
+        assert!(placeholders[1].filters.is_empty());
+        // Everything from an unterminated `${` on is text
+        assert_eq!(variables("${MAJOR}-${MINOR"), ["MAJOR"]);
+        assert!(variables("$MAJOR {MINOR}").is_empty());
+    }
+
+    fn numbers(version: &str) -> Vec<String> {
+        version_variables(version)
+            .into_iter()
+            .skip(1)
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    #[test]
+    fn version_components_are_extracted() {
+        let variables = version_variables("v1.27-alpine");
+        assert_eq!(
+            variables[0],
+            ("RELEASE_VERSION", "v1.27-alpine".to_string())
+        );
+        let names: Vec<_> = variables.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            ["RELEASE_VERSION", "MAJOR", "MINOR", "PATCH", "MAJOR_MINOR"]
+        );
+        assert_eq!(numbers("v1.27-alpine"), ["1", "27", "0", "1.27"]);
+        assert_eq!(numbers("V2.4.62"), ["2", "4", "62", "2.4"]);
+        assert_eq!(numbers("1.2.3.4"), ["1", "2", "3", "1.2"]);
+        // Leading zeros are kept, as in the tags of calendar versions
+        assert_eq!(numbers("2024.05"), ["2024", "05", "0", "2024.05"]);
+        assert_eq!(numbers("7"), ["7", "0", "0", "7.0"]);
+        assert_eq!(numbers("3.x"), ["3", "0", "0", "3.0"]);
+        assert_eq!(numbers("1.2.3-rc.4"), ["1", "2", "3", "1.2"]);
+    }
+
+    #[test]
+    fn non_numeric_versions_have_no_components() {
+        for version in ["latest", "release-1.2", "v", ""] {
+            assert_eq!(
+                version_variables(version),
+                [("RELEASE_VERSION", version.to_string())]
+            );
+        }
+    }
+
+    #[test]
+    fn templates_are_rendered() {
+        let values = [("RELEASE_VERSION", "V1.2.3+build".to_string())];
+        let render = |template| render(template, &values).unwrap();
+        assert_eq!(
+            render("${RELEASE_VERSION|strip_prefix:V|replace:+,-|upper}-alpine"),
+            "1.2.3-BUILD-alpine"
+        );
+        assert_eq!(
+            render("${RELEASE_VERSION}-${COMMIT}"),
+            "V1.2.3+build-${COMMIT}"
+        );
+        assert_eq!(render("${RELEASE_VERSION"), "${RELEASE_VERSION");
+        assert!(super::render("${RELEASE_VERSION|title}", &values).is_err());
+    }
+
+    #[test]
+    fn patterns_capture_the_variable() {
+        assert_eq!(
+            pattern("v${RELEASE_VERSION}-alpine", "RELEASE_VERSION", "(.+)"),
+            r"^v(.+)\-alpine$"
+        );
+        // The filters of the placeholder are left to the capture
+        assert_eq!(
+            pattern(
+                "${RELEASE_VERSION|strip_prefix:v}.${MAJOR}",
+                "RELEASE_VERSION",
+                "(.+)"
+            ),
+            r"^(.+)\.\$\{MAJOR\}$"
+        );
+        assert_eq!(pattern("latest", "RELEASE_VERSION", "(.+)"), "^latest$");
+    }
+}
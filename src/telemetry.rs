@@ -0,0 +1,137 @@
+//! OTLP export of the service spans and of run metrics, built with the `otel`
+//! feature.
+
+use super::config::OutputData;
+use super::error::AppError;
+use std::time::Duration;
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+#[cfg(feature = "otel")]
+use {
+    opentelemetry::trace::TracerProvider,
+    opentelemetry::{global, KeyValue},
+    opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig},
+    opentelemetry_sdk::metrics::SdkMeterProvider,
+    opentelemetry_sdk::trace::SdkTracerProvider,
+    opentelemetry_sdk::Resource,
+    tracing::warn,
+};
+
+#[cfg(feature = "otel")]
+const SERVICE_NAME: &str = "version-updater";
+
+/// The exporters, which send what is left and shut down when dropped.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    tracer_provider: SdkTracerProvider,
+    #[cfg(feature = "otel")]
+    meter_provider: SdkMeterProvider,
+}
+
+impl Telemetry {
+    /// Exports to the OTLP/HTTP collector at `endpoint`, e.g. `http://localhost:4318`.
+    /// Headers and timeouts are read from the standard `OTEL_EXPORTER_OTLP_*` variables.
+    #[cfg(feature = "otel")]
+    pub fn init(endpoint: &str) -> Result<Self, AppError> {
+        let endpoint = endpoint.trim_end_matches('/');
+        let resource = Resource::builder().with_service_name(SERVICE_NAME).build();
+        let exporter_error = |e: opentelemetry_otlp::ExporterBuildError| {
+            AppError::ConfigError(format!("Failed to create OTLP exporter: {}", e))
+        };
+
+        let span_exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint))
+            .build()
+            .map_err(exporter_error)?;
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(span_exporter)
+            .with_resource(resource.clone())
+            .build();
+
+        let metric_exporter = MetricExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/metrics", endpoint))
+            .build()
+            .map_err(exporter_error)?;
+        let meter_provider = SdkMeterProvider::builder()
+            .with_periodic_exporter(metric_exporter)
+            .with_resource(resource)
+            .build();
+        global::set_meter_provider(meter_provider.clone());
+
+        Ok(Self {
+            tracer_provider,
+            meter_provider,
+        })
+    }
+
+    #[cfg(not(feature = "otel"))]
+    pub fn init(_endpoint: &str) -> Result<Self, AppError> {
+        Err(AppError::FeatureDisabled("OpenTelemetry export", "otel"))
+    }
+
+    /// A layer exporting the spans of resolved services.
+    #[cfg(feature = "otel")]
+    pub fn layer<S>(&self) -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.tracer_provider.tracer(SERVICE_NAME))
+    }
+
+    #[cfg(not(feature = "otel"))]
+    pub fn layer<S>(&self) -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        tracing_subscriber::layer::Identity::new()
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        {
+            if let Err(e) = self.tracer_provider.shutdown() {
+                warn!("Failed to export traces: {}", e);
+            }
+            if let Err(e) = self.meter_provider.shutdown() {
+                warn!("Failed to export metrics: {}", e);
+            }
+        }
+    }
+}
+
+/// Records the duration of a run and the outcome of each service in it. Does
+/// nothing unless `Telemetry` was initialized.
+#[cfg(feature = "otel")]
+pub fn record_run(output: &OutputData, duration: Duration) {
+    let meter = global::meter(SERVICE_NAME);
+    meter
+        .f64_histogram("version_updater.run.duration")
+        .with_unit("s")
+        .with_description("Time taken to resolve the services of a run")
+        .build()
+        .record(duration.as_secs_f64(), &[]);
+
+    let outcomes = meter
+        .u64_counter("version_updater.service.outcomes")
+        .with_description(
+            "Resolved services by status: resolved, tag_missing, rate_limited or error",
+        )
+        .build();
+    for (name, version) in output {
+        outcomes.add(
+            1,
+            &[
+                KeyValue::new("service", name.clone()),
+                KeyValue::new("status", version.outcome.status()),
+            ],
+        );
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn record_run(_output: &OutputData, _duration: Duration) {}
@@ -0,0 +1,101 @@
+use super::updater::{ServiceUpdate, UpdatePlan};
+
+pub const DEFAULT_SERVICE_TITLE: &str = "chore(deps): bump {service} to {new_version}";
+pub const DEFAULT_COMBINED_TITLE: &str = "chore(deps): bump {count} services";
+pub const DEFAULT_SERVICE_BODY: &str = "Updates `{image}` from `{old_tag}` to `{new_tag}`.";
+pub const DEFAULT_COMBINED_BODY: &str = "{updates}";
+
+/// Replaces `{name}` placeholders with the given values. Unknown placeholders
+/// are left as they are.
+pub fn render(template: &str, vars: &[(&str, String)]) -> String {
+    let mut rendered = template.to_string();
+    for (name, value) in vars {
+        rendered = rendered.replace(&format!("{{{}}}", name), value);
+    }
+    rendered
+}
+
+/// Variables describing a single service update: `{service}`, `{image}`,
+/// `{old_tag}`, `{new_tag}`, `{old_version}`, `{new_version}` and `{update_type}`.
+pub fn service_vars(update: &ServiceUpdate) -> Vec<(&'static str, String)> {
+    let unknown = || "unknown".to_string();
+    vec![
+        ("service", update.service.clone()),
+        ("image", update.image.clone()),
+        ("old_tag", update.old_tag.clone().unwrap_or_else(unknown)),
+        ("new_tag", update.tag.clone()),
+        (
+            "old_version",
+            update.old_version.clone().unwrap_or_else(unknown),
+        ),
+        ("new_version", update.version.clone()),
+        (
+            "update_type",
+            update_type(update.old_version.as_deref(), &update.version).to_string(),
+        ),
+        // Short alias of `new_tag`
+        ("tag", update.tag.clone()),
+    ]
+}
+
+/// Variables describing a whole update run: `{count}`, `{services}` and
+/// `{updates}` (a markdown list). If a single service was updated, its
+/// variables are available as well.
+pub fn plan_vars(plan: &UpdatePlan) -> Vec<(&'static str, String)> {
+    let updates: Vec<String> = plan
+        .services
+        .iter()
+        .map(|update| match &update.old_version {
+            Some(old) => format!("- `{}`: {} → {}", update.service, old, update.version),
+            None => format!("- `{}`: {}", update.service, update.version),
+        })
+        .collect();
+    let services: Vec<&str> = plan.services.iter().map(|u| u.service.as_str()).collect();
+
+    let mut vars = vec![
+        ("count", plan.services.len().to_string()),
+        ("services", services.join(", ")),
+        ("updates", updates.join("\n")),
+    ];
+    if let [update] = plan.services.as_slice() {
+        vars.extend(service_vars(update));
+    }
+    vars
+}
+
+/// Renders a commit message from a title and body template, separated by a blank line.
+pub fn message(title: &str, body: &str, vars: &[(&str, String)]) -> String {
+    let title = render(title, vars);
+    let body = render(body, vars);
+    if body.trim().is_empty() {
+        title
+    } else {
+        format!("{}\n\n{}\n", title, body.trim_end())
+    }
+}
+
+/// Classifies an update as `major`, `minor`, `patch` or `other` by comparing the
+/// numeric components of the versions, or `unknown` without a previous version.
+pub fn update_type(old_version: Option<&str>, new_version: &str) -> &'static str {
+    let Some(old) = old_version else {
+        return "unknown";
+    };
+    let (old, new) = (numeric_parts(old), numeric_parts(new_version));
+    if old.is_empty() || new.is_empty() {
+        return "other";
+    }
+    for (i, kind) in ["major", "minor", "patch"].into_iter().enumerate() {
+        if old.get(i) != new.get(i) {
+            return kind;
+        }
+    }
+    "other"
+}
+
+fn numeric_parts(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches(['v', 'V'])
+        .split(['.', '-', '+'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
@@ -0,0 +1,108 @@
+use super::yaml;
+use super::{ResolvedUpdate, TargetValue};
+use tracing::{debug, warn};
+
+/// Sets a (nested) variable in an Ansible `group_vars`/`host_vars` file.
+/// Vault-encrypted files and values that are Jinja expressions are refused, as
+/// rewriting them would break the inventory.
+pub fn update(
+    content: &str,
+    key: &str,
+    value: &TargetValue,
+    update: &ResolvedUpdate,
+) -> Result<String, String> {
+    if content.trim_start().starts_with("$ANSIBLE_VAULT") {
+        return Err("Vault-encrypted vars files cannot be updated".to_string());
+    }
+
+    let segments = yaml::parse_path(key)?;
+    let new_value = value.render(update);
+
+    let mut found = false;
+    let mut templated = false;
+    let updated = yaml::rewrite_values(content, |entry| {
+        if entry.path != segments.as_slice() {
+            return None;
+        }
+        if entry.value.contains("{{") || entry.value.starts_with("!vault") {
+            warn!("Not updating {}: its value is templated or encrypted", key);
+            templated = true;
+            return None;
+        }
+        found = true;
+        debug!("Setting Ansible variable {} to {}", key, new_value);
+        Some(new_value.clone())
+    });
+
+    if !found {
+        return Err(if templated {
+            format!("Variable '{}' is templated or encrypted", key)
+        } else {
+            format!("Variable '{}' not found", key)
+        });
+    }
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: ResolvedUpdate = ResolvedUpdate {
+        service: "web",
+        image: "nginx",
+        tag: "1.27.0-alpine",
+        version: "1.27.0",
+    };
+
+    #[test]
+    fn nested_variables_are_set() {
+        let content = "\
+---
+# web servers
+nginx:
+  version: &version \"1.25\" # pinned
+  flags: [--with-http_v2]
+nginx_version: 1.25
+";
+        assert_eq!(
+            update(content, "nginx.version", &TargetValue::Version, &UPDATE),
+            Ok("\
+---
+# web servers
+nginx:
+  version: &version \"1.27.0\" # pinned
+  flags: [--with-http_v2]
+nginx_version: 1.25
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn templated_and_encrypted_values_are_refused() {
+        let content = "nginx_version: \"{{ default_version }}\"\n";
+        assert_eq!(
+            update(content, "nginx_version", &TargetValue::Tag, &UPDATE),
+            Err("Variable 'nginx_version' is templated or encrypted".to_string())
+        );
+        assert_eq!(
+            update(
+                "$ANSIBLE_VAULT;1.1;AES256\n6162\n",
+                "nginx_version",
+                &TargetValue::Tag,
+                &UPDATE
+            ),
+            Err("Vault-encrypted vars files cannot be updated".to_string())
+        );
+        assert_eq!(
+            update(
+                "nginx:\n  port: 80\n",
+                "nginx.version",
+                &TargetValue::Tag,
+                &UPDATE
+            ),
+            Err("Variable 'nginx.version' not found".to_string())
+        );
+    }
+}
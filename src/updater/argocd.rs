@@ -0,0 +1,177 @@
+use super::yaml::{self, Segment};
+use super::{same_image, split_image_ref, ResolvedUpdate, TargetValue};
+use tracing::debug;
+
+/// Updates an Argo CD `Application` manifest. With a `parameter`, the `value` of
+/// the matching `helm.parameters` entry is set; otherwise the `kustomize.images`
+/// entries referencing the service image get the new tag. Both single-source
+/// (`spec.source`) and multi-source (`spec.sources`) applications are supported.
+pub fn update(
+    content: &str,
+    parameter: Option<&str>,
+    value: &TargetValue,
+    update: &ResolvedUpdate,
+) -> Result<String, String> {
+    let mut found = false;
+    let updated = match parameter {
+        Some(parameter) => {
+            // Parameters are matched by their `name`, which may come before or after
+            // the `value` key, so the matching list items are collected first
+            let mut items: Vec<Vec<Segment>> = Vec::new();
+            yaml::rewrite_values(content, |entry| {
+                if let Some(item) = parameter_item(entry.path, "name") {
+                    if entry.value == parameter {
+                        items.push(item.to_vec());
+                    }
+                }
+                None
+            });
+
+            let new_value = value.render(update);
+            yaml::rewrite_values(content, |entry| {
+                let item = parameter_item(entry.path, "value")?;
+                if !items.iter().any(|matched| matched == item) {
+                    return None;
+                }
+                found = true;
+                debug!("Setting helm parameter {} to {}", parameter, new_value);
+                Some(new_value.clone())
+            })
+        }
+        None => yaml::rewrite_values(content, |entry| {
+            let [.., Segment::Key(kustomize), Segment::Key(images), Segment::Index(_)] = entry.path
+            else {
+                return None;
+            };
+            if kustomize != "kustomize" || images != "images" {
+                return None;
+            }
+            let new_value = rewrite_kustomize_image(entry.value, update)?;
+            found = true;
+            Some(new_value)
+        }),
+    };
+
+    if !found {
+        return Err(match parameter {
+            Some(parameter) => format!("Helm parameter '{}' not found", parameter),
+            None => format!("No kustomize image entry for {}", update.image),
+        });
+    }
+    Ok(updated)
+}
+
+/// Returns the path of the `helm.parameters` list item if `path` points at its `key`.
+fn parameter_item<'a>(path: &'a [Segment], key: &str) -> Option<&'a [Segment]> {
+    let [.., Segment::Key(helm), Segment::Key(parameters), Segment::Index(_), Segment::Key(k)] =
+        path
+    else {
+        return None;
+    };
+    (helm == "helm" && parameters == "parameters" && k == key).then(|| &path[..path.len() - 1])
+}
+
+/// Rewrites a kustomize image override (`name:tag` or `name=newName:tag`).
+fn rewrite_kustomize_image(entry: &str, update: &ResolvedUpdate) -> Option<String> {
+    match entry.split_once('=') {
+        Some((name, replacement)) => {
+            let (new_name, _) = split_image_ref(replacement);
+            if !same_image(name, update.image) && !same_image(new_name, update.image) {
+                return None;
+            }
+            Some(format!("{}={}:{}", name, new_name, update.tag))
+        }
+        None => {
+            let (name, _) = split_image_ref(entry);
+            same_image(name, update.image).then(|| format!("{}:{}", name, update.tag))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: ResolvedUpdate = ResolvedUpdate {
+        service: "web",
+        image: "nginx",
+        tag: "1.27.0-alpine",
+        version: "1.27.0",
+    };
+
+    #[test]
+    fn helm_parameters_are_matched_by_name() {
+        let content = "\
+spec:
+  sources:
+    - helm:
+        parameters:
+          - value: \"1.25\" # pinned
+            name: image.tag
+          - name: replicas
+            value: \"2\"
+    - helm:
+        parameters:
+          - name: image.tag
+            value: 1.25
+";
+        assert_eq!(
+            update(content, Some("image.tag"), &TargetValue::Tag, &UPDATE),
+            Ok("\
+spec:
+  sources:
+    - helm:
+        parameters:
+          - value: \"1.27.0-alpine\" # pinned
+            name: image.tag
+          - name: replicas
+            value: \"2\"
+    - helm:
+        parameters:
+          - name: image.tag
+            value: 1.27.0-alpine
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn kustomize_images_are_updated() {
+        let content = "\
+spec:
+  source:
+    kustomize:
+      images:
+        - nginx:1.25
+        - 'proxy=docker.io/library/nginx:1.25'
+        - redis:7
+";
+        assert_eq!(
+            update(content, None, &TargetValue::Tag, &UPDATE),
+            Ok("\
+spec:
+  source:
+    kustomize:
+      images:
+        - nginx:1.27.0-alpine
+        - 'proxy=docker.io/library/nginx:1.27.0-alpine'
+        - redis:7
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn missing_entries_are_errors() {
+        let content =
+            "spec:\n  source:\n    helm:\n      parameters: [{name: image.tag, value: \"1.25\"}]\n";
+        assert_eq!(
+            update(content, Some("image.tag"), &TargetValue::Tag, &UPDATE),
+            Err("Helm parameter 'image.tag' not found".to_string())
+        );
+        assert_eq!(
+            update(content, None, &TargetValue::Tag, &UPDATE),
+            Err("No kustomize image entry for nginx".to_string())
+        );
+    }
+}
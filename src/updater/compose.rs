@@ -0,0 +1,58 @@
+use super::{update_compose_images, ResolvedUpdate};
+
+/// Rewrites every `image:` entry of a docker-compose file that references the
+/// service image. Everything else, including comments, is left untouched.
+pub fn update(content: &str, update: &ResolvedUpdate) -> String {
+    update_compose_images(content, update.image, update.tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: ResolvedUpdate = ResolvedUpdate {
+        service: "web",
+        image: "nginx",
+        tag: "1.27.0-alpine",
+        version: "1.27.0",
+    };
+
+    #[test]
+    fn image_lines_of_the_service_are_updated() {
+        let content = "\
+x-base: &base
+  image: \"nginx:1.25\" # pinned
+services:
+  web:
+    <<: *base
+  proxy:
+    image: docker.io/library/nginx@sha256:0123
+  cache:
+    image: 'redis:7'
+  jobs:
+    - image: nginx
+";
+        assert_eq!(
+            update(content, &UPDATE),
+            "\
+x-base: &base
+  image: \"nginx:1.27.0-alpine\" # pinned
+services:
+  web:
+    <<: *base
+  proxy:
+    image: docker.io/library/nginx:1.27.0-alpine
+  cache:
+    image: 'redis:7'
+  jobs:
+    - image: nginx:1.27.0-alpine
+"
+        );
+    }
+
+    #[test]
+    fn other_images_leave_the_file_unchanged() {
+        let content = "services:\r\n  web:\r\n    image: nginx-exporter:1.0 # nginx\r\n    environment: [IMAGE=nginx:1.25]\r\n";
+        assert_eq!(update(content, &UPDATE), content);
+    }
+}
@@ -0,0 +1,118 @@
+use super::{map_lines, same_image, split_image_ref, ResolvedUpdate, TargetValue};
+use regex::Regex;
+use std::sync::LazyLock;
+
+// `FROM [--platform=...] image[:tag][@digest] [AS name]`
+static FROM_LINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)^(\s*FROM\s+(?:--\S+\s+)*)(\S+)(.*)$").unwrap());
+
+// `ARG NAME=default`, optionally quoted
+static ARG_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)^(\s*ARG\s+)([A-Za-z_][A-Za-z0-9_]*)=(["']?)([^"'\s]*)(["']?)(.*)$"#).unwrap()
+});
+
+/// Rewrites `FROM` instructions referencing the service image and, when `arg`
+/// is given, the default value of `ARG <arg>=...`.
+pub fn update(
+    content: &str,
+    arg: Option<&str>,
+    value: &TargetValue,
+    update: &ResolvedUpdate,
+) -> String {
+    map_lines(content, |line| {
+        if let Some(captures) = FROM_LINE.captures(line) {
+            let reference = &captures[2];
+            // A reference with variables is updated through its ARG
+            if reference.contains('$') {
+                return None;
+            }
+            let (name, _) = split_image_ref(reference);
+            if !same_image(name, update.image) {
+                return None;
+            }
+            return Some(format!(
+                "{}{}:{}{}",
+                &captures[1], name, update.tag, &captures[3]
+            ));
+        }
+
+        let arg = arg?;
+        let captures = ARG_LINE.captures(line)?;
+        if &captures[2] != arg {
+            return None;
+        }
+        Some(format!(
+            "{}{}={}{}{}{}",
+            &captures[1],
+            &captures[2],
+            &captures[3],
+            value.render(update),
+            &captures[5],
+            &captures[6]
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: ResolvedUpdate = ResolvedUpdate {
+        service: "web",
+        image: "nginx",
+        tag: "1.27.0-alpine",
+        version: "1.27.0",
+    };
+
+    #[test]
+    fn from_lines_of_the_image_are_updated() {
+        let content = "FROM --platform=$BUILDPLATFORM nginx:1.25 AS build\n\
+                       RUN make\n\
+                       from docker.io/library/nginx:1.25@sha256:0123 as runtime\n\
+                       FROM node:20\n";
+        assert_eq!(
+            update(content, None, &TargetValue::Tag, &UPDATE),
+            "FROM --platform=$BUILDPLATFORM nginx:1.27.0-alpine AS build\n\
+             RUN make\n\
+             from docker.io/library/nginx:1.27.0-alpine as runtime\n\
+             FROM node:20\n"
+        );
+    }
+
+    #[test]
+    fn from_lines_with_variables_are_kept() {
+        let content = "ARG NGINX_VERSION=1.25\n\
+                       FROM nginx:${NGINX_VERSION}\n\
+                       FROM ${REGISTRY}/nginx:1.25\n";
+        assert_eq!(update(content, None, &TargetValue::Tag, &UPDATE), content);
+        assert_eq!(
+            update(
+                content,
+                Some("NGINX_VERSION"),
+                &TargetValue::Version,
+                &UPDATE
+            ),
+            "ARG NGINX_VERSION=1.27.0\n\
+             FROM nginx:${NGINX_VERSION}\n\
+             FROM ${REGISTRY}/nginx:1.25\n"
+        );
+    }
+
+    #[test]
+    fn arg_defaults_keep_their_quotes() {
+        let content = "ARG NGINX_TAG=\"1.25\" # pinned\nARG OTHER=1.25\narg nginx_tag=1.25\n";
+        assert_eq!(
+            update(content, Some("NGINX_TAG"), &TargetValue::Tag, &UPDATE),
+            "ARG NGINX_TAG=\"1.27.0-alpine\" # pinned\nARG OTHER=1.25\narg nginx_tag=1.25\n"
+        );
+    }
+
+    #[test]
+    fn unrelated_content_is_unchanged() {
+        let content = "FROM node:20 AS build\r\nARG VERSION=1\r\n";
+        assert_eq!(
+            update(content, Some("NGINX_TAG"), &TargetValue::Tag, &UPDATE),
+            content
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use super::{map_lines, ResolvedUpdate, TargetValue};
+use regex::Regex;
+
+pub const DEFAULT_KEY_TEMPLATE: &str = "${SERVICE}_TAG";
+
+/// Rewrites the `KEY=value` entry of an env file. `${SERVICE}` in the key
+/// template is replaced with the service name in upper snake case.
+pub fn update(
+    content: &str,
+    key_template: &str,
+    value: &TargetValue,
+    update: &ResolvedUpdate,
+) -> Result<String, String> {
+    let key = key_template.replace("${SERVICE}", &env_name(update.service));
+    let re = Regex::new(&format!(
+        r#"^(\s*(?:export\s+)?{}\s*=\s*)(["']?)([^"'#]*?)(["']?)(\s*(?:#.*)?)$"#,
+        regex::escape(&key)
+    ))
+    .map_err(|e| e.to_string())?;
+    let new_value = value.render(update);
+
+    let mut found = false;
+    let updated = map_lines(content, |line| {
+        let captures = re.captures(line)?;
+        found = true;
+        Some(format!(
+            "{}{}{}{}{}",
+            &captures[1], &captures[2], new_value, &captures[4], &captures[5]
+        ))
+    });
+
+    if !found {
+        return Err(format!("Key '{}' not found", key));
+    }
+    Ok(updated)
+}
+
+fn env_name(service: &str) -> String {
+    service
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: ResolvedUpdate = ResolvedUpdate {
+        service: "web-app",
+        image: "nginx",
+        tag: "1.27.0-alpine",
+        version: "1.27.0",
+    };
+
+    #[test]
+    fn keys_keep_their_quotes_and_comments() {
+        let content = "# images\nexport WEB_APP_TAG=\"1.25\" # pinned\nWEB_APP_TAG_OLD=1.24\n";
+        assert_eq!(
+            update(content, DEFAULT_KEY_TEMPLATE, &TargetValue::Tag, &UPDATE),
+            Ok(
+                "# images\nexport WEB_APP_TAG=\"1.27.0-alpine\" # pinned\nWEB_APP_TAG_OLD=1.24\n"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            update(
+                "NGINX_VERSION='1.25'\r\n",
+                "NGINX_VERSION",
+                &TargetValue::Version,
+                &UPDATE
+            ),
+            Ok("NGINX_VERSION='1.27.0'\r\n".to_string())
+        );
+        assert_eq!(
+            update(
+                "WEB_APP_TAG = 1.25\n",
+                DEFAULT_KEY_TEMPLATE,
+                &TargetValue::Tag,
+                &UPDATE
+            ),
+            Ok("WEB_APP_TAG = 1.27.0-alpine\n".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_keys_are_errors() {
+        assert_eq!(
+            update(
+                "# WEB_APP_TAG=1.25\nWEB_TAG=1.25\n",
+                DEFAULT_KEY_TEMPLATE,
+                &TargetValue::Tag,
+                &UPDATE
+            ),
+            Err("Key 'WEB_APP_TAG' not found".to_string())
+        );
+    }
+}
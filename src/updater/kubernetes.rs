@@ -0,0 +1,113 @@
+use super::yaml::{self, Segment};
+use super::{same_image, split_image_ref, ResolvedUpdate};
+use tracing::debug;
+
+const CONTAINER_LISTS: [&str; 3] = ["containers", "initContainers", "ephemeralContainers"];
+
+/// Rewrites the `image` of every container referencing the service image in a
+/// (multi-document) Kubernetes manifest. Works for any workload kind, as the
+/// container lists are matched by name rather than by their full path.
+pub fn update(content: &str, update: &ResolvedUpdate) -> String {
+    yaml::rewrite_values(content, |entry| {
+        let [.., Segment::Key(list), Segment::Index(_), Segment::Key(key)] = entry.path else {
+            return None;
+        };
+        if key != "image" || !CONTAINER_LISTS.contains(&list.as_str()) {
+            return None;
+        }
+
+        let (name, _) = split_image_ref(entry.value);
+        if !same_image(name, update.image) {
+            return None;
+        }
+
+        debug!(
+            "Updating {} in document {} to tag {}",
+            yaml::display_path(entry.path),
+            entry.doc,
+            update.tag
+        );
+        Some(format!("{}:{}", name, update.tag))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: ResolvedUpdate = ResolvedUpdate {
+        service: "web",
+        image: "nginx",
+        tag: "1.27.0-alpine",
+        version: "1.27.0",
+    };
+
+    #[test]
+    fn containers_of_every_document_are_updated() {
+        let content = "\
+apiVersion: apps/v1
+kind: Deployment
+spec:
+  template:
+    spec:
+      initContainers:
+        - image: 'nginx:1.25' # init
+      containers:
+        - name: web
+          image: \"docker.io/library/nginx:1.25\"
+          args: [--image, nginx:1.25]
+        - name: cache
+          image: redis:7
+---
+kind: CronJob
+spec:
+  jobTemplate:
+    spec:
+      template:
+        spec:
+          containers:
+            - image: nginx@sha256:0123
+";
+        assert_eq!(
+            update(content, &UPDATE),
+            "\
+apiVersion: apps/v1
+kind: Deployment
+spec:
+  template:
+    spec:
+      initContainers:
+        - image: 'nginx:1.27.0-alpine' # init
+      containers:
+        - name: web
+          image: \"docker.io/library/nginx:1.27.0-alpine\"
+          args: [--image, nginx:1.25]
+        - name: cache
+          image: redis:7
+---
+kind: CronJob
+spec:
+  jobTemplate:
+    spec:
+      template:
+        spec:
+          containers:
+            - image: nginx:1.27.0-alpine
+"
+        );
+    }
+
+    #[test]
+    fn images_outside_container_lists_are_unchanged() {
+        let content = "\
+metadata:
+  annotations:
+    image: nginx:1.25
+spec:
+  containers: [{name: web, image: \"nginx:1.25\"}]
+  volumes:
+    - image: nginx:1.25
+";
+        assert_eq!(update(content, &UPDATE), content);
+    }
+}
@@ -0,0 +1,444 @@
+mod ansible;
+mod argocd;
+mod compose;
+mod dockerfile;
+mod dotenv;
+mod kubernetes;
+mod path;
+mod pattern;
+mod terraform;
+pub(crate) mod yaml;
+
+use super::config::{OutputData, ServiceConfig};
+use super::error::AppError;
+use super::files;
+use super::service;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use tracing::{debug, info, warn};
+
+// Matches `image: <ref>` and `- image: <ref>` lines, keeping quotes and trailing comments
+static IMAGE_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(\s*(?:-\s+)?image:\s*)(["']?)([^"'\s#]+)(["']?)(.*)$"#).unwrap()
+});
+
+/// A file that should be rewritten with the resolved version of a service.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum UpdateTarget {
+    /// Rewrites `image:` lines referencing the service image in a docker-compose file
+    Compose { file: PathBuf },
+    /// Rewrites container images in (multi-document) Kubernetes manifests
+    Kubernetes { file: PathBuf },
+    /// Sets a (nested) key like `image.tag` in a Helm values file
+    Helm {
+        file: PathBuf,
+        path: String,
+        #[serde(default)]
+        value: TargetValue,
+    },
+    /// Rewrites `FROM` lines referencing the service image and optionally an `ARG` default
+    Dockerfile {
+        file: PathBuf,
+        #[serde(default)]
+        arg: Option<String>,
+        #[serde(default)]
+        value: TargetValue,
+    },
+    /// Sets a string variable in a `.tfvars` file or `locals` block
+    Terraform {
+        file: PathBuf,
+        variable: String,
+        #[serde(default)]
+        value: TargetValue,
+    },
+    /// Rewrites a `KEY=value` entry in an env file
+    Env {
+        file: PathBuf,
+        #[serde(default = "default_env_key")]
+        key: String,
+        #[serde(default)]
+        value: TargetValue,
+    },
+    /// Replaces every match of a regex (or its first capture group)
+    Regex {
+        file: PathBuf,
+        pattern: String,
+        #[serde(default)]
+        value: TargetValue,
+    },
+    /// Rewrites lines marked with a `version-updater:<service>` comment
+    Marker {
+        file: PathBuf,
+        #[serde(default)]
+        pattern: Option<String>,
+        #[serde(default)]
+        value: TargetValue,
+    },
+    /// Sets a Helm parameter or kustomize image in an Argo CD `Application`
+    Argocd {
+        file: PathBuf,
+        #[serde(default)]
+        parameter: Option<String>,
+        #[serde(default)]
+        value: TargetValue,
+    },
+    /// Sets a variable in an Ansible `group_vars`/`host_vars` file
+    Ansible {
+        file: PathBuf,
+        key: String,
+        #[serde(default)]
+        value: TargetValue,
+    },
+    /// Sets a path expression like `.spec.values.image.tag` in any YAML or JSON document
+    Path {
+        file: PathBuf,
+        path: String,
+        #[serde(default)]
+        value: TargetValue,
+    },
+}
+
+fn default_env_key() -> String {
+    dotenv::DEFAULT_KEY_TEMPLATE.to_string()
+}
+
+/// Which part of the resolved image is written to keyed targets.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetValue {
+    /// The image tag, e.g. `1.27.0`
+    #[default]
+    Tag,
+    /// The image name without tag, e.g. `nginx`
+    Image,
+    /// The full reference, e.g. `nginx:1.27.0`
+    Reference,
+    /// The version extracted from the upstream release, e.g. `1.27.0`
+    Version,
+}
+
+impl TargetValue {
+    fn render(&self, update: &ResolvedUpdate) -> String {
+        match self {
+            TargetValue::Tag => update.tag.to_string(),
+            TargetValue::Image => update.image.to_string(),
+            TargetValue::Reference => format!("{}:{}", update.image, update.tag),
+            TargetValue::Version => update.version.to_string(),
+        }
+    }
+}
+
+/// The resolved values a target is updated with.
+pub struct ResolvedUpdate<'a> {
+    pub service: &'a str,
+    pub image: &'a str,
+    pub tag: &'a str,
+    pub version: &'a str,
+}
+
+/// The new content of a single file after all targets pointing at it were applied.
+pub struct FileChange {
+    pub path: PathBuf,
+    pub original: String,
+    pub updated: String,
+}
+
+impl FileChange {
+    /// Renders the change as a unified diff with three lines of context.
+    pub fn unified_diff(&self) -> String {
+        let path = self.path.display().to_string();
+        TextDiff::from_lines(&self.original, &self.updated)
+            .unified_diff()
+            .context_radius(3)
+            .header(&format!("a/{}", path), &format!("b/{}", path))
+            .to_string()
+    }
+}
+
+/// The files changed by a single service, with their content right after the
+/// service's targets were applied. Used to create one commit per service.
+pub struct ServiceUpdate {
+    pub service: String,
+    pub image: String,
+    pub tag: String,
+    pub version: String,
+    /// Tag and version from the previous output file, if known
+    pub old_tag: Option<String>,
+    pub old_version: Option<String>,
+    pub files: Vec<(PathBuf, String)>,
+}
+
+/// All changes of an update run, both per file and per service.
+#[derive(Default)]
+pub struct UpdatePlan {
+    pub changes: Vec<FileChange>,
+    pub services: Vec<ServiceUpdate>,
+}
+
+impl UpdateTarget {
+    pub fn file(&self) -> &Path {
+        match self {
+            UpdateTarget::Compose { file }
+            | UpdateTarget::Kubernetes { file }
+            | UpdateTarget::Helm { file, .. }
+            | UpdateTarget::Dockerfile { file, .. }
+            | UpdateTarget::Terraform { file, .. }
+            | UpdateTarget::Env { file, .. }
+            | UpdateTarget::Regex { file, .. }
+            | UpdateTarget::Marker { file, .. }
+            | UpdateTarget::Argocd { file, .. }
+            | UpdateTarget::Ansible { file, .. }
+            | UpdateTarget::Path { file, .. } => file,
+        }
+    }
+
+    /// Checks the settings of the target without reading the file.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.file().exists() {
+            return Err("File does not exist".to_string());
+        }
+        match self {
+            UpdateTarget::Helm { path, .. } | UpdateTarget::Path { path, .. } => {
+                yaml::parse_path(path).map(drop)
+            }
+            UpdateTarget::Ansible { key, .. } => yaml::parse_path(key).map(drop),
+            UpdateTarget::Regex { pattern, .. }
+            | UpdateTarget::Marker {
+                pattern: Some(pattern),
+                ..
+            } => Regex::new(pattern).map(drop).map_err(|e| e.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(&self, content: &str, update: &ResolvedUpdate) -> Result<String, String> {
+        match self {
+            UpdateTarget::Compose { .. } => Ok(compose::update(content, update)),
+            UpdateTarget::Kubernetes { .. } => Ok(kubernetes::update(content, update)),
+            UpdateTarget::Helm { path, value, .. } => {
+                path::update_yaml(content, path, value, update)
+            }
+            UpdateTarget::Dockerfile { arg, value, .. } => {
+                Ok(dockerfile::update(content, arg.as_deref(), value, update))
+            }
+            UpdateTarget::Terraform {
+                variable, value, ..
+            } => terraform::update(content, variable, value, update),
+            UpdateTarget::Env { key, value, .. } => dotenv::update(content, key, value, update),
+            UpdateTarget::Regex { pattern, value, .. } => {
+                pattern::update_regex(content, pattern, value, update)
+            }
+            UpdateTarget::Marker { pattern, value, .. } => {
+                pattern::update_marker(content, pattern.as_deref(), value, update)
+            }
+            UpdateTarget::Argocd {
+                parameter, value, ..
+            } => argocd::update(content, parameter.as_deref(), value, update),
+            UpdateTarget::Ansible { key, value, .. } => {
+                ansible::update(content, key, value, update)
+            }
+            UpdateTarget::Path {
+                file, path, value, ..
+            } => path::update(file, content, path, value, update),
+        }
+    }
+}
+
+/// Computes the file changes for all successfully resolved services without touching the disk.
+/// The `previous` output, if any, provides the versions the services are updated from.
+pub fn plan_updates(
+    services: &HashMap<String, ServiceConfig>,
+    output: &OutputData,
+    previous: Option<&OutputData>,
+) -> Result<UpdatePlan, AppError> {
+    // Several services may target the same file, so changes are accumulated per path
+    let mut files: BTreeMap<PathBuf, (String, String)> = BTreeMap::new();
+    let mut service_updates = Vec::new();
+
+    for (name, result) in output {
+        let Some(service) = services.get(name) else {
+            continue;
+        };
+        if service.targets.is_empty() {
+            continue;
+        }
+        let _span = service::span(name, service).entered();
+        let (Some(tag), Some(version)) = (result.tag(), result.version()) else {
+            warn!("Skipping updates for service '{}': no valid tag", name);
+            continue;
+        };
+
+        let update = ResolvedUpdate {
+            service: name,
+            image: &result.image,
+            tag,
+            version,
+        };
+
+        let mut changed_files: Vec<PathBuf> = Vec::new();
+        for target in &service.targets {
+            let path = target.file().to_path_buf();
+            let (_, current) = match files.entry(path) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let content = fs::read_to_string(entry.key()).map_err(|e| {
+                        AppError::UpdateError(format!("{}: {}", entry.key().display(), e))
+                    })?;
+                    entry.insert((content.clone(), content))
+                }
+            };
+
+            let updated = target.apply(current, &update).map_err(|e| {
+                AppError::UpdateError(format!("{}: {}", target.file().display(), e))
+            })?;
+            if updated == *current {
+                debug!(
+                    "{} is already up to date for service '{}'",
+                    target.file().display(),
+                    name
+                );
+            } else if !changed_files.iter().any(|path| path == target.file()) {
+                changed_files.push(target.file().to_path_buf());
+            }
+            *current = updated;
+        }
+
+        if !changed_files.is_empty() {
+            let previous = previous
+                .and_then(|previous| previous.get(name))
+                .and_then(|previous| Some((previous.tag()?, previous.version()?)));
+            service_updates.push(ServiceUpdate {
+                service: name.clone(),
+                image: update.image.to_string(),
+                tag: update.tag.to_string(),
+                version: update.version.to_string(),
+                old_tag: previous.map(|(tag, _)| tag.to_string()),
+                old_version: previous.map(|(_, version)| version.to_string()),
+                files: changed_files
+                    .into_iter()
+                    .map(|path| {
+                        let content = files[&path].1.clone();
+                        (path, content)
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    Ok(UpdatePlan {
+        changes: files
+            .into_iter()
+            .filter(|(_, (original, updated))| original != updated)
+            .map(|(path, (original, updated))| FileChange {
+                path,
+                original,
+                updated,
+            })
+            .collect(),
+        services: service_updates,
+    })
+}
+
+/// Rewrites the `image:` lines of a compose file referencing `image` to `tag`.
+pub fn update_compose_images(content: &str, image: &str, tag: &str) -> String {
+    map_lines(content, |line| rewrite_image_line(line, image, tag))
+}
+
+/// Writes the planned changes to disk, refusing to overwrite files that were
+/// modified since the changes were planned.
+pub fn apply_changes(changes: &[FileChange], backup: bool) -> Result<(), AppError> {
+    for change in changes {
+        let current = fs::read_to_string(&change.path)
+            .map_err(|e| AppError::UpdateError(format!("{}: {}", change.path.display(), e)))?;
+        if current != change.original {
+            return Err(AppError::UpdateError(format!(
+                "{} was modified while updates were being planned",
+                change.path.display()
+            )));
+        }
+        info!("Updating {}", change.path.display());
+        files::write_atomic(&change.path, change.updated.as_bytes(), backup)?;
+    }
+    Ok(())
+}
+
+/// Splits an image reference into its name and tag, dropping any digest.
+pub fn split_image_ref(reference: &str) -> (&str, Option<&str>) {
+    let reference = reference.split('@').next().unwrap_or(reference);
+    match reference.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => (name, Some(tag)),
+        _ => (reference, None),
+    }
+}
+
+/// Returns whether two image names refer to the same repository, treating the
+/// different Docker Hub spellings (`nginx`, `docker.io/library/nginx`, ...) as equal.
+pub fn same_image(a: &str, b: &str) -> bool {
+    canonical_image_name(a) == canonical_image_name(b)
+}
+
+fn canonical_image_name(name: &str) -> String {
+    let (registry, path) = match name.split_once('/') {
+        Some((first, rest))
+            if first.contains('.') || first.contains(':') || first == "localhost" =>
+        {
+            (first, rest)
+        }
+        _ => ("docker.io", name),
+    };
+    let registry = match registry {
+        "registry.hub.docker.com" | "index.docker.io" | "registry-1.docker.io" => "docker.io",
+        r => r,
+    };
+    if registry == "docker.io" && !path.contains('/') {
+        format!("{}/library/{}", registry, path).to_lowercase()
+    } else {
+        format!("{}/{}", registry, path).to_lowercase()
+    }
+}
+
+/// Rewrites an `image:` line to `image:tag` if it references `image`, preserving
+/// indentation, quoting and trailing comments. Returns `None` for other lines.
+pub(crate) fn rewrite_image_line(line: &str, image: &str, tag: &str) -> Option<String> {
+    let captures = IMAGE_LINE.captures(line)?;
+    let reference = &captures[3];
+    let (name, _) = split_image_ref(reference);
+    if !same_image(name, image) {
+        return None;
+    }
+    if reference.contains('@') {
+        warn!("Dropping digest from image reference {}", reference);
+    }
+
+    Some(format!(
+        "{}{}{}:{}{}{}",
+        &captures[1], &captures[2], name, tag, &captures[4], &captures[5]
+    ))
+}
+
+/// Applies `rewrite` to every line while keeping the original line endings.
+pub(crate) fn map_lines(content: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let (body, ending) = match line.strip_suffix("\r\n") {
+            Some(body) => (body, "\r\n"),
+            None => match line.strip_suffix('\n') {
+                Some(body) => (body, "\n"),
+                None => (line, ""),
+            },
+        };
+        match rewrite(body) {
+            Some(updated) => result.push_str(&updated),
+            None => result.push_str(body),
+        }
+        result.push_str(ending);
+    }
+    result
+}
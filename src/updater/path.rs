@@ -0,0 +1,354 @@
+use super::yaml::{self, Segment};
+use super::{ResolvedUpdate, TargetValue};
+use std::path::Path;
+use tracing::debug;
+
+/// Sets the value at a path expression like `.spec.values.image.tag` in a YAML
+/// or JSON document. JSON is detected from the file extension or content.
+pub fn update(
+    file: &Path,
+    content: &str,
+    path: &str,
+    value: &TargetValue,
+    update: &ResolvedUpdate,
+) -> Result<String, String> {
+    let is_json = file.extension().is_some_and(|ext| ext == "json")
+        || content.trim_start().starts_with(['{', '[']);
+    if is_json {
+        update_json(content, path, value, update)
+    } else {
+        update_yaml(content, path, value, update)
+    }
+}
+
+/// Sets the value at `path` in every document of a YAML file, preserving comments.
+pub fn update_yaml(
+    content: &str,
+    path: &str,
+    value: &TargetValue,
+    update: &ResolvedUpdate,
+) -> Result<String, String> {
+    let segments = yaml::parse_path(path)?;
+    let new_value = value.render(update);
+
+    let mut found = false;
+    let updated = yaml::rewrite_values(content, |entry| {
+        if entry.path != segments.as_slice() {
+            return None;
+        }
+        found = true;
+        debug!(
+            "Setting {} in document {} to {}",
+            path, entry.doc, new_value
+        );
+        Some(new_value.clone())
+    });
+
+    if !found {
+        return Err(format!("Key '{}' not found", path));
+    }
+    Ok(updated)
+}
+
+/// Sets the value at `path` in a JSON document, leaving the rest of the text as is.
+fn update_json(
+    content: &str,
+    path: &str,
+    value: &TargetValue,
+    update: &ResolvedUpdate,
+) -> Result<String, String> {
+    let segments = yaml::parse_path(path)?;
+    let mut scanner = JsonScanner {
+        bytes: content.as_bytes(),
+        pos: 0,
+    };
+    let (start, end) = scanner
+        .find(&segments)?
+        .ok_or_else(|| format!("Key '{}' not found", path))?;
+
+    let new_value = serde_json::to_string(&value.render(update)).map_err(|e| e.to_string())?;
+    debug!("Setting {} to {}", path, new_value);
+    Ok(format!(
+        "{}{}{}",
+        &content[..start],
+        new_value,
+        &content[end..]
+    ))
+}
+
+/// Locates the byte range of a value inside a JSON document without parsing it
+/// into a tree, so the surrounding formatting can be kept.
+struct JsonScanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonScanner<'_> {
+    /// Returns the span of the value at `path`, starting at the current position.
+    fn find(&mut self, path: &[Segment]) -> Result<Option<(usize, usize)>, String> {
+        self.skip_whitespace();
+        let Some((segment, rest)) = path.split_first() else {
+            let start = self.pos;
+            self.skip_value()?;
+            return Ok(Some((start, self.pos)));
+        };
+
+        match (self.peek(), segment) {
+            (Some(b'{'), Segment::Key(wanted)) => {
+                self.pos += 1;
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() == Some(b'}') {
+                        return Ok(None);
+                    }
+                    let key = self.read_string()?;
+                    self.skip_whitespace();
+                    self.expect(b':')?;
+                    if key == *wanted {
+                        return self.find(rest);
+                    }
+                    self.skip_value()?;
+                    if !self.next_element(b'}')? {
+                        return Ok(None);
+                    }
+                }
+            }
+            (Some(b'['), Segment::Index(wanted)) => {
+                self.pos += 1;
+                let mut index = 0;
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() == Some(b']') {
+                        return Ok(None);
+                    }
+                    if index == *wanted {
+                        return self.find(rest);
+                    }
+                    self.skip_value()?;
+                    if !self.next_element(b']')? {
+                        return Ok(None);
+                    }
+                    index += 1;
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Consumes a `,` and returns true, or consumes `close` and returns false.
+    fn next_element(&mut self, close: u8) -> Result<bool, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b',') => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(c) if c == close => {
+                self.pos += 1;
+                Ok(false)
+            }
+            _ => Err(self.error("expected ',' or closing bracket")),
+        }
+    }
+
+    fn skip_value(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'"') => self.read_string().map(|_| ()),
+            Some(open @ (b'{' | b'[')) => {
+                let close = if open == b'{' { b'}' } else { b']' };
+                self.pos += 1;
+                self.skip_whitespace();
+                if self.peek() == Some(close) {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                loop {
+                    if open == b'{' {
+                        self.read_string()?;
+                        self.skip_whitespace();
+                        self.expect(b':')?;
+                    }
+                    self.skip_value()?;
+                    if !self.next_element(close)? {
+                        return Ok(());
+                    }
+                    self.skip_whitespace();
+                }
+            }
+            Some(_) => {
+                while self
+                    .peek()
+                    .is_some_and(|c| !matches!(c, b',' | b'}' | b']') && !c.is_ascii_whitespace())
+                {
+                    self.pos += 1;
+                }
+                Ok(())
+            }
+            None => Err(self.error("unexpected end of document")),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        self.expect(b'"')?;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                b'\\' => self.pos += 1,
+                b'"' => {
+                    let raw = std::str::from_utf8(&self.bytes[start..self.pos])
+                        .map_err(|e| e.to_string())?;
+                    return serde_json::from_str(raw).map_err(|e| e.to_string());
+                }
+                _ => {}
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{}'", c as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("Invalid JSON at byte {}: {}", self.pos, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: ResolvedUpdate = ResolvedUpdate {
+        service: "web",
+        image: "nginx",
+        tag: "1.27.0-alpine",
+        version: "1.27.0",
+    };
+
+    #[test]
+    fn nested_yaml_keys_are_set_in_every_document() {
+        let content = "\
+image:
+  repository: nginx # upstream
+  tag: \"1.25\" # pinned
+sidecar:
+  tag: 1.25
+---
+image:
+  tag: '1.25'
+";
+        assert_eq!(
+            update_yaml(content, "image.tag", &TargetValue::Tag, &UPDATE),
+            Ok("\
+image:
+  repository: nginx # upstream
+  tag: \"1.27.0-alpine\" # pinned
+sidecar:
+  tag: 1.25
+---
+image:
+  tag: '1.27.0-alpine'
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn yaml_sequences_are_addressed_by_index() {
+        let content = "spec:\n  values:\n    images:\n      - nginx:1.25\n      - redis:7\n";
+        assert_eq!(
+            update_yaml(
+                content,
+                ".spec.values.images[0]",
+                &TargetValue::Reference,
+                &UPDATE
+            ),
+            Ok(
+                "spec:\n  values:\n    images:\n      - nginx:1.27.0-alpine\n      - redis:7\n"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn yaml_flow_collections_are_not_entered() {
+        let content = "image: {repository: nginx, tag: \"1.25\"}\n";
+        assert_eq!(
+            update_yaml(content, "image.tag", &TargetValue::Tag, &UPDATE),
+            Err("Key 'image.tag' not found".to_string())
+        );
+    }
+
+    #[test]
+    fn up_to_date_yaml_is_unchanged() {
+        let content = "image:\r\n  tag: \"1.27.0-alpine\" # pinned\r\n";
+        assert_eq!(
+            update_yaml(content, "image.tag", &TargetValue::Tag, &UPDATE),
+            Ok(content.to_string())
+        );
+    }
+
+    #[test]
+    fn json_values_keep_the_formatting() {
+        let content = "{\n  \"name\": \"web\",\n  \"containers\": [\n    {\"image\": \"nginx:1.25\", \"ports\": [80, 443]},\n    {\"image\": \"redis:7\"}\n  ]\n}\n";
+        assert_eq!(
+            update(
+                Path::new("app.json"),
+                content,
+                ".containers[1].image",
+                &TargetValue::Reference,
+                &UPDATE
+            ),
+            Ok("{\n  \"name\": \"web\",\n  \"containers\": [\n    {\"image\": \"nginx:1.25\", \"ports\": [80, 443]},\n    {\"image\": \"nginx:1.27.0-alpine\"}\n  ]\n}\n".to_string())
+        );
+    }
+
+    #[test]
+    fn json_keys_with_escapes_are_matched() {
+        let content = r#"{"a\"b": {"tag": null}, "tag": "1.25"}"#;
+        assert_eq!(
+            update(
+                Path::new("values"),
+                content,
+                "tag",
+                &TargetValue::Tag,
+                &UPDATE
+            ),
+            Ok(r#"{"a\"b": {"tag": null}, "tag": "1.27.0-alpine"}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn missing_json_keys_and_invalid_json_are_errors() {
+        let content = r#"{"image": {"tag": "1.25"}, "list": []}"#;
+        assert_eq!(
+            update_json(content, "image.name", &TargetValue::Tag, &UPDATE),
+            Err("Key 'image.name' not found".to_string())
+        );
+        assert_eq!(
+            update_json(content, "list[0]", &TargetValue::Tag, &UPDATE),
+            Err("Key 'list[0]' not found".to_string())
+        );
+        assert!(
+            update_json(r#"{"a": 1 "tag": 2}"#, "tag", &TargetValue::Tag, &UPDATE)
+                .is_err_and(|e| e.starts_with("Invalid JSON at byte 8"))
+        );
+    }
+}
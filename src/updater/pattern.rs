@@ -0,0 +1,179 @@
+use super::{map_lines, ResolvedUpdate, TargetValue};
+use regex::{Captures, Regex};
+
+/// Matches version-like tokens such as `1.27`, `v2.0.1` or `1.2.3-alpine`.
+const DEFAULT_VERSION_PATTERN: &str = r"v?\d+(?:\.\d+)+(?:[-+][0-9A-Za-z.-]+)?";
+
+/// Replaces every match of `pattern` with the new value. If the pattern has a
+/// capture group only the first group is replaced, so the surrounding text can
+/// be used as an anchor (e.g. `VERSION := (\S+)`).
+pub fn update_regex(
+    content: &str,
+    pattern: &str,
+    value: &TargetValue,
+    update: &ResolvedUpdate,
+) -> Result<String, String> {
+    let re = Regex::new(&format!("(?m){}", pattern)).map_err(|e| e.to_string())?;
+    if !re.is_match(content) {
+        return Err(format!("Pattern '{}' did not match", pattern));
+    }
+
+    let new_value = value.render(update);
+    Ok(re
+        .replace_all(content, |captures: &Captures| {
+            replace_group(captures, &new_value)
+        })
+        .into_owned())
+}
+
+/// Rewrites lines marked with a `version-updater:<service>` comment. The value on
+/// the marked line (or on the following line, if the marker stands on its own
+/// line) matching `pattern` is replaced; by default the first version-like token.
+pub fn update_marker(
+    content: &str,
+    pattern: Option<&str>,
+    value: &TargetValue,
+    update: &ResolvedUpdate,
+) -> Result<String, String> {
+    let marker = format!("version-updater:{}", update.service);
+    let re = Regex::new(pattern.unwrap_or(DEFAULT_VERSION_PATTERN)).map_err(|e| e.to_string())?;
+    let new_value = value.render(update);
+
+    let mut found = false;
+    let mut next_line_marked = false;
+    let updated = map_lines(content, |line| {
+        let marked = std::mem::take(&mut next_line_marked);
+        let (code, has_marker) = match line.find(&marker) {
+            Some(pos) if is_marker_end(&line[pos + marker.len()..]) => (&line[..pos], true),
+            _ => (line, false),
+        };
+
+        if has_marker && !re.is_match(code) {
+            // The marker stands on its own line and applies to the next one
+            next_line_marked = true;
+            return None;
+        }
+        if !has_marker && !marked {
+            return None;
+        }
+
+        let captures = re.captures(code)?;
+        found = true;
+        let matched = captures.get(0)?;
+        Some(format!(
+            "{}{}{}",
+            &line[..matched.start()],
+            replace_group(&captures, &new_value),
+            &line[matched.end()..]
+        ))
+    });
+
+    if !found {
+        return Err(format!("No line marked with '{}'", marker));
+    }
+    Ok(updated)
+}
+
+fn is_marker_end(rest: &str) -> bool {
+    !rest.starts_with(|c: char| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+fn replace_group(captures: &Captures, new_value: &str) -> String {
+    let whole = captures.get(0).unwrap();
+    match captures.get(1) {
+        Some(group) => format!(
+            "{}{}{}",
+            &whole.as_str()[..group.start() - whole.start()],
+            new_value,
+            &whole.as_str()[group.end() - whole.start()..]
+        ),
+        None => new_value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: ResolvedUpdate = ResolvedUpdate {
+        service: "web",
+        image: "nginx",
+        tag: "1.27.0-alpine",
+        version: "1.27.0",
+    };
+
+    #[test]
+    fn regex_replaces_the_first_group_of_every_match() {
+        let content = "VERSION := 1.25\nOTHER := 1.25\n  VERSION := 1.24\n";
+        assert_eq!(
+            update_regex(
+                content,
+                r"^\s*VERSION := (\S+)",
+                &TargetValue::Version,
+                &UPDATE
+            ),
+            Ok("VERSION := 1.27.0\nOTHER := 1.25\n  VERSION := 1.27.0\n".to_string())
+        );
+        assert_eq!(
+            update_regex(
+                "image = \"nginx:1.25\"\n",
+                r"nginx:[\w.-]+",
+                &TargetValue::Reference,
+                &UPDATE
+            ),
+            Ok("image = \"nginx:1.27.0-alpine\"\n".to_string())
+        );
+    }
+
+    #[test]
+    fn regex_without_match_is_an_error() {
+        assert_eq!(
+            update_regex("VERSION=1.25\n", "^TAG=(.*)$", &TargetValue::Tag, &UPDATE),
+            Err("Pattern '^TAG=(.*)$' did not match".to_string())
+        );
+    }
+
+    #[test]
+    fn marked_lines_are_updated() {
+        let content = "\
+url: https://example.org/v1.25.0/app.tar.gz # version-updater:web
+# version-updater:web
+tag: \"1.25\"
+other: 1.25
+api: 2.0 # version-updater:web-api
+";
+        assert_eq!(
+            update_marker(content, None, &TargetValue::Tag, &UPDATE),
+            Ok("\
+url: https://example.org/1.27.0-alpine/app.tar.gz # version-updater:web
+# version-updater:web
+tag: \"1.27.0-alpine\"
+other: 1.25
+api: 2.0 # version-updater:web-api
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn marker_patterns_select_the_value() {
+        let content =
+            "RUN curl -o app-1.25.tgz https://example.org/1.25/app # version-updater:web\n";
+        assert_eq!(
+            update_marker(content, Some(r"/([\d.]+)/"), &TargetValue::Version, &UPDATE),
+            Ok(
+                "RUN curl -o app-1.25.tgz https://example.org/1.27.0/app # version-updater:web\n"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn missing_markers_are_errors() {
+        let content = "tag: 1.25 # version-updater:web-api\n# version-updater:web\n";
+        assert_eq!(
+            update_marker(content, None, &TargetValue::Tag, &UPDATE),
+            Err("No line marked with 'version-updater:web'".to_string())
+        );
+    }
+}
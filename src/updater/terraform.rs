@@ -0,0 +1,73 @@
+use super::{map_lines, ResolvedUpdate, TargetValue};
+use regex::Regex;
+
+/// Sets `variable = "..."` assignments in `.tfvars` files or `locals` blocks.
+/// Every assignment of the variable is rewritten, wherever it is nested.
+pub fn update(
+    content: &str,
+    variable: &str,
+    value: &TargetValue,
+    update: &ResolvedUpdate,
+) -> Result<String, String> {
+    let re = Regex::new(&format!(
+        r#"^(\s*"?{}"?\s*=\s*)"([^"]*)"(.*)$"#,
+        regex::escape(variable)
+    ))
+    .map_err(|e| e.to_string())?;
+    let new_value = value.render(update);
+
+    let mut found = false;
+    let updated = map_lines(content, |line| {
+        let captures = re.captures(line)?;
+        found = true;
+        Some(format!("{}\"{}\"{}", &captures[1], new_value, &captures[3]))
+    });
+
+    if !found {
+        return Err(format!("Variable '{}' not found", variable));
+    }
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: ResolvedUpdate = ResolvedUpdate {
+        service: "web",
+        image: "nginx",
+        tag: "1.27.0-alpine",
+        version: "1.27.0",
+    };
+
+    #[test]
+    fn every_assignment_is_updated() {
+        let content = "\
+image_tag = \"1.25\" # pinned
+image_tag_old = \"1.24\"
+locals {
+  \"image_tag\"   =   \"1.25\"
+}
+";
+        assert_eq!(
+            update(content, "image_tag", &TargetValue::Tag, &UPDATE),
+            Ok("\
+image_tag = \"1.27.0-alpine\" # pinned
+image_tag_old = \"1.24\"
+locals {
+  \"image_tag\"   =   \"1.27.0-alpine\"
+}
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn missing_variables_are_errors() {
+        let content = "image_tags = [\"1.25\"]\nimage_tag = var.tag\n";
+        assert_eq!(
+            update(content, "image_tag", &TargetValue::Tag, &UPDATE),
+            Err("Variable 'image_tag' not found".to_string())
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! A minimal line-based walker over block-style YAML.
+//!
+//! Rewriting documents through serde would drop comments and reformat the file, so
+//! instead every `key: value` line is visited together with its key path and only
+//! the scalar value is replaced. Flow collections (`{...}`, `[...]`) are treated as
+//! opaque scalars.
+
+use super::map_lines;
+use regex::Regex;
+use std::fmt;
+use std::sync::LazyLock;
+
+static KEY_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^("[^"]*"|'[^']*'|[^\s#'"\-][^:#]*?|-[^\s:#][^:#]*?)[ \t]*:(?:[ \t]+|$)(.*)$"#)
+        .unwrap()
+});
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Segment::Key(key) => write!(f, ".{}", key),
+            Segment::Index(index) => write!(f, "[{}]", index),
+        }
+    }
+}
+
+/// A scalar `key: value` line of a YAML document.
+pub struct Entry<'a> {
+    /// Zero-based index of the document within a multi-document file
+    pub doc: usize,
+    /// Full path of the key, including sequence indices
+    pub path: &'a [Segment],
+    /// The value with surrounding quotes removed
+    pub value: &'a str,
+    /// One-based line and column of the key (or of the sequence item)
+    pub position: (usize, usize),
+}
+
+enum Frame {
+    Key { indent: usize, name: String },
+    Item { indent: usize, index: usize },
+}
+
+impl Frame {
+    fn indent(&self) -> usize {
+        match self {
+            Frame::Key { indent, .. } | Frame::Item { indent, .. } => *indent,
+        }
+    }
+
+    fn segment(&self) -> Segment {
+        match self {
+            Frame::Key { name, .. } => Segment::Key(name.clone()),
+            Frame::Item { index, .. } => Segment::Index(*index),
+        }
+    }
+}
+
+/// Calls `visit` for every scalar entry (including scalar sequence items) and replaces its value with the returned
+/// string, if any. Indentation, quoting and trailing comments are preserved.
+pub fn rewrite_values(content: &str, visit: impl FnMut(&Entry) -> Option<String>) -> String {
+    walk(content, false, visit)
+}
+
+/// Returns the one-based line and column of the key at `path`, if the document
+/// contains it.
+pub fn key_position(content: &str, path: &[Segment]) -> Option<(usize, usize)> {
+    let mut position = None;
+    walk(content, true, |entry| {
+        if position.is_none() && entry.path == path {
+            position = Some(entry.position);
+        }
+        None
+    });
+    position
+}
+
+/// Walks the document like `rewrite_values`, additionally visiting keys with
+/// nested values (with an empty value) if `keys` is set.
+fn walk(content: &str, keys: bool, mut visit: impl FnMut(&Entry) -> Option<String>) -> String {
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut doc = 0;
+    let mut block_scalar_indent: Option<usize> = None;
+    let mut line_number = 0;
+
+    map_lines(content, |line| {
+        line_number += 1;
+        let trimmed = line.trim_start();
+        let mut indent = line.len() - trimmed.len();
+
+        if let Some(scalar_indent) = block_scalar_indent {
+            if trimmed.is_empty() || indent > scalar_indent {
+                return None;
+            }
+            block_scalar_indent = None;
+        }
+
+        if line.starts_with("---") {
+            stack.clear();
+            doc += 1;
+            return None;
+        }
+        if trimmed.is_empty() || trimmed.starts_with('#') || line.starts_with("...") {
+            return None;
+        }
+
+        // Sequence items: `- value` or `- key: value`
+        let mut rest = trimmed;
+        let mut is_item = false;
+        while rest == "-" || rest.starts_with("- ") {
+            is_item = true;
+            while stack.last().is_some_and(|frame| match frame {
+                Frame::Key { indent: i, .. } => *i > indent,
+                Frame::Item { indent: i, .. } => *i > indent,
+            }) {
+                stack.pop();
+            }
+            match stack.last_mut() {
+                Some(Frame::Item { indent: i, index }) if *i == indent => *index += 1,
+                _ => stack.push(Frame::Item { indent, index: 0 }),
+            }
+
+            let after_dash = &rest[1..];
+            let item = after_dash.trim_start();
+            indent += 1 + after_dash.len() - item.len();
+            rest = item;
+        }
+
+        let Some(captures) = KEY_LINE.captures(rest) else {
+            // A scalar sequence item such as `- nginx:1.25`, addressed by its index
+            let rest = skip_anchor(rest);
+            let (value, comment) = split_comment(rest);
+            if !is_item || value.is_empty() || value.starts_with(['{', '[', '|', '>', '&', '*']) {
+                return None;
+            }
+            let path: Vec<Segment> = stack.iter().map(Frame::segment).collect();
+            let position = (line_number, indent + 1);
+            return replace_value(
+                line,
+                rest,
+                value,
+                comment,
+                (doc, position),
+                &path,
+                &mut visit,
+            );
+        };
+        let key = unquote(captures.get(1)?.as_str());
+        let raw_value = skip_anchor(captures.get(2)?.as_str());
+
+        while stack.last().is_some_and(|frame| frame.indent() >= indent) {
+            stack.pop();
+        }
+
+        let (value, comment) = split_comment(raw_value);
+        let mut path: Vec<Segment> = stack.iter().map(Frame::segment).collect();
+        path.push(Segment::Key(key.to_string()));
+        let position = (line_number, indent + 1);
+        if value.is_empty() || value.starts_with('|') || value.starts_with('>') {
+            if keys {
+                visit(&Entry {
+                    doc,
+                    path: &path,
+                    value: "",
+                    position,
+                });
+            }
+            if value.is_empty() {
+                stack.push(Frame::Key {
+                    indent,
+                    name: key.to_string(),
+                });
+            } else {
+                block_scalar_indent = Some(indent);
+            }
+            return None;
+        }
+
+        replace_value(
+            line,
+            raw_value,
+            value,
+            comment,
+            (doc, position),
+            &path,
+            &mut visit,
+        )
+    })
+}
+
+/// Visits a scalar and rebuilds its line if the visitor returns a new value.
+/// `raw_value` is the tail of `line` holding the value and its comment.
+fn replace_value(
+    line: &str,
+    raw_value: &str,
+    value: &str,
+    comment: &str,
+    (doc, position): (usize, (usize, usize)),
+    path: &[Segment],
+    visit: &mut impl FnMut(&Entry) -> Option<String>,
+) -> Option<String> {
+    let entry = Entry {
+        doc,
+        path,
+        value: unquote(value),
+        position,
+    };
+    let new_value = visit(&entry)?;
+    if new_value == entry.value {
+        return None;
+    }
+
+    let prefix = &line[..line.len() - raw_value.len()];
+    Some(format!(
+        "{}{}{}",
+        prefix,
+        quote_like(value, &new_value),
+        comment
+    ))
+}
+
+/// Parses a path expression like `image.tag`, `.spec.values.image` or
+/// `containers[0].image` into its segments.
+pub fn parse_path(expression: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    for part in expression.trim_start_matches('.').split('.') {
+        let (key, mut indices) = match part.find('[') {
+            Some(pos) => (&part[..pos], &part[pos..]),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            segments.push(Segment::Key(key.to_string()));
+        } else if indices.is_empty() {
+            return Err(format!("Empty key in path '{}'", expression));
+        }
+        while let Some(rest) = indices.strip_prefix('[') {
+            let (index, remaining) = rest
+                .split_once(']')
+                .ok_or_else(|| format!("Unclosed index in path '{}'", expression))?;
+            let index = index
+                .parse()
+                .map_err(|_| format!("Invalid index '{}' in path '{}'", index, expression))?;
+            segments.push(Segment::Index(index));
+            indices = remaining;
+        }
+        if !indices.is_empty() {
+            return Err(format!("Invalid path '{}'", expression));
+        }
+    }
+    Ok(segments)
+}
+
+/// Formats a path the way it is written in target definitions, e.g. `.spec.containers[0].image`.
+pub fn display_path(path: &[Segment]) -> String {
+    path.iter().map(ToString::to_string).collect()
+}
+
+/// Splits a raw value into the value itself and a trailing comment (including
+/// the whitespace before it), ignoring `#` inside quotes.
+fn split_comment(raw: &str) -> (&str, &str) {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in raw.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') if i == 0 => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') if previous.is_whitespace() => {
+                let value = raw[..i].trim_end();
+                return (value, &raw[value.len()..]);
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+    let value = raw.trim_end();
+    (value, &raw[value.len()..])
+}
+
+/// Skips an anchor like `&tag` in front of a value, so it is kept when the value
+/// is replaced and an anchored mapping still opens a nested key.
+fn skip_anchor(raw: &str) -> &str {
+    match raw.strip_prefix('&') {
+        Some(anchor) => anchor
+            .find(char::is_whitespace)
+            .map_or("", |end| anchor[end..].trim_start()),
+        None => raw,
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+/// Quotes `new` the same way `old` was quoted, adding double quotes when an
+/// unquoted value would not survive as a plain scalar.
+fn quote_like(old: &str, new: &str) -> String {
+    if old.starts_with('"') {
+        format!("\"{}\"", new.replace('\\', "\\\\").replace('"', "\\\""))
+    } else if old.starts_with('\'') {
+        format!("'{}'", new.replace('\'', "''"))
+    } else if new.is_empty()
+        || new.contains(": ")
+        || new.contains(" #")
+        || new.starts_with(|c: char| "!&*{}[]|>'\"%@`#,?-:".contains(c))
+    {
+        format!("\"{}\"", new.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        new.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> Segment {
+        Segment::Key(name.to_string())
+    }
+
+    /// Collects the paths and values of all scalar entries.
+    fn entries(content: &str) -> Vec<(usize, String, String)> {
+        let mut entries = Vec::new();
+        rewrite_values(content, |entry| {
+            entries.push((entry.doc, display_path(entry.path), entry.value.to_string()));
+            None
+        });
+        entries
+    }
+
+    #[test]
+    fn nested_keys_and_sequences_are_walked() {
+        let content = "\
+spec:
+  template:
+    containers:
+      - name: web
+        image: \"nginx:1.25\" # pinned
+      - image: 'redis:7'
+    args: [--port, \"80\"]
+  hosts:
+    - example.org
+notes: |
+  key: not a key
+---
+kind: Service
+";
+        assert_eq!(
+            entries(content),
+            [
+                (0, ".spec.template.containers[0].name", "web"),
+                (0, ".spec.template.containers[0].image", "nginx:1.25"),
+                (0, ".spec.template.containers[1].image", "redis:7"),
+                (0, ".spec.template.args", "[--port, \"80\"]"),
+                (0, ".spec.hosts[0]", "example.org"),
+                (1, ".kind", "Service"),
+            ]
+            .map(|(doc, path, value)| (doc, path.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn anchors_are_kept() {
+        let content = "image: &image\n  tag: &tag 1.25 # pinned\nsidecar:\n  tag: *tag\n";
+        let updated = rewrite_values(content, |entry| {
+            (entry.path == [key("image"), key("tag")]).then(|| "1.27".to_string())
+        });
+        assert_eq!(
+            updated,
+            "image: &image\n  tag: &tag 1.27 # pinned\nsidecar:\n  tag: *tag\n"
+        );
+    }
+
+    #[test]
+    fn values_keep_their_quotes_and_comments() {
+        let content = "a: \"1.25\" # \"quoted\"\nb: '1.25'\nc: 1.25 #comment\nd: \"x # y\"\r\n";
+        let updated = rewrite_values(content, |entry| Some(format!("{}-1", entry.value)));
+        assert_eq!(
+            updated,
+            "a: \"1.25-1\" # \"quoted\"\nb: '1.25-1'\nc: 1.25-1 #comment\nd: \"x # y-1\"\r\n"
+        );
+    }
+
+    #[test]
+    fn unchanged_values_leave_the_document_identical() {
+        let content = "# header\nimage:\n  tag:   '1.25'   # spaced\nlist: [a, b]\n\n...\n";
+        assert_eq!(
+            rewrite_values(content, |entry| Some(entry.value.to_string())),
+            content
+        );
+        assert_eq!(rewrite_values(content, |_| None), content);
+    }
+
+    #[test]
+    fn key_positions_include_nested_keys() {
+        let content = "spec:\n  containers:\n    - name: web\n      image: nginx\n";
+        assert_eq!(key_position(content, &[key("spec")]), Some((1, 1)));
+        assert_eq!(
+            key_position(
+                content,
+                &[
+                    key("spec"),
+                    key("containers"),
+                    Segment::Index(0),
+                    key("image")
+                ]
+            ),
+            Some((4, 7))
+        );
+        assert_eq!(key_position(content, &[key("image")]), None);
+    }
+
+    #[test]
+    fn paths_are_parsed() {
+        assert_eq!(
+            parse_path(".spec.containers[0].image"),
+            Ok(vec![
+                key("spec"),
+                key("containers"),
+                Segment::Index(0),
+                key("image")
+            ])
+        );
+        assert_eq!(parse_path("image.tag"), Ok(vec![key("image"), key("tag")]));
+        assert_eq!(
+            parse_path("[1][2]"),
+            Ok(vec![Segment::Index(1), Segment::Index(2)])
+        );
+        assert!(parse_path("image..tag").is_err());
+        assert!(parse_path("items[0").is_err());
+        assert!(parse_path("items[x]").is_err());
+        assert!(parse_path("items[0]x").is_err());
+    }
+
+    #[test]
+    fn new_values_are_quoted_like_the_old_ones() {
+        assert_eq!(quote_like("\"1.25\"", "a\"b"), "\"a\\\"b\"");
+        assert_eq!(quote_like("'1.25'", "it's"), "'it''s'");
+        assert_eq!(quote_like("1.25", "1.27"), "1.27");
+        assert_eq!(quote_like("1.25", "*latest"), "\"*latest\"");
+        assert_eq!(quote_like("1.25", "a: b"), "\"a: b\"");
+        assert_eq!(quote_like("1.25", ""), "\"\"");
+    }
+}
@@ -0,0 +1,83 @@
+use super::error::AppError;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{self, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tracing::{debug, info};
+
+/// Watches files for changes. The parent directories are watched instead of the
+/// files themselves so that editors replacing a file on save are noticed as well.
+/// For directories, changes to the files directly inside them are reported.
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+    changes: UnboundedReceiver<()>,
+    /// A change was received but not reported yet
+    pending: bool,
+}
+
+impl FileWatcher {
+    pub fn new(files: &[PathBuf]) -> Result<Self, AppError> {
+        let files = files
+            .iter()
+            .map(path::absolute)
+            .collect::<Result<Vec<_>, _>>()?;
+        let (sender, changes) = mpsc::unbounded_channel();
+
+        let (watched_dirs, watched): (Vec<_>, Vec<_>) =
+            files.iter().cloned().partition(|file| file.is_dir());
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+            let is_watched = |path: &PathBuf| {
+                watched.contains(path)
+                    || path
+                        .parent()
+                        .is_some_and(|dir| watched_dirs.iter().any(|watched| watched == dir))
+            };
+            if event.paths.iter().any(is_watched) {
+                debug!("File change: {:?}", event);
+                let _ = sender.send(());
+            }
+        })
+        .map_err(watch_error)?;
+
+        for file in &files {
+            let dir = match file.is_dir() {
+                true => file,
+                false => file.parent().unwrap_or(file),
+            };
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(watch_error)?;
+            info!("Watching {} for changes", file.display());
+        }
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+            pending: false,
+        })
+    }
+
+    /// Waits for the next change. Bursts of events, as written by most editors,
+    /// are reported as a single change. Cancelling the wait does not lose a
+    /// change that was already received.
+    pub async fn changed(&mut self) {
+        if !self.pending {
+            if self.changes.recv().await.is_none() {
+                return std::future::pending().await;
+            }
+            self.pending = true;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        while self.changes.try_recv().is_ok() {}
+        self.pending = false;
+    }
+}
+
+fn watch_error(e: notify::Error) -> AppError {
+    AppError::WatchError(e.to_string())
+}
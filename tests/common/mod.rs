@@ -0,0 +1,134 @@
+//! A mock server standing in for the git providers and registries, so flows
+//! can be tested offline. Every test starts its own server.
+
+// Each test crate uses only some of the helpers
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use version_updater::http::HttpConfig;
+use version_updater::registry::RegistryCredentials;
+use version_updater::{VersionUpdater, VersionUpdaterBuilder};
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Hosts whose requests are sent to the mock server.
+const HOSTS: &[&str] = &[
+    "api.github.com",
+    "gitlab.com",
+    "codeberg.org",
+    "auth.docker.io",
+    "registry.hub.docker.com",
+    "ghcr.io",
+];
+
+/// Token the mocked Docker Hub auth endpoint hands out.
+pub const REGISTRY_TOKEN: &str = "registry-token";
+
+pub struct MockApis {
+    pub server: MockServer,
+}
+
+impl MockApis {
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    /// An updater builder sending all requests to the mock server, without
+    /// retries and with Docker Hub credentials, so nothing is read from the
+    /// environment.
+    pub fn builder(&self) -> VersionUpdaterBuilder {
+        VersionUpdater::builder()
+            .http(self.http_config())
+            .registry("docker.io", credentials())
+    }
+
+    /// HTTP settings sending all requests to the mock server, without retries.
+    pub fn http_config(&self) -> HttpConfig {
+        let mut http = HttpConfig::default();
+        http.retry.max_attempts = 1;
+        for host in HOSTS {
+            http.base_urls.insert(host.to_string(), self.server.uri());
+        }
+        http
+    }
+
+    /// Answers the latest release request of `api_path` with `tag`.
+    pub async fn release(&self, api_path: &str, tag: &str) {
+        Mock::given(method("GET"))
+            .and(path(api_path))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "tag_name": tag,
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Hands out `REGISTRY_TOKEN` for pulling `image_path` from Docker Hub.
+    pub async fn docker_hub_token(&self, image_path: &str) {
+        Mock::given(method("GET"))
+            .and(path("/token"))
+            .and(query_param("service", "registry.docker.io"))
+            .and(query_param(
+                "scope",
+                format!("repository:{}:pull", image_path),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": REGISTRY_TOKEN,
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Serves the manifest of `image_path:tag` with `digest`.
+    pub async fn manifest(&self, image_path: &str, tag: &str, digest: &str) {
+        Mock::given(method("GET"))
+            .and(path(format!("/v2/{}/manifests/{}", image_path, tag)))
+            .and(wiremock::matchers::header(
+                "Authorization",
+                format!("Bearer {}", REGISTRY_TOKEN),
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Docker-Content-Digest", digest)
+                    .set_body_json(serde_json::json!({ "schemaVersion": 2 })),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Lists `tags` for `image_path`.
+    pub async fn tags(&self, image_path: &str, tags: &[&str]) {
+        Mock::given(method("GET"))
+            .and(path(format!("/v2/{}/tags/list", image_path)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": image_path,
+                "tags": tags,
+            })))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// Credentials accepted by the mocked Docker Hub auth endpoint.
+pub fn credentials() -> RegistryCredentials {
+    RegistryCredentials {
+        username: "user".to_string(),
+        password: Some("password".to_string()),
+        password_env: None,
+        password_file: None,
+    }
+}
+
+/// Writes `content` to a file unique to the test and returns its path.
+pub fn temp_file(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "version-updater-test-{}-{}",
+        std::process::id(),
+        name
+    ));
+    fs::write(&path, content).unwrap();
+    path
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use clap::Parser;
+use common::{temp_file, MockApis};
+use version_updater::git::Provider;
+use version_updater::{service, AppConfig, Args, GitConfig, ImageConfig, ServiceConfig};
+
+fn args(config: &str) -> Args {
+    Args::try_parse_from(["version-updater", "-c", config, "-o", "output.json"]).unwrap()
+}
+
+#[tokio::test]
+async fn remote_config_requires_https() {
+    let e = AppConfig::load_config(args("http://127.0.0.1:9/config.yaml")).unwrap_err();
+    assert!(e.to_string().contains("https://"), "{}", e);
+}
+
+#[tokio::test]
+async fn remote_config_loads_within_current_thread_runtime() {
+    // Nothing listens on the discard port, the fetch fails instead of panicking
+    let e = AppConfig::load_config(args("https://127.0.0.1:9/config.yaml")).unwrap_err();
+    assert!(e.to_string().contains("127.0.0.1:9"), "{}", e);
+}
+
+#[tokio::test]
+async fn timings_are_only_written_on_request() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/nginx/nginx/releases/latest", "1.27.0")
+        .await;
+    let yaml = r#"
+global:
+  git: { github: { authenticate: false } }
+  cache: { enabled: false }
+  http: { base_urls: { api.github.com: "{url}" } }
+services:
+  web:
+    git: { type: github, repo: nginx/nginx }
+    image: none
+"#;
+    let config = temp_file("timings.yaml", &yaml.replace("{url}", &apis.server.uri()));
+    for timings in [false, true] {
+        let mut args = args(config.to_str().unwrap());
+        args.timings = timings;
+        let config = AppConfig::load_config(args).unwrap();
+        let run = service::process_services_until(&config, None, false, std::future::pending())
+            .await
+            .unwrap();
+        assert_eq!(run.output["web"].tag(), Some("1.27.0"));
+        assert_eq!(run.output["web"].timing.is_some(), timings);
+        assert!(run.durations.contains_key("web"));
+    }
+}
+
+#[tokio::test]
+async fn invalid_version_filter_is_rejected() {
+    let git = GitConfig::new(Provider::Github, "nginx/nginx").with_filter("(");
+    let service = ServiceConfig::new(git, ImageConfig::none());
+    let e = AppConfig::single(args("missing.yaml"), "nginx", service).unwrap_err();
+    assert!(e.to_string().contains("Invalid version_filter"), "{}", e);
+}
@@ -0,0 +1,253 @@
+mod common;
+
+use common::{temp_file, MockApis};
+use version_updater::git::Provider;
+use version_updater::{
+    GitClient, GitConfig, HttpClient, ImageConfig, ResolutionOutcome, ServiceConfig,
+    ServiceProcessor,
+};
+use wiremock::matchers::{header, method, path, query_param};
+use wiremock::{Mock, ResponseTemplate};
+
+fn resolved(version: &str) -> ResolutionOutcome {
+    ResolutionOutcome::Resolved {
+        version: version.to_string(),
+        tag: version.to_string(),
+        digest: None,
+    }
+}
+
+#[tokio::test]
+async fn github_release_is_filtered() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/traefik/traefik/releases/latest", "v3.1.2")
+        .await;
+    let updater = apis
+        .builder()
+        .service(
+            "traefik",
+            ServiceConfig::new(
+                GitConfig::new(Provider::Github, "traefik/traefik").with_filter("v(.*)"),
+                ImageConfig::none(),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let version = updater.resolve("traefik").await.unwrap();
+    assert_eq!(version.outcome, resolved("3.1.2"));
+}
+
+#[tokio::test]
+async fn gitlab_release_is_resolved_by_project_id() {
+    let apis = MockApis::start().await;
+    apis.release(
+        "/api/v4/projects/278964/releases/permalink/latest",
+        "17.2.0",
+    )
+    .await;
+    let updater = apis
+        .builder()
+        .service(
+            "gitlab",
+            ServiceConfig::new(GitConfig::gitlab(278964), ImageConfig::none()),
+        )
+        .build()
+        .unwrap();
+
+    let version = updater.resolve("gitlab").await.unwrap();
+    assert_eq!(version.outcome, resolved("17.2.0"));
+}
+
+#[tokio::test]
+async fn codeberg_release_is_resolved() {
+    let apis = MockApis::start().await;
+    apis.release("/api/v1/repos/forgejo/forgejo/releases/latest", "v8.0.0")
+        .await;
+    let updater = apis
+        .builder()
+        .service(
+            "forgejo",
+            ServiceConfig::new(
+                GitConfig::new(Provider::Codeberg, "forgejo/forgejo").with_filter("v(.*)"),
+                ImageConfig::none(),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let version = updater.resolve("forgejo").await.unwrap();
+    assert_eq!(version.outcome, resolved("8.0.0"));
+}
+
+#[tokio::test]
+async fn private_repository_sends_token() {
+    let apis = MockApis::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/org/private/releases/latest"))
+        .and(header("Authorization", "Bearer secret-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "tag_name": "1.0.0",
+        })))
+        .mount(&apis.server)
+        .await;
+    let mut git = GitConfig::new(Provider::Github, "org/private").with_private(true);
+    git.token_file = Some(temp_file("github-token", "secret-token\n"));
+    let updater = apis
+        .builder()
+        .service("private", ServiceConfig::new(git, ImageConfig::none()))
+        .build()
+        .unwrap();
+
+    let version = updater.resolve("private").await.unwrap();
+    assert_eq!(version.outcome, resolved("1.0.0"));
+}
+
+#[tokio::test]
+async fn rate_limit_is_reported() {
+    let apis = MockApis::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/org/app/releases/latest"))
+        .respond_with(ResponseTemplate::new(403))
+        .mount(&apis.server)
+        .await;
+    let updater = apis
+        .builder()
+        .service(
+            "app",
+            ServiceConfig::new(
+                GitConfig::new(Provider::Github, "org/app"),
+                ImageConfig::none(),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let version = updater.resolve("app").await.unwrap();
+    assert_eq!(
+        version.outcome,
+        ResolutionOutcome::RateLimited {
+            source: "GitHub(org/app) API".to_string()
+        }
+    );
+}
+
+#[tokio::test]
+async fn unmatched_filter_is_an_error() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/org/app/releases/latest", "nightly")
+        .await;
+    let updater = apis
+        .builder()
+        .service(
+            "app",
+            ServiceConfig::new(
+                GitConfig::new(Provider::Github, "org/app").with_filter(r"v(\d+\.\d+)"),
+                ImageConfig::none(),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let output = updater.run().await;
+    let error = output["app"].error().unwrap();
+    assert!(error.contains("No matching version"), "{}", error);
+}
+
+#[tokio::test]
+async fn rate_limit_budget_is_tracked_per_host() {
+    let apis = MockApis::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/traefik/traefik/releases/latest"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("x-ratelimit-limit", "60")
+                .insert_header("x-ratelimit-remaining", "4")
+                .set_body_json(serde_json::json!({ "tag_name": "v3.1.2" })),
+        )
+        .mount(&apis.server)
+        .await;
+    let http = HttpClient::new(&apis.http_config(), None).unwrap();
+    let service = ServiceConfig::new(
+        GitConfig::new(Provider::Github, "traefik/traefik"),
+        ImageConfig::none(),
+    );
+
+    ServiceProcessor::new(service, http.clone())
+        .process()
+        .await
+        .unwrap();
+    let budgets = http.budgets();
+    let budget = budgets.values().next().unwrap();
+    assert_eq!((budget.remaining, budget.limit), (4, Some(60)));
+    assert!(budget.is_low());
+}
+
+#[tokio::test]
+async fn github_releases_are_listed() {
+    let apis = MockApis::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/traefik/traefik/releases"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "tag_name": "v3.2.0-rc1", "published_at": "2024-09-02T10:00:00Z", "prerelease": true },
+            { "tag_name": "v3.1.2", "published_at": "2024-08-12T10:00:00Z", "prerelease": false },
+        ])))
+        .mount(&apis.server)
+        .await;
+    let http = HttpClient::new(&apis.http_config(), None).unwrap();
+    let git = GitConfig::parse_source("github:traefik/traefik").unwrap();
+
+    let releases = GitClient::releases(&git, &http, 10).await.unwrap();
+    assert_eq!(releases.len(), 2);
+    assert_eq!(releases[1].tag, "v3.1.2");
+    assert!(releases[0].prerelease);
+}
+
+#[tokio::test]
+async fn missing_repository_releases_are_an_error() {
+    let apis = MockApis::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/org/gone/releases"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+            "message": "Not Found",
+        })))
+        .mount(&apis.server)
+        .await;
+    let http = HttpClient::new(&apis.http_config(), None).unwrap();
+    let git = GitConfig::parse_source("github:org/gone").unwrap();
+
+    let error = GitClient::releases(&git, &http, 10).await.unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("Not found: GitHub(org/gone) API"));
+}
+
+#[tokio::test]
+async fn release_limit_is_capped_at_the_page_size() {
+    let apis = MockApis::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/traefik/traefik/releases"))
+        .and(query_param("per_page", "100"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+        .mount(&apis.server)
+        .await;
+    let http = HttpClient::new(&apis.http_config(), None).unwrap();
+    let git = GitConfig::parse_source("github:traefik/traefik").unwrap();
+
+    let releases = GitClient::releases(&git, &http, 500).await.unwrap();
+    assert!(releases.is_empty());
+}
+
+#[tokio::test]
+async fn invalid_filter_fails_the_lookup() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/nginx/nginx/releases/latest", "1.27.0")
+        .await;
+    let http = HttpClient::new(&apis.http_config(), None).unwrap();
+    let git = GitConfig::new(Provider::Github, "nginx/nginx").with_filter("(");
+
+    let error = GitClient::get_version(&git, &http).await.unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("Invalid configuration: Invalid version_filter"));
+}
@@ -0,0 +1,453 @@
+mod common;
+
+use common::{credentials, MockApis, REGISTRY_TOKEN};
+use std::time::Duration;
+use version_updater::cache::{Cache, CacheConfig};
+use version_updater::git::Provider;
+use version_updater::http::SendError;
+use version_updater::registry::RegistryCredentials;
+use version_updater::service::Verification;
+use version_updater::{
+    GitConfig, HttpClient, ImageConfig, ResolutionOutcome, ServiceConfig, ServiceProcessor,
+};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const DIGEST: &str = "sha256:0123456789abcdef";
+
+#[tokio::test]
+async fn docker_hub_tag_is_validated() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/nginx/nginx/releases/latest", "release-1.27.0")
+        .await;
+    apis.docker_hub_token("library/nginx").await;
+    apis.manifest("library/nginx", "1.27.0-alpine", DIGEST)
+        .await;
+    let updater = apis
+        .builder()
+        .service(
+            "nginx",
+            ServiceConfig::new(
+                GitConfig::new(Provider::Github, "nginx/nginx").with_filter("release-(.*)"),
+                ImageConfig::new("nginx", "${RELEASE_VERSION}-alpine"),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let version = updater.resolve("nginx").await.unwrap();
+    assert_eq!(version.image, "nginx");
+    assert_eq!(
+        version.outcome,
+        ResolutionOutcome::Resolved {
+            version: "1.27.0".to_string(),
+            tag: "1.27.0-alpine".to_string(),
+            digest: Some(DIGEST.to_string()),
+        }
+    );
+}
+
+#[tokio::test]
+async fn missing_tag_lists_candidates() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/org/app/releases/latest", "2.0.0")
+        .await;
+    apis.docker_hub_token("org/app").await;
+    Mock::given(method("GET"))
+        .and(path("/v2/org/app/manifests/v2.0.0"))
+        .respond_with(ResponseTemplate::new(404).set_body_string(
+            r#"{"errors":[{"code":"MANIFEST_UNKNOWN","message":"manifest unknown"}]}"#,
+        ))
+        .mount(&apis.server)
+        .await;
+    apis.tags("org/app", &["1.9.0", "2.0.0", "2.0.0-rc1"]).await;
+    let updater = apis
+        .builder()
+        .service(
+            "app",
+            ServiceConfig::new(
+                GitConfig::new(Provider::Github, "org/app"),
+                ImageConfig::new("org/app", "v${RELEASE_VERSION}"),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let version = updater.resolve("app").await.unwrap();
+    assert_eq!(
+        version.outcome,
+        ResolutionOutcome::TagMissing {
+            version: "2.0.0".to_string(),
+            tag: "v2.0.0".to_string(),
+            candidates: vec!["2.0.0".to_string(), "2.0.0-rc1".to_string()],
+        }
+    );
+}
+
+#[tokio::test]
+async fn image_only_service_uses_latest_matching_tag() {
+    let apis = MockApis::start().await;
+    apis.docker_hub_token("library/redis").await;
+    apis.tags(
+        "library/redis",
+        &["7.2.5-alpine", "7.10.0-alpine", "7.10.0", "latest"],
+    )
+    .await;
+    apis.manifest("library/redis", "7.10.0-alpine", DIGEST)
+        .await;
+    let updater = apis
+        .builder()
+        .service(
+            "redis",
+            ServiceConfig::new(
+                GitConfig::none(),
+                ImageConfig::new("redis", "${RELEASE_VERSION}-alpine"),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let version = updater.resolve("redis").await.unwrap();
+    assert_eq!(version.tag(), Some("7.10.0-alpine"));
+    assert_eq!(version.version(), Some("7.10.0"));
+}
+
+#[tokio::test]
+async fn timing_counts_http_requests() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/nginx/nginx/releases/latest", "1.27.0")
+        .await;
+    apis.docker_hub_token("library/nginx").await;
+    apis.manifest("library/nginx", "1.27.0", DIGEST).await;
+    let mut image = ImageConfig::new("nginx", "${RELEASE_VERSION}");
+    image.credentials = Some(credentials());
+    let service = ServiceConfig::new(GitConfig::new(Provider::Github, "nginx/nginx"), image);
+    let http = HttpClient::new(&apis.http_config(), None).unwrap();
+
+    let version = ServiceProcessor::new(service, http)
+        .with_timing(true)
+        .process()
+        .await
+        .unwrap();
+    assert_eq!(version.tag(), Some("1.27.0"));
+    // The release, the registry token and the manifest
+    assert_eq!(version.timing.unwrap().http_requests, 3);
+}
+
+#[tokio::test]
+async fn offline_answers_from_expired_cache() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/nginx/nginx/releases/latest", "1.27.0")
+        .await;
+    apis.docker_hub_token("library/nginx").await;
+    apis.manifest("library/nginx", "1.27.0", DIGEST).await;
+    let mut image = ImageConfig::new("nginx", "${RELEASE_VERSION}");
+    image.credentials = Some(credentials());
+    let service = ServiceConfig::new(GitConfig::new(Provider::Github, "nginx/nginx"), image);
+    let cache_config = CacheConfig {
+        enabled: true,
+        dir: Some(std::env::temp_dir().join(format!(
+            "version-updater-test-{}-offline-cache",
+            std::process::id()
+        ))),
+        ttl: Duration::ZERO,
+    };
+    let http = HttpClient::new(&apis.http_config(), Cache::new(&cache_config)).unwrap();
+    ServiceProcessor::new(service.clone(), http)
+        .process()
+        .await
+        .unwrap();
+
+    apis.server.reset().await;
+    let http = HttpClient::new(&apis.http_config(), Cache::new(&cache_config))
+        .unwrap()
+        .with_offline(true);
+    let version = ServiceProcessor::new(service, http)
+        .process()
+        .await
+        .unwrap();
+    assert_eq!(version.tag(), Some("1.27.0"));
+    assert!(version.stale.is_some());
+    assert!(apis.server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn refresh_bypasses_the_cache() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/nginx/nginx/releases/latest", "1.27.0")
+        .await;
+    let service = ServiceConfig::new(
+        GitConfig::new(Provider::Github, "nginx/nginx"),
+        ImageConfig::none(),
+    );
+    let cache_config = CacheConfig {
+        enabled: true,
+        dir: Some(std::env::temp_dir().join(format!(
+            "version-updater-test-{}-refresh-cache",
+            std::process::id()
+        ))),
+        ttl: Duration::from_secs(300),
+    };
+    let http = HttpClient::new(&apis.http_config(), Cache::new(&cache_config)).unwrap();
+    ServiceProcessor::new(service.clone(), http)
+        .process()
+        .await
+        .unwrap();
+
+    apis.server.reset().await;
+    apis.release("/repos/nginx/nginx/releases/latest", "1.27.1")
+        .await;
+    let cached = HttpClient::new(&apis.http_config(), Cache::new(&cache_config)).unwrap();
+    let version = ServiceProcessor::new(service.clone(), cached.clone())
+        .process()
+        .await
+        .unwrap();
+    assert_eq!(version.tag(), Some("1.27.0"));
+    let version = ServiceProcessor::new(service.clone(), cached.new_run().with_refresh(true))
+        .process()
+        .await
+        .unwrap();
+    assert_eq!(version.tag(), Some("1.27.1"));
+    // The fresh response replaced the cached one
+    let cached = HttpClient::new(&apis.http_config(), Cache::new(&cache_config)).unwrap();
+    let version = ServiceProcessor::new(service, cached)
+        .process()
+        .await
+        .unwrap();
+    assert_eq!(version.tag(), Some("1.27.1"));
+    assert_eq!(apis.server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn offline_client_sends_nothing() {
+    let apis = MockApis::start().await;
+    let http = HttpClient::new(&apis.http_config(), None)
+        .unwrap()
+        .with_offline(true);
+
+    let result = http.send(http.get("https://ghcr.io/v2/")).await;
+    assert!(matches!(result, Err(SendError::Offline(url)) if url.ends_with("/v2/")));
+    assert!(apis.server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn shared_lookups_are_sent_once() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/nginx/nginx/releases/latest", "1.27.0")
+        .await;
+    apis.docker_hub_token("library/nginx").await;
+    apis.manifest("library/nginx", "1.27.0", DIGEST).await;
+    let service = || {
+        ServiceConfig::new(
+            GitConfig::new(Provider::Github, "nginx/nginx"),
+            ImageConfig::new("nginx", "${RELEASE_VERSION}"),
+        )
+    };
+    let updater = apis
+        .builder()
+        .service("web", service())
+        .service("proxy", service())
+        .build()
+        .unwrap();
+
+    let output = updater.run().await;
+    assert_eq!(output["web"].tag(), Some("1.27.0"));
+    assert_eq!(output["proxy"].tag(), Some("1.27.0"));
+    // The release, the registry token and the manifest
+    assert_eq!(apis.server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn deep_verify_fails_on_missing_blobs() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/nginx/nginx/releases/latest", "1.27.0")
+        .await;
+    apis.docker_hub_token("library/nginx").await;
+    apis.manifest("library/nginx", "1.27.0", DIGEST).await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v2/library/nginx/manifests/{}", DIGEST)))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "schemaVersion": 2,
+            "config": { "digest": "sha256:c0" },
+            "layers": [{ "digest": "sha256:l1" }, { "digest": "sha256:l2" }],
+        })))
+        .mount(&apis.server)
+        .await;
+    for blob in ["sha256:c0", "sha256:l1"] {
+        Mock::given(method("HEAD"))
+            .and(path(format!("/v2/library/nginx/blobs/{}", blob)))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&apis.server)
+            .await;
+    }
+    let service = ServiceConfig::new(
+        GitConfig::new(Provider::Github, "nginx/nginx"),
+        ImageConfig::new("nginx", "${RELEASE_VERSION}"),
+    );
+    let shallow = apis
+        .builder()
+        .service("nginx", service.clone())
+        .build()
+        .unwrap();
+    let deep = apis
+        .builder()
+        .service("nginx", service)
+        .deep_verify(true)
+        .build()
+        .unwrap();
+
+    let version = shallow.resolve("nginx").await.unwrap();
+    assert_eq!(version.verified, Some(Verification::Shallow));
+    let version = deep.resolve("nginx").await.unwrap();
+    assert_eq!(version.verified, None);
+    assert!(version
+        .error()
+        .is_some_and(|error| error.contains("missing blobs sha256:l2")));
+}
+
+#[tokio::test]
+async fn lookups_are_shared_per_credentials() {
+    let apis = MockApis::start().await;
+    apis.release("/repos/nginx/nginx/releases/latest", "1.27.0")
+        .await;
+    // Only the robot account may pull the image
+    Mock::given(method("GET"))
+        .and(path("/token"))
+        .and(header("Authorization", "Basic cm9ib3Q6c2VjcmV0"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "token": REGISTRY_TOKEN,
+        })))
+        .mount(&apis.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/token"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&apis.server)
+        .await;
+    apis.manifest("library/nginx", "1.27.0", DIGEST).await;
+    let service = || {
+        ServiceConfig::new(
+            GitConfig::new(Provider::Github, "nginx/nginx"),
+            ImageConfig::new("nginx", "${RELEASE_VERSION}"),
+        )
+    };
+    let mut robot = service();
+    robot.image.credentials = Some(RegistryCredentials {
+        username: "robot".to_string(),
+        password: Some("secret".to_string()),
+        ..credentials()
+    });
+    let updater = apis
+        .builder()
+        .service("robot", robot)
+        .service("user", service())
+        .build()
+        .unwrap();
+
+    let output = updater.run().await;
+    assert_eq!(output["robot"].tag(), Some("1.27.0"));
+    assert_eq!(output["user"].tag(), None);
+}
+
+#[tokio::test]
+async fn tag_lists_are_shared_per_credentials() {
+    let apis = MockApis::start().await;
+    // Only the robot account may list the tags
+    Mock::given(method("GET"))
+        .and(path("/token"))
+        .and(header("Authorization", "Basic cm9ib3Q6c2VjcmV0"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "token": REGISTRY_TOKEN,
+        })))
+        .mount(&apis.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/token"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&apis.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v2/library/redis/tags/list"))
+        .and(header(
+            "Authorization",
+            format!("Bearer {}", REGISTRY_TOKEN),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "name": "library/redis",
+            "tags": ["7.2.5", "7.10.0"],
+        })))
+        .mount(&apis.server)
+        .await;
+    apis.manifest("library/redis", "7.10.0", DIGEST).await;
+    let service = || {
+        ServiceConfig::new(
+            GitConfig::none(),
+            ImageConfig::new("redis", "${RELEASE_VERSION}"),
+        )
+    };
+    let robot = || {
+        let mut service = service();
+        service.image.credentials = Some(RegistryCredentials {
+            username: "robot".to_string(),
+            password: Some("secret".to_string()),
+            ..credentials()
+        });
+        service
+    };
+    let updater = apis
+        .builder()
+        .service("cache", robot())
+        .service("queue", robot())
+        .service("user", service())
+        .build()
+        .unwrap();
+
+    let output = updater.run().await;
+    assert_eq!(output["cache"].tag(), Some("7.10.0"));
+    assert_eq!(output["queue"].tag(), Some("7.10.0"));
+    assert_eq!(output["user"].tag(), None);
+    let listings = apis
+        .server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|request| request.url.path().ends_with("/tags/list"))
+        .count();
+    assert_eq!(listings, 1);
+}
+
+#[tokio::test]
+async fn image_credentials_win_over_github_token() {
+    std::env::set_var("GITHUB_TOKEN", "github-token");
+    let apis = MockApis::start().await;
+    apis.release("/repos/owner/app/releases/latest", "2.1.0")
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/token"))
+        .and(header("Authorization", "Basic cm9ib3Q6c2VjcmV0"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "token": REGISTRY_TOKEN,
+        })))
+        .mount(&apis.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/token"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&apis.server)
+        .await;
+    apis.manifest("owner/app", "2.1.0", DIGEST).await;
+    let mut service = ServiceConfig::new(
+        GitConfig::new(Provider::Github, "owner/app"),
+        ImageConfig::new("ghcr.io/owner/app", "${RELEASE_VERSION}"),
+    );
+    service.image.credentials = Some(RegistryCredentials {
+        username: "robot".to_string(),
+        password: Some("secret".to_string()),
+        ..credentials()
+    });
+    let updater = apis.builder().service("app", service).build().unwrap();
+
+    let output = updater.run().await;
+    assert_eq!(output["app"].tag(), Some("2.1.0"));
+}